@@ -0,0 +1,614 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+/// CIDR 网段，例如 10.0.0.0/8、2001:db8::/32
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Self, String> {
+        let max_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix_len > max_len {
+            return Err(format!("前缀长度 {} 超出范围（最大 {}）", prefix_len, max_len));
+        }
+        Ok(Self {
+            addr: mask_addr(addr, prefix_len),
+            prefix_len,
+        })
+    }
+
+    /// 网段的网络地址
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// 判断 IP 是否属于该网段
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_)) => {
+                mask_addr(*ip, self.prefix_len) == self.addr
+            }
+            _ => false,
+        }
+    }
+
+    /// 判断 other 是否完全属于该网段
+    pub fn covers(&self, other: &Cidr) -> bool {
+        self.prefix_len <= other.prefix_len && self.contains(&other.addr)
+    }
+
+    /// 判断两个网段是否有重叠的地址
+    pub fn overlaps(&self, other: &Cidr) -> bool {
+        self.covers(other) || other.covers(self)
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    /// 解析 CIDR 字符串，不带前缀长度时视为单个主机地址
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr_str, prefix_str) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr_str
+            .trim()
+            .parse()
+            .map_err(|_| format!("无效的 IP 地址: {}", addr_str))?;
+        let prefix_len = match prefix_str {
+            Some(p) => p
+                .trim()
+                .parse::<u8>()
+                .map_err(|_| format!("无效的前缀长度: {}", p))?,
+            None => match addr {
+                IpAddr::V4(_) => 32,
+                IpAddr::V6(_) => 128,
+            },
+        };
+        Self::new(addr, prefix_len)
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// 将 IP 地址按前缀长度截断（主机位清零）
+pub fn mask_addr(addr: IpAddr, prefix_len: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(v4) => {
+            let bits = u32::from(v4);
+            let mask = if prefix_len == 0 { 0 } else { u32::MAX << (32 - prefix_len.min(32)) };
+            IpAddr::V4(Ipv4Addr::from(bits & mask))
+        }
+        IpAddr::V6(v6) => {
+            let bits = u128::from(v6);
+            let mask = if prefix_len == 0 { 0 } else { u128::MAX << (128 - prefix_len.min(128)) };
+            IpAddr::V6(Ipv6Addr::from(bits & mask))
+        }
+    }
+}
+
+/// 地址分类
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressClass {
+    /// 公网地址
+    Public,
+    /// 0.0.0.0/8、::（当前网络 / 未指定地址）
+    Unspecified,
+    /// 10.0.0.0/8、172.16.0.0/12、192.168.0.0/16
+    Private,
+    /// 127.0.0.0/8、::1
+    Loopback,
+    /// 169.254.0.0/16、fe80::/10
+    LinkLocal,
+    /// 224.0.0.0/4、ff00::/8
+    Multicast,
+    /// 240.0.0.0/4
+    Reserved,
+    /// 255.255.255.255
+    Broadcast,
+    /// fc00::/7（IPv6 唯一本地地址）
+    UniqueLocal,
+}
+
+impl AddressClass {
+    const NAMES: &'static str = "public, unspecified, private, loopback, link-local, multicast, reserved, broadcast, ula, lan";
+
+    /// 解析分类名称，lan 是 private、link-local、ula 的组合
+    fn parse_names(name: &str) -> Option<Vec<Self>> {
+        let classes = match name.to_lowercase().as_str() {
+            "public" => vec![Self::Public],
+            "unspecified" => vec![Self::Unspecified],
+            "private" => vec![Self::Private],
+            "loopback" => vec![Self::Loopback],
+            "link-local" | "linklocal" => vec![Self::LinkLocal],
+            "multicast" => vec![Self::Multicast],
+            "reserved" => vec![Self::Reserved],
+            "broadcast" => vec![Self::Broadcast],
+            "ula" | "unique-local" => vec![Self::UniqueLocal],
+            "lan" => vec![Self::Private, Self::LinkLocal, Self::UniqueLocal],
+            _ => return None,
+        };
+        Some(classes)
+    }
+
+    /// 对 IP 地址进行分类
+    pub fn of(ip: &IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(ipv4) => {
+                let octets = ipv4.octets();
+                if octets == [255, 255, 255, 255] {
+                    Self::Broadcast
+                } else if octets[0] == 0 {
+                    Self::Unspecified
+                } else if octets[0] == 127 {
+                    Self::Loopback
+                } else if octets[0] == 10
+                    || (octets[0] == 172 && (16..=31).contains(&octets[1]))
+                    || (octets[0] == 192 && octets[1] == 168)
+                {
+                    Self::Private
+                } else if octets[0] == 169 && octets[1] == 254 {
+                    Self::LinkLocal
+                } else if (224..=239).contains(&octets[0]) {
+                    Self::Multicast
+                } else if octets[0] >= 240 {
+                    Self::Reserved
+                } else {
+                    Self::Public
+                }
+            }
+            IpAddr::V6(ipv6) => {
+                let segments = ipv6.segments();
+                if ipv6.is_unspecified() {
+                    Self::Unspecified
+                } else if ipv6.is_loopback() {
+                    Self::Loopback
+                } else if ipv6.is_multicast() {
+                    Self::Multicast
+                } else if segments[0] & 0xffc0 == 0xfe80 {
+                    Self::LinkLocal
+                } else if segments[0] & 0xfe00 == 0xfc00 {
+                    Self::UniqueLocal
+                } else {
+                    Self::Public
+                }
+            }
+        }
+    }
+}
+
+/// 地址过滤规则：决定哪些远程地址参与统计
+///
+/// 优先级：排除网段 > 包含网段 > 地址分类。默认只统计公网地址。
+#[derive(Debug, Clone)]
+pub struct AddressFilter {
+    allowed_classes: HashSet<AddressClass>,
+    include_cidrs: Vec<Cidr>,
+    exclude_cidrs: Vec<Cidr>,
+}
+
+impl Default for AddressFilter {
+    fn default() -> Self {
+        Self {
+            allowed_classes: HashSet::from([AddressClass::Public]),
+            include_cidrs: Vec::new(),
+            exclude_cidrs: Vec::new(),
+        }
+    }
+}
+
+impl AddressFilter {
+    /// 根据命令行参数构建过滤规则，每一项可以是分类名称或 CIDR
+    pub fn from_rules(include: &[String], exclude: &[String]) -> Result<Self, String> {
+        let mut filter = Self::default();
+
+        for rule in include {
+            match AddressClass::parse_names(rule) {
+                Some(classes) => filter.allowed_classes.extend(classes),
+                None => filter.include_cidrs.push(Self::parse_cidr_rule(rule)?),
+            }
+        }
+
+        for rule in exclude {
+            match AddressClass::parse_names(rule) {
+                Some(classes) => {
+                    for class in classes {
+                        filter.allowed_classes.remove(&class);
+                    }
+                }
+                None => filter.exclude_cidrs.push(Self::parse_cidr_rule(rule)?),
+            }
+        }
+
+        Ok(filter)
+    }
+
+    fn parse_cidr_rule(rule: &str) -> Result<Cidr, String> {
+        rule.parse::<Cidr>().map_err(|e| {
+            format!("无效的地址规则 \"{}\": {}（可用分类: {}）", rule, e, AddressClass::NAMES)
+        })
+    }
+
+    /// 判断地址是否参与统计
+    pub fn is_allowed(&self, ip: &IpAddr) -> bool {
+        if self.exclude_cidrs.iter().any(|c| c.contains(ip)) {
+            return false;
+        }
+        if self.include_cidrs.iter().any(|c| c.contains(ip)) {
+            return true;
+        }
+        self.allowed_classes.contains(&AddressClass::of(ip))
+    }
+
+    /// 判断字符串形式的地址是否参与统计，无法解析的地址一律丢弃
+    pub fn is_allowed_str(&self, ip: &str) -> bool {
+        ip.parse::<IpAddr>().is_ok_and(|addr| self.is_allowed(&addr))
+    }
+}
+
+/// 统计前的网段过滤与聚合规则
+///
+/// 包含列表为空时统计所有地址；排除列表优先。聚合前缀用于把远程地址归并为网段（例如 /24）。
+#[derive(Debug, Clone, Default)]
+pub struct FlowFilter {
+    include: Vec<Cidr>,
+    exclude: Vec<Cidr>,
+    v4_prefix: Option<u8>,
+    v6_prefix: Option<u8>,
+}
+
+impl FlowFilter {
+    pub fn new(
+        include: &[String],
+        exclude: &[String],
+        v4_prefix: Option<u8>,
+        v6_prefix: Option<u8>,
+    ) -> Result<Self, String> {
+        if v4_prefix.is_some_and(|p| p > 32) {
+            return Err("IPv4 聚合前缀长度不能超过 32".to_string());
+        }
+        if v6_prefix.is_some_and(|p| p > 128) {
+            return Err("IPv6 聚合前缀长度不能超过 128".to_string());
+        }
+
+        let parse = |rules: &[String]| -> Result<Vec<Cidr>, String> {
+            rules
+                .iter()
+                .map(|r| r.parse::<Cidr>().map_err(|e| format!("无效的过滤网段 \"{}\": {}", r, e)))
+                .collect()
+        };
+
+        Ok(Self {
+            include: parse(include)?,
+            exclude: parse(exclude)?,
+            v4_prefix,
+            v6_prefix,
+        })
+    }
+
+    /// 计算远程地址在全局统计中的存储键，被过滤掉的地址返回 None
+    pub fn storage_key(&self, ip: &str) -> Option<String> {
+        let addr: IpAddr = match ip.parse() {
+            Ok(addr) => addr,
+            // 无法解析的键（例如已经聚合过的网段）原样保留
+            Err(_) => return Some(ip.to_string()),
+        };
+
+        if self.exclude.iter().any(|c| c.contains(&addr)) {
+            return None;
+        }
+        if !self.include.is_empty() && !self.include.iter().any(|c| c.contains(&addr)) {
+            return None;
+        }
+
+        let prefix = match addr {
+            IpAddr::V4(_) => self.v4_prefix.filter(|&p| p < 32),
+            IpAddr::V6(_) => self.v6_prefix.filter(|&p| p < 128),
+        };
+        match prefix {
+            Some(p) => Some(format!("{}/{}", mask_addr(addr, p), p)),
+            None => Some(ip.to_string()),
+        }
+    }
+}
+
+/// 本地地址判定规则（非本机数据源用来判断流量方向）
+///
+/// 指定了本地网段时按网段判断；未指定时，本机网卡上的地址和私有地址（RFC 1918、ULA、链路本地、环回）视为本地地址，
+/// 与地址过滤规则无关（--include-addr lan 统计的局域网对端仍然是远程地址）。
+#[derive(Debug, Clone)]
+pub struct LocalNetworks {
+    nets: Vec<Cidr>,
+    host_addrs: HashSet<IpAddr>,
+}
+
+impl LocalNetworks {
+    pub fn new(nets: Vec<Cidr>) -> Self {
+        let host_addrs = if nets.is_empty() { host_addresses() } else { HashSet::new() };
+        Self { nets, host_addrs }
+    }
+
+    /// 判断地址是否属于本地
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        if self.nets.is_empty() {
+            self.host_addrs.contains(ip)
+                || matches!(
+                    AddressClass::of(ip),
+                    AddressClass::Private | AddressClass::UniqueLocal | AddressClass::LinkLocal | AddressClass::Loopback
+                )
+        } else {
+            self.nets.iter().any(|net| net.contains(ip))
+        }
+    }
+}
+
+/// 读取本机所有网卡上的地址
+pub fn host_addresses() -> HashSet<IpAddr> {
+    let mut addrs = HashSet::new();
+    let mut ifap: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut ifap) } != 0 {
+        return addrs;
+    }
+    let mut cursor = ifap;
+    while !cursor.is_null() {
+        let ifa = unsafe { &*cursor };
+        if !ifa.ifa_addr.is_null() {
+            match unsafe { (*ifa.ifa_addr).sa_family } as i32 {
+                libc::AF_INET => {
+                    let sin = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in) };
+                    addrs.insert(IpAddr::V4(Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr))));
+                }
+                libc::AF_INET6 => {
+                    let sin6 = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in6) };
+                    addrs.insert(IpAddr::V6(Ipv6Addr::from(sin6.sin6_addr.s6_addr)));
+                }
+                _ => {}
+            }
+        }
+        cursor = ifa.ifa_next;
+    }
+    unsafe { libc::freeifaddrs(ifap) };
+    addrs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn cidr(s: &str) -> Cidr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn cidr_parsing() {
+        // 主机位被清零，Display 输出网络地址
+        assert_eq!(cidr("192.168.1.77/24").to_string(), "192.168.1.0/24");
+        assert_eq!(cidr("2001:db8:abcd:1234::1/48").to_string(), "2001:db8:abcd::/48");
+        assert_eq!(cidr(" 10.1.2.3 / 8 ").to_string(), "10.0.0.0/8");
+        assert_eq!(cidr("10.1.2.3/0").to_string(), "0.0.0.0/0");
+        assert_eq!(cidr("2001:db8::1/0").to_string(), "::/0");
+        // 不带前缀长度时视为单个主机
+        assert_eq!(cidr("10.1.2.3"), cidr("10.1.2.3/32"));
+        assert_eq!(cidr("2001:db8::1"), cidr("2001:db8::1/128"));
+        assert_eq!(cidr("10.1.2.3/32").addr(), ip("10.1.2.3"));
+
+        for invalid in [
+            "10.0.0.0/33",
+            "2001:db8::/129",
+            "10.0.0.0/-1",
+            "10.0.0.0/",
+            "10.0.0.0/abc",
+            "10.0.0.256/8",
+            "10.0.0/8",
+            "lan",
+            "",
+            "/24",
+        ] {
+            assert!(invalid.parse::<Cidr>().is_err(), "{}", invalid);
+        }
+        assert!(Cidr::new(ip("10.0.0.0"), 33).is_err());
+        assert!(Cidr::new(ip("::"), 128).is_ok());
+    }
+
+    #[test]
+    fn cidr_contains() {
+        let net = cidr("172.16.0.0/12");
+        assert!(net.contains(&ip("172.16.0.0")));
+        assert!(net.contains(&ip("172.31.255.255")));
+        assert!(!net.contains(&ip("172.32.0.0")));
+        assert!(!net.contains(&ip("172.15.255.255")));
+
+        let host = cidr("10.0.0.1/32");
+        assert!(host.contains(&ip("10.0.0.1")));
+        assert!(!host.contains(&ip("10.0.0.2")));
+
+        let host6 = cidr("2001:db8::1/128");
+        assert!(host6.contains(&ip("2001:db8::1")));
+        assert!(!host6.contains(&ip("2001:db8::2")));
+
+        // /0 只匹配同一协议族的所有地址
+        assert!(cidr("0.0.0.0/0").contains(&ip("255.255.255.255")));
+        assert!(!cidr("0.0.0.0/0").contains(&ip("::")));
+        assert!(cidr("::/0").contains(&ip("ffff::1")));
+        assert!(!cidr("::/0").contains(&ip("0.0.0.0")));
+
+        // IPv4 映射的 IPv6 地址不按 IPv4 网段匹配
+        assert!(!cidr("10.0.0.0/8").contains(&ip("::ffff:10.0.0.1")));
+        assert!(cidr("::ffff:0:0/96").contains(&ip("::ffff:10.0.0.1")));
+    }
+
+    #[test]
+    fn cidr_covers_and_overlaps() {
+        let net8 = cidr("10.0.0.0/8");
+        let net24 = cidr("10.1.2.0/24");
+        let host = cidr("10.1.2.3");
+        assert!(net8.covers(&net24));
+        assert!(net8.covers(&host));
+        assert!(net24.covers(&host));
+        assert!(net24.covers(&net24));
+        assert!(!net24.covers(&net8));
+        assert!(!host.covers(&net24));
+
+        assert!(net8.overlaps(&net24));
+        assert!(net24.overlaps(&net8));
+        assert!(!net24.overlaps(&cidr("10.1.3.0/24")));
+        assert!(cidr("0.0.0.0/0").covers(&net8));
+        assert!(cidr("0.0.0.0/0").overlaps(&host));
+
+        // 不同协议族的网段互不覆盖、不重叠
+        let v6_all = cidr("::/0");
+        assert!(!v6_all.covers(&net8));
+        assert!(!v6_all.overlaps(&cidr("0.0.0.0/0")));
+        assert!(cidr("2001:db8::/32").covers(&cidr("2001:db8:1::/48")));
+        assert!(!cidr("2001:db8:1::/48").overlaps(&cidr("2001:db8:2::/48")));
+    }
+
+    #[test]
+    fn masking() {
+        assert_eq!(mask_addr(ip("192.168.1.77"), 24), ip("192.168.1.0"));
+        assert_eq!(mask_addr(ip("192.168.1.77"), 0), ip("0.0.0.0"));
+        assert_eq!(mask_addr(ip("192.168.1.77"), 32), ip("192.168.1.77"));
+        assert_eq!(mask_addr(ip("192.168.1.77"), 31), ip("192.168.1.76"));
+        assert_eq!(mask_addr(ip("255.255.255.255"), 1), ip("128.0.0.0"));
+        // 超出范围的前缀长度按最大值处理
+        assert_eq!(mask_addr(ip("192.168.1.77"), 200), ip("192.168.1.77"));
+
+        assert_eq!(mask_addr(ip("2001:db8:1:2:3:4:5:6"), 48), ip("2001:db8:1::"));
+        assert_eq!(mask_addr(ip("2001:db8:1:2:3:4:5:6"), 0), ip("::"));
+        assert_eq!(mask_addr(ip("2001:db8:1:2:3:4:5:6"), 128), ip("2001:db8:1:2:3:4:5:6"));
+        assert_eq!(mask_addr(ip("2001:db8:1:2:3:4:5:6"), 127), ip("2001:db8:1:2:3:4:5:6"));
+        assert_eq!(mask_addr(ip("2001:db8:1:2:3:4:5:7"), 127), ip("2001:db8:1:2:3:4:5:6"));
+        assert_eq!(mask_addr(ip("2001:db8:1:2:3:4:5:6"), 255), ip("2001:db8:1:2:3:4:5:6"));
+    }
+
+    #[test]
+    fn storage_keys() {
+        let rules = |rules: &[&str]| rules.iter().map(|r| r.to_string()).collect::<Vec<_>>();
+
+        // 默认不过滤也不聚合
+        let filter = FlowFilter::default();
+        assert_eq!(filter.storage_key("1.2.3.4").as_deref(), Some("1.2.3.4"));
+        assert_eq!(filter.storage_key("2001:db8::1").as_deref(), Some("2001:db8::1"));
+
+        let filter = FlowFilter::new(
+            &rules(&["0.0.0.0/0", "2001:db8::/32"]),
+            &rules(&["203.0.113.7", "198.51.100.0/24", "2001:db8:dead::/48"]),
+            Some(24),
+            Some(48),
+        )
+        .unwrap();
+        assert_eq!(filter.storage_key("1.2.3.4").as_deref(), Some("1.2.3.0/24"));
+        assert_eq!(filter.storage_key("203.0.113.8").as_deref(), Some("203.0.113.0/24"));
+        // 排除规则优先于包含规则
+        assert_eq!(filter.storage_key("203.0.113.7"), None);
+        assert_eq!(filter.storage_key("198.51.100.200"), None);
+        assert_eq!(filter.storage_key("2001:db8:dead::1"), None);
+        assert_eq!(filter.storage_key("2001:db8:1:2::1").as_deref(), Some("2001:db8:1::/48"));
+        // 不在包含列表中
+        assert_eq!(filter.storage_key("2001:db9::1"), None);
+        // 已经聚合过的键原样保留
+        assert_eq!(filter.storage_key("1.2.3.0/24").as_deref(), Some("1.2.3.0/24"));
+
+        // /32、/128 聚合等同于不聚合，/0 聚合为整个地址空间
+        let filter = FlowFilter::new(&[], &[], Some(32), Some(0)).unwrap();
+        assert_eq!(filter.storage_key("1.2.3.4").as_deref(), Some("1.2.3.4"));
+        assert_eq!(filter.storage_key("2001:db8::1").as_deref(), Some("::/0"));
+        let filter = FlowFilter::new(&[], &[], Some(0), Some(128)).unwrap();
+        assert_eq!(filter.storage_key("1.2.3.4").as_deref(), Some("0.0.0.0/0"));
+        assert_eq!(filter.storage_key("2001:db8::1").as_deref(), Some("2001:db8::1"));
+
+        assert!(FlowFilter::new(&[], &[], Some(33), None).is_err());
+        assert!(FlowFilter::new(&[], &[], None, Some(129)).is_err());
+        assert!(FlowFilter::new(&rules(&["10.0.0.0/40"]), &[], None, None).is_err());
+        assert!(FlowFilter::new(&[], &rules(&["example.com"]), None, None).is_err());
+    }
+
+    #[test]
+    fn address_classes() {
+        let cases = [
+            ("8.8.8.8", AddressClass::Public),
+            ("2001:4860::8888", AddressClass::Public),
+            ("0.0.0.0", AddressClass::Unspecified),
+            ("0.1.2.3", AddressClass::Unspecified),
+            ("::", AddressClass::Unspecified),
+            ("10.0.0.1", AddressClass::Private),
+            ("10.255.255.255", AddressClass::Private),
+            ("172.16.0.1", AddressClass::Private),
+            ("172.31.255.255", AddressClass::Private),
+            ("172.15.255.255", AddressClass::Public),
+            ("172.32.0.1", AddressClass::Public),
+            ("192.168.1.1", AddressClass::Private),
+            ("192.169.0.1", AddressClass::Public),
+            ("127.0.0.1", AddressClass::Loopback),
+            ("127.255.0.1", AddressClass::Loopback),
+            ("::1", AddressClass::Loopback),
+            ("169.254.1.1", AddressClass::LinkLocal),
+            ("169.255.0.1", AddressClass::Public),
+            ("fe80::1", AddressClass::LinkLocal),
+            ("febf::1", AddressClass::LinkLocal),
+            ("fec0::1", AddressClass::Public),
+            // CGNAT（100.64.0.0/10）是运营商共享地址，按公网统计，需要时用 CIDR 规则排除
+            ("100.64.0.1", AddressClass::Public),
+            ("100.127.255.255", AddressClass::Public),
+            ("fc00::1", AddressClass::UniqueLocal),
+            ("fd12:3456::1", AddressClass::UniqueLocal),
+            ("fe00::1", AddressClass::Public),
+            ("224.0.0.1", AddressClass::Multicast),
+            ("239.255.255.250", AddressClass::Multicast),
+            ("ff02::1", AddressClass::Multicast),
+            ("ff0e::1", AddressClass::Multicast),
+            ("240.0.0.1", AddressClass::Reserved),
+            ("255.255.255.254", AddressClass::Reserved),
+            ("255.255.255.255", AddressClass::Broadcast),
+        ];
+        for (addr, class) in cases {
+            assert_eq!(AddressClass::of(&ip(addr)), class, "{}", addr);
+        }
+    }
+
+    #[test]
+    fn class_and_cidr_rules() {
+        let rules = |rules: &[&str]| rules.iter().map(|r| r.to_string()).collect::<Vec<_>>();
+
+        // 默认只统计公网地址
+        let filter = AddressFilter::default();
+        assert!(filter.is_allowed(&ip("8.8.8.8")));
+        assert!(filter.is_allowed(&ip("100.64.0.1")));
+        assert!(!filter.is_allowed(&ip("192.168.1.1")));
+        assert!(!filter.is_allowed(&ip("fd00::1")));
+        assert!(!filter.is_allowed_str("not-an-ip"));
+
+        // lan = private + link-local + ula；排除网段优先于分类和包含网段
+        let filter = AddressFilter::from_rules(
+            &rules(&["LAN", "224.0.0.0/24"]),
+            &rules(&["public", "192.168.99.0/24", "100.64.0.0/10"]),
+        )
+        .unwrap();
+        for allowed in ["10.0.0.1", "192.168.1.1", "169.254.0.1", "fe80::1", "fd00::1", "224.0.0.251"] {
+            assert!(filter.is_allowed(&ip(allowed)), "{}", allowed);
+        }
+        for denied in ["8.8.8.8", "100.64.0.1", "192.168.99.1", "127.0.0.1", "239.1.1.1", "::1"] {
+            assert!(!filter.is_allowed(&ip(denied)), "{}", denied);
+        }
+
+        let err = AddressFilter::from_rules(&rules(&["privat"]), &[]).unwrap_err();
+        assert!(err.contains("可用分类"));
+    }
+}
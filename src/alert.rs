@@ -0,0 +1,636 @@
+use crate::anomaly::Anomaly;
+use crate::monitor::{format_bytes, TrafficStats};
+use crate::quota::parse_size;
+use chrono::Local;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fs;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// 每个规则窗口划分的桶数（窗口越长，桶越粗）
+const WINDOW_BUCKETS: u64 = 60;
+
+const DEFAULT_WINDOW: &str = "1m";
+const DEFAULT_COOLDOWN: &str = "10m";
+
+// webhook 请求超时
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 待发送告警队列长度，队列满时丢弃新告警
+const ALERT_QUEUE_SIZE: usize = 100;
+
+// 退出时等待队列中告警发送完的最长时间
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 规则类型
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleKind {
+    /// 窗口内流量超过阈值
+    #[default]
+    Traffic,
+    /// 出现此前没有使用过网络的进程
+    NewProcess,
+    /// 异常检测（--anomaly）发现的流量突增
+    Anomaly,
+}
+
+/// 流量规则的统计维度
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    #[default]
+    Ip,
+    Country,
+    Process,
+    Global,
+}
+
+/// 流量规则统计的方向
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    #[default]
+    Total,
+    Tx,
+    Rx,
+}
+
+impl Direction {
+    fn bytes(&self, traffic: &TrafficStats) -> u64 {
+        match self {
+            Self::Total => traffic.total(),
+            Self::Tx => traffic.tx_bytes,
+            Self::Rx => traffic.rx_bytes,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Total => "TX+RX",
+            Self::Tx => "TX",
+            Self::Rx => "RX",
+        }
+    }
+}
+
+/// webhook 消息格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// 通用 JSON（包含全部字段）
+    #[default]
+    Generic,
+    DingTalk,
+    Feishu,
+    Slack,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    name: String,
+    #[serde(default)]
+    kind: RuleKind,
+    #[serde(default)]
+    group_by: GroupBy,
+    #[serde(default)]
+    direction: Direction,
+    threshold: Option<String>,
+    window: Option<String>,
+    cooldown: Option<String>,
+    #[serde(default)]
+    country_in: Vec<String>,
+    #[serde(default)]
+    country_not_in: Vec<String>,
+    severity: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WebhookConfig {
+    url: String,
+    #[serde(default)]
+    format: WebhookFormat,
+}
+
+/// 告警规则文件格式
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AlertConfig {
+    #[serde(default)]
+    rules: Vec<RuleConfig>,
+    #[serde(default)]
+    webhooks: Vec<WebhookConfig>,
+}
+
+/// 一条已解析的规则
+struct Rule {
+    name: String,
+    kind: RuleKind,
+    group_by: GroupBy,
+    direction: Direction,
+    threshold: u64,
+    window: Duration,
+    cooldown: Duration,
+    country_in: Vec<String>,
+    country_not_in: Vec<String>,
+    severity: String,
+    // 分组 -> (桶序号, 字节数)
+    buckets: HashMap<String, VecDeque<(u64, u64)>>,
+}
+
+impl Rule {
+    fn from_config(config: RuleConfig) -> Result<Self, String> {
+        let context = |e: String| format!("告警规则 {}: {}", config.name, e);
+        let threshold = match (config.kind, &config.threshold) {
+            (RuleKind::Traffic, Some(t)) => parse_size(t).map_err(context)?,
+            (RuleKind::Traffic, None) => return Err(context("traffic 规则需要 threshold".to_string())),
+            (_, _) => 0,
+        };
+        Ok(Self {
+            kind: config.kind,
+            group_by: config.group_by,
+            direction: config.direction,
+            threshold,
+            window: parse_duration(config.window.as_deref().unwrap_or(DEFAULT_WINDOW)).map_err(context)?,
+            cooldown: parse_duration(config.cooldown.as_deref().unwrap_or(DEFAULT_COOLDOWN)).map_err(context)?,
+            country_in: config.country_in,
+            country_not_in: config.country_not_in,
+            severity: config.severity.unwrap_or_else(|| "warning".to_string()),
+            buckets: HashMap::new(),
+            name: config.name,
+        })
+    }
+
+    /// 国家条件（同时匹配国家名称和 ISO 代码）
+    fn matches_country(&self, flow: &AlertFlow) -> bool {
+        let matches = |list: &[String]| {
+            list.iter()
+                .any(|c| c.eq_ignore_ascii_case(&flow.country) || c.eq_ignore_ascii_case(&flow.country_code))
+        };
+        (self.country_in.is_empty() || matches(&self.country_in)) && !matches(&self.country_not_in)
+    }
+
+    fn group_key(&self, flow: &AlertFlow) -> Option<String> {
+        match self.group_by {
+            GroupBy::Ip => Some(flow.remote_ip.clone()),
+            GroupBy::Country => Some(flow.country.clone()),
+            GroupBy::Process => flow.process.clone(),
+            GroupBy::Global => Some("global".to_string()),
+        }
+    }
+
+    /// 累加本周期流量并返回超过阈值的 (分组, 窗口内字节数)
+    fn evaluate_traffic(&mut self, flows: &[AlertFlow], now_secs: u64) -> Vec<(String, u64)> {
+        let bucket_secs = (self.window.as_secs() / WINDOW_BUCKETS).max(1);
+        let bucket = now_secs / bucket_secs;
+        let oldest = bucket.saturating_sub(self.window.as_secs().div_ceil(bucket_secs) - 1);
+
+        let mut cycle: HashMap<String, u64> = HashMap::new();
+        for flow in flows.iter().filter(|f| self.matches_country(f)) {
+            if let Some(key) = self.group_key(flow) {
+                let total = cycle.entry(key).or_default();
+                *total = total.saturating_add(self.direction.bytes(&flow.traffic));
+            }
+        }
+        for (key, bytes) in cycle {
+            let buckets = self.buckets.entry(key).or_default();
+            match buckets.back_mut() {
+                Some((index, total)) if *index == bucket => *total = total.saturating_add(bytes),
+                _ => buckets.push_back((bucket, bytes)),
+            }
+        }
+
+        let mut exceeded = Vec::new();
+        self.buckets.retain(|key, buckets| {
+            while buckets.front().is_some_and(|(index, _)| *index < oldest) {
+                buckets.pop_front();
+            }
+            let total = buckets.iter().fold(0u64, |sum, (_, bytes)| sum.saturating_add(*bytes));
+            if total > self.threshold {
+                exceeded.push((key.clone(), total));
+            }
+            !buckets.is_empty()
+        });
+        exceeded
+    }
+}
+
+/// 告警规则评估时使用的一条流
+#[derive(Debug, Clone)]
+pub struct AlertFlow {
+    pub remote_ip: String,
+    pub country: String,
+    pub country_code: String,
+    pub process: Option<String>,
+    pub traffic: TrafficStats,
+}
+
+/// 一条告警
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub rule: String,
+    pub severity: String,
+    pub group: String,
+    pub message: String,
+    pub timestamp: u64,
+}
+
+/// 告警引擎：每个监控周期评估规则，按 (规则, 分组) 去重，冷却时间内不重复通知
+///
+/// 通知由后台线程发送到 webhook，发送失败只打印警告，不影响监控周期；
+/// webhook 响应过慢导致队列积压时丢弃新告警。
+pub struct AlertEngine {
+    rules: Vec<Rule>,
+    webhooks: Vec<WebhookConfig>,
+    // (规则, 分组) -> (上次通知时间, 冷却时间)
+    last_fired: HashMap<(String, String), (Instant, Duration)>,
+    known_processes: HashSet<String>,
+    // 第一个周期只记录已有进程，不产生 new_process 告警
+    learned_processes: bool,
+    sender: Option<SyncSender<Alert>>,
+    sender_thread: Option<thread::JoinHandle<()>>,
+}
+
+impl AlertEngine {
+    /// 从 JSON 规则文件加载
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path).map_err(|e| format!("无法读取告警规则文件 {}: {}", path, e))?;
+        let config: AlertConfig =
+            serde_json::from_str(&content).map_err(|e| format!("告警规则文件 {} 格式错误: {}", path, e))?;
+        let rules = config
+            .rules
+            .into_iter()
+            .map(Rule::from_config)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            rules,
+            webhooks: config.webhooks,
+            last_fired: HashMap::new(),
+            known_processes: HashSet::new(),
+            learned_processes: false,
+            sender: None,
+            sender_thread: None,
+        })
+    }
+
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn webhook_count(&self) -> usize {
+        self.webhooks.len()
+    }
+
+    /// 启动后台发送线程
+    pub fn start(&mut self) {
+        if self.webhooks.is_empty() {
+            return;
+        }
+        let (tx, rx) = mpsc::sync_channel(ALERT_QUEUE_SIZE);
+        let webhooks = self.webhooks.clone();
+        self.sender = Some(tx);
+        self.sender_thread = Some(thread::spawn(move || Self::send_loop(rx, webhooks)));
+    }
+
+    /// 停止发送线程（最多等待 STOP_TIMEOUT 让队列中的告警发送完）
+    pub fn stop(&mut self) {
+        self.sender = None;
+        let Some(handle) = self.sender_thread.take() else {
+            return;
+        };
+        let deadline = Instant::now() + STOP_TIMEOUT;
+        while !handle.is_finished() {
+            if Instant::now() >= deadline {
+                eprintln!("[警告] 告警通知未能在 {} 秒内发送完，放弃剩余告警", STOP_TIMEOUT.as_secs());
+                return;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        let _ = handle.join();
+    }
+
+    fn send_loop(rx: Receiver<Alert>, webhooks: Vec<WebhookConfig>) {
+        let agent = webhook_agent();
+        for alert in rx {
+            for webhook in &webhooks {
+                if let Err(e) = send_webhook(&agent, webhook, &alert) {
+                    eprintln!("[警告] 告警通知发送失败 {}: {}", webhook.url, e);
+                }
+            }
+        }
+    }
+
+    /// 同步发送一条测试告警到全部 webhook（--alert-test）
+    pub fn send_test(&self) -> Result<(), String> {
+        let agent = webhook_agent();
+        let alert = Alert {
+            rule: "test".to_string(),
+            severity: "info".to_string(),
+            group: "test".to_string(),
+            message: "这是一条测试告警".to_string(),
+            timestamp: unix_now(),
+        };
+        let mut failed = 0;
+        for webhook in &self.webhooks {
+            match send_webhook(&agent, webhook, &alert) {
+                Ok(()) => println!("测试告警已发送: {}", webhook.url),
+                Err(e) => {
+                    eprintln!("测试告警发送失败 {}: {}", webhook.url, e);
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            return Err(format!("{} 个 webhook 发送失败", failed));
+        }
+        Ok(())
+    }
+
+    /// 评估一个周期的流量，返回本周期新发出的告警
+    pub fn evaluate(&mut self, flows: &[AlertFlow]) -> Vec<Alert> {
+        let now_secs = unix_now();
+        let mut fired = Vec::new();
+
+        let mut candidates = Vec::new();
+        for rule in &mut self.rules {
+            match rule.kind {
+                RuleKind::Traffic => {
+                    for (group, total) in rule.evaluate_traffic(flows, now_secs) {
+                        let message = format!(
+                            "{} {} 在 {}内的 {} 流量为 {}，超过阈值 {}",
+                            group_label(rule.group_by),
+                            group,
+                            format_duration(rule.window),
+                            rule.direction.label(),
+                            format_bytes(total),
+                            format_bytes(rule.threshold)
+                        );
+                        candidates.push((rule.name.clone(), rule.severity.clone(), rule.cooldown, group, message));
+                    }
+                }
+                RuleKind::NewProcess => {
+                    for flow in flows {
+                        let Some(process) = &flow.process else {
+                            continue;
+                        };
+                        if self.known_processes.contains(process) {
+                            continue;
+                        }
+                        if self.learned_processes {
+                            let message = format!("新进程 {} 开始使用网络（远程 {}）", process, flow.remote_ip);
+                            candidates.push((rule.name.clone(), rule.severity.clone(), rule.cooldown, process.clone(), message));
+                        }
+                    }
+                }
+                // 由 notify_anomalies 处理
+                RuleKind::Anomaly => {}
+            }
+        }
+        self.known_processes.extend(flows.iter().filter_map(|f| f.process.clone()));
+        self.learned_processes = true;
+
+        for (rule, severity, cooldown, group, message) in candidates {
+            if let Some(alert) = self.raise(rule, severity, cooldown, group, message) {
+                fired.push(alert);
+            }
+        }
+        fired
+    }
+
+    /// 把异常检测的结果发给全部 anomaly 规则，返回本周期新发出的告警
+    pub fn notify_anomalies(&mut self, anomalies: &[Anomaly]) -> Vec<Alert> {
+        let candidates: Vec<_> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.kind == RuleKind::Anomaly)
+            .flat_map(|rule| {
+                anomalies.iter().map(|anomaly| {
+                    let group = format!("{}:{}", anomaly.scope.as_str(), anomaly.target);
+                    (rule.name.clone(), rule.severity.clone(), rule.cooldown, group, anomaly.message())
+                })
+            })
+            .collect();
+        candidates
+            .into_iter()
+            .filter_map(|(rule, severity, cooldown, group, message)| self.raise(rule, severity, cooldown, group, message))
+            .collect()
+    }
+
+    /// 发出一条告警；同一 (规则, 分组) 在冷却时间内只通知一次
+    pub fn raise(&mut self, rule: String, severity: String, cooldown: Duration, group: String, message: String) -> Option<Alert> {
+        let key = (rule, group);
+        if self.last_fired.get(&key).is_some_and(|(last, _)| last.elapsed() < cooldown) {
+            return None;
+        }
+        // 清理已过冷却时间的去重记录
+        self.last_fired.retain(|_, (last, cooldown)| last.elapsed() < *cooldown);
+        self.last_fired.insert(key.clone(), (Instant::now(), cooldown));
+
+        let (rule, group) = key;
+        let alert = Alert {
+            rule,
+            severity,
+            group,
+            message,
+            timestamp: unix_now(),
+        };
+        if let Some(sender) = &self.sender {
+            match sender.try_send(alert.clone()) {
+                Ok(()) | Err(TrySendError::Disconnected(_)) => {}
+                Err(TrySendError::Full(_)) => {
+                    eprintln!("[警告] 告警通知队列已满，丢弃告警: {}", alert.message);
+                }
+            }
+        }
+        Some(alert)
+    }
+}
+
+fn webhook_agent() -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .timeout_global(Some(WEBHOOK_TIMEOUT))
+        .build()
+        .into()
+}
+
+/// 按 webhook 格式生成消息体并发送
+fn send_webhook(agent: &ureq::Agent, webhook: &WebhookConfig, alert: &Alert) -> Result<(), Box<dyn Error>> {
+    let hostname = fs::read_to_string("/proc/sys/kernel/hostname").unwrap_or_default();
+    let text = format!(
+        "[{}] {} @ {}\n{}\n时间: {}",
+        alert.severity.to_uppercase(),
+        alert.rule,
+        hostname.trim(),
+        alert.message,
+        Local::now().format("%Y-%m-%d %H:%M:%S")
+    );
+    let payload = match webhook.format {
+        WebhookFormat::Generic => json!({
+            "rule": alert.rule,
+            "severity": alert.severity,
+            "group": alert.group,
+            "message": alert.message,
+            "host": hostname.trim(),
+            "timestamp": alert.timestamp,
+        }),
+        WebhookFormat::DingTalk => json!({ "msgtype": "text", "text": { "content": text } }),
+        WebhookFormat::Feishu => json!({ "msg_type": "text", "content": { "text": text } }),
+        WebhookFormat::Slack => json!({ "text": text }),
+    };
+    agent
+        .post(&webhook.url)
+        .header("Content-Type", "application/json")
+        .send(payload.to_string())?;
+    Ok(())
+}
+
+fn group_label(group_by: GroupBy) -> &'static str {
+    match group_by {
+        GroupBy::Ip => "IP",
+        GroupBy::Country => "国家",
+        GroupBy::Process => "进程",
+        GroupBy::Global => "全部流量",
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// 解析时长，支持 s/m/h/d 后缀，例如 30s、5m、1d
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (number, multiplier) = match s.char_indices().last() {
+        Some((i, 's')) => (&s[..i], 1),
+        Some((i, 'm')) => (&s[..i], 60),
+        Some((i, 'h')) => (&s[..i], 3600),
+        Some((i, 'd')) => (&s[..i], 86400),
+        _ => (s, 1),
+    };
+    let value: u64 = number.trim().parse().map_err(|_| format!("无效的时长: {}", s))?;
+    if value == 0 {
+        return Err(format!("时长必须大于 0: {}", s));
+    }
+    Ok(Duration::from_secs(value * multiplier))
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        s if s.is_multiple_of(86400) => format!("{} 天", s / 86400),
+        s if s.is_multiple_of(3600) => format!("{} 小时", s / 3600),
+        s if s.is_multiple_of(60) => format!("{} 分钟", s / 60),
+        s => format!("{} 秒", s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    /// 接收一个 HTTP 请求，返回 200 并把请求体解析为 JSON
+    fn receive_json(listener: TcpListener) -> Value {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut body = vec![0u8; content_length];
+        reader.read_exact(&mut body).unwrap();
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn post(format: WebhookFormat) -> Value {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let webhook = WebhookConfig {
+            url: format!("http://{}/hook", listener.local_addr().unwrap()),
+            format,
+        };
+        let server = thread::spawn(move || receive_json(listener));
+        let alert = Alert {
+            rule: "rule".to_string(),
+            severity: "critical".to_string(),
+            group: "1.2.3.4".to_string(),
+            message: "流量超过阈值".to_string(),
+            timestamp: 1_700_000_000,
+        };
+        send_webhook(&webhook_agent(), &webhook, &alert).unwrap();
+        server.join().unwrap()
+    }
+
+    #[test]
+    fn traffic_rule_saturates() {
+        let path = std::env::temp_dir().join(format!("ip_traffic_alert_test_{}.json", std::process::id()));
+        fs::write(&path, r#"{"rules": [{"name": "big", "threshold": "1G"}, {"name": "all", "group_by": "global", "threshold": "1G"}]}"#)
+            .unwrap();
+        let mut engine = AlertEngine::load(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let flow = |remote_ip: &str| AlertFlow {
+            remote_ip: remote_ip.to_string(),
+            country: String::new(),
+            country_code: String::new(),
+            process: None,
+            traffic: TrafficStats {
+                tx_bytes: u64::MAX,
+                rx_bytes: 1,
+                ..Default::default()
+            },
+        };
+        let flows = [flow("1.1.1.1"), flow("2.2.2.2")];
+        let alerts = engine.evaluate(&flows);
+        assert_eq!(alerts.len(), 3);
+        // 同一窗口内再次累加仍然饱和，且在冷却时间内不重复告警
+        assert!(engine.evaluate(&flows).is_empty());
+        let total = engine.rules[1].buckets["global"].iter().fold(0u64, |sum, (_, bytes)| sum.saturating_add(*bytes));
+        assert_eq!(total, u64::MAX);
+    }
+
+    #[test]
+    fn webhook_formats() {
+        let generic = post(WebhookFormat::Generic);
+        assert_eq!(generic["rule"], "rule");
+        assert_eq!(generic["severity"], "critical");
+        assert_eq!(generic["group"], "1.2.3.4");
+        assert_eq!(generic["message"], "流量超过阈值");
+        assert_eq!(generic["timestamp"], 1_700_000_000);
+
+        let dingtalk = post(WebhookFormat::DingTalk);
+        assert_eq!(dingtalk["msgtype"], "text");
+        assert!(dingtalk["text"]["content"].as_str().unwrap().contains("流量超过阈值"));
+
+        let feishu = post(WebhookFormat::Feishu);
+        assert_eq!(feishu["msg_type"], "text");
+        assert!(feishu["content"]["text"].as_str().unwrap().contains("[CRITICAL] rule"));
+
+        let slack = post(WebhookFormat::Slack);
+        assert!(slack["text"].as_str().unwrap().contains("流量超过阈值"));
+    }
+}
@@ -0,0 +1,206 @@
+use crate::monitor::{format_bytes, TrafficStats};
+use std::collections::HashMap;
+
+// 标准差下限（字节），避免长期静默的对象方差接近 0 时分数无穷大
+const MIN_STD_BYTES: f64 = 1024.0;
+
+// 连续多少个周期没有流量且基线接近 0 后删除模型
+const IDLE_EVICT_CYCLES: u64 = 720;
+
+/// 基线模型的统计对象
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AnomalyScope {
+    Ip,
+    Process,
+}
+
+impl AnomalyScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ip => "ip",
+            Self::Process => "process",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Ip => "IP",
+            Self::Process => "进程",
+        }
+    }
+}
+
+type ModelKey = (AnomalyScope, String, bool);
+
+/// 指数加权的均值和方差
+#[derive(Debug, Clone, Default)]
+struct Ewma {
+    mean: f64,
+    variance: f64,
+    samples: u64,
+    idle_cycles: u64,
+    // 最近一个周期的异常分数
+    score: f64,
+}
+
+impl Ewma {
+    fn std_dev(&self) -> f64 {
+        self.variance.sqrt().max(MIN_STD_BYTES)
+    }
+
+    fn update(&mut self, value: f64, alpha: f64) {
+        if self.samples == 0 {
+            self.mean = value;
+        } else {
+            let diff = value - self.mean;
+            let increment = alpha * diff;
+            self.mean += increment;
+            self.variance = (1.0 - alpha) * (self.variance + diff * increment);
+        }
+        self.samples += 1;
+    }
+}
+
+/// 一次检测到的异常
+#[derive(Debug, Clone)]
+pub struct Anomaly {
+    pub scope: AnomalyScope,
+    pub target: String,
+    pub is_tx: bool,
+    pub bytes: u64,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub score: f64,
+}
+
+impl Anomaly {
+    pub fn message(&self) -> String {
+        format!(
+            "{} {} 的 {} 流量异常：本周期 {}，基线 {} ± {}（{:.1}σ）",
+            self.scope.label(),
+            self.target,
+            if self.is_tx { "TX" } else { "RX" },
+            format_bytes(self.bytes),
+            format_bytes(self.mean as u64),
+            format_bytes(self.std_dev as u64),
+            self.score
+        )
+    }
+}
+
+/// 单个指标的最近一次异常分数
+pub struct AnomalyScore {
+    pub scope: AnomalyScope,
+    pub target: String,
+    pub is_tx: bool,
+    pub score: f64,
+}
+
+/// 基于 EWMA 的流量异常检测
+///
+/// 对每个远程 IP 和每个进程的每周期 TX / RX 字节数分别维护指数加权均值和方差，
+/// 本周期流量超过均值 N 个标准差（且超出量不小于 min_bytes）时视为异常。
+/// 没有流量的周期按 0 更新基线；样本数不足 warmup 个周期的模型只学习不判断。
+pub struct AnomalyDetector {
+    alpha: f64,
+    sigma: f64,
+    warmup: u64,
+    min_bytes: u64,
+    models: HashMap<ModelKey, Ewma>,
+}
+
+impl AnomalyDetector {
+    pub fn new(alpha: f64, sigma: f64, warmup: u64, min_bytes: u64) -> Result<Self, String> {
+        if !(alpha > 0.0 && alpha <= 1.0) {
+            return Err("EWMA 平滑系数必须在 (0, 1] 之间".to_string());
+        }
+        if sigma <= 0.0 {
+            return Err("异常阈值（标准差倍数）必须大于 0".to_string());
+        }
+        Ok(Self {
+            alpha,
+            sigma,
+            warmup,
+            min_bytes,
+            models: HashMap::new(),
+        })
+    }
+
+    /// 用一个周期的流量更新基线，返回本周期的异常
+    ///
+    /// flows 为 (远程 IP, 进程名, 流量)。
+    pub fn observe<'a>(&mut self, flows: impl IntoIterator<Item = (&'a str, Option<&'a str>, &'a TrafficStats)>) -> Vec<Anomaly> {
+        let mut cycle: HashMap<ModelKey, u64> = HashMap::new();
+        for (remote_ip, process, traffic) in flows {
+            let mut targets = vec![(AnomalyScope::Ip, remote_ip)];
+            if let Some(process) = process {
+                targets.push((AnomalyScope::Process, process));
+            }
+            for (scope, target) in targets {
+                for (is_tx, bytes) in [(true, traffic.tx_bytes), (false, traffic.rx_bytes)] {
+                    let total = cycle.entry((scope, target.to_string(), is_tx)).or_default();
+                    *total = total.saturating_add(bytes);
+                }
+            }
+        }
+
+        // 已有模型在本周期没有流量时按 0 更新
+        for key in self.models.keys() {
+            cycle.entry(key.clone()).or_default();
+        }
+
+        let mut anomalies = Vec::new();
+        for (key, bytes) in cycle {
+            let model = self.models.entry(key.clone()).or_default();
+            let value = bytes as f64;
+            let std_dev = model.std_dev();
+            model.score = if model.samples > 0 { (value - model.mean) / std_dev } else { 0.0 };
+
+            if model.samples >= self.warmup
+                && model.score > self.sigma
+                && value - model.mean >= self.min_bytes as f64
+            {
+                let (scope, target, is_tx) = key;
+                anomalies.push(Anomaly {
+                    scope,
+                    target,
+                    is_tx,
+                    bytes,
+                    mean: model.mean,
+                    std_dev,
+                    score: model.score,
+                });
+            }
+
+            model.update(value, self.alpha);
+            model.idle_cycles = if bytes == 0 { model.idle_cycles + 1 } else { 0 };
+        }
+
+        self.models
+            .retain(|_, model| model.idle_cycles < IDLE_EVICT_CYCLES || model.mean >= 1.0);
+        anomalies.sort_by(|a, b| b.score.total_cmp(&a.score));
+        anomalies
+    }
+
+    /// 只保留 keep 返回 true 的远程 IP 的模型（Top-K 模式下清理已淘汰的流），进程模型不受影响
+    pub fn retain_ips(&mut self, keep: impl Fn(&str) -> bool) {
+        self.models
+            .retain(|(scope, target, _), _| *scope != AnomalyScope::Ip || keep(target));
+    }
+
+    /// 全部模型最近一个周期的异常分数
+    pub fn scores(&self) -> Vec<AnomalyScore> {
+        let mut scores: Vec<AnomalyScore> = self
+            .models
+            .iter()
+            .map(|((scope, target, is_tx), model)| AnomalyScore {
+                scope: *scope,
+                target: target.clone(),
+                is_tx: *is_tx,
+                score: model.score,
+            })
+            .collect();
+        scores.sort_by(|a, b| (a.scope, &a.target, a.is_tx).cmp(&(b.scope, &b.target, b.is_tx)));
+        scores
+    }
+}
@@ -0,0 +1,305 @@
+use once_cell::sync::Lazy;
+use procfs::process::Process;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// /proc/net 下 socket 表的刷新间隔
+const SOCKET_TABLE_REFRESH: Duration = Duration::from_secs(5);
+
+// 找到进程的归属结果最长保留时间（仍需通过存活与 inode 校验）
+const POSITIVE_TTL: Duration = Duration::from_secs(3600);
+
+// 未找到进程的归属结果保留时间（socket 可能稍后才出现在 /proc/net 的 socket 表中）
+const NEGATIVE_TTL: Duration = Duration::from_secs(10);
+
+/// /proc/net 下 socket 表中一条连接的 socket 信息
+#[derive(Debug, Clone, Copy)]
+struct SocketInfo {
+    inode: u32,
+    uid: u32,
+}
+
+/// 远程 IP 对应的本地进程和用户
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IpOwner {
+    pub pid: Option<i32>,
+    pub uid: Option<u32>,
+}
+
+/// 归属缓存条目，记录解析时依赖的 socket inode 和进程启动时间，用于校验是否仍然有效
+#[derive(Debug, Clone, Copy)]
+struct CachedOwner {
+    owner: IpOwner,
+    inode: Option<u32>,
+    start_time: Option<u64>,
+    resolved_at: Instant,
+}
+
+impl CachedOwner {
+    /// 检查缓存是否仍然有效
+    fn is_valid(&self, current_inode: Option<u32>) -> bool {
+        // socket 出现、消失或被替换，之前的结论都不再可信
+        if self.inode != current_inode {
+            return false;
+        }
+
+        match self.owner.pid {
+            None => self.resolved_at.elapsed() < NEGATIVE_TTL,
+            Some(pid) => {
+                self.resolved_at.elapsed() < POSITIVE_TTL
+                    && self.start_time.is_some()
+                    && process_start_time(pid) == self.start_time
+            }
+        }
+    }
+}
+
+// IP -> 归属（PID、UID）缓存（减少 /proc 遍历）
+static OWNER_CACHE: Lazy<Mutex<HashMap<String, CachedOwner>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// PID -> 进程名缓存（减少 /proc 文件读取），进程退出时随归属缓存一起失效
+static PROCESS_NAME_CACHE: Lazy<Mutex<HashMap<i32, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// UID -> 用户名缓存（减少 /etc/passwd 读取）
+static USER_NAME_CACHE: Lazy<Mutex<HashMap<u32, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// /proc/net socket 表缓存（减少文件读取），初始刷新时间为空以便首次查询立即读取
+type SocketTable = (Option<Instant>, HashMap<String, SocketInfo>);
+static SOCKET_TABLE_CACHE: Lazy<Mutex<SocketTable>> = Lazy::new(|| Mutex::new((None, HashMap::new())));
+
+/// 查询远程 IP 对应的本地进程和用户（带缓存）
+pub fn get_owner_for_ip(ip: &str) -> IpOwner {
+    let socket = current_socket_for_ip(ip);
+    let current_inode = socket.map(|s| s.inode);
+
+    // 先检查归属缓存，校验失败的条目直接丢弃
+    {
+        let mut cache = OWNER_CACHE.lock().unwrap();
+        if let Some(cached) = cache.get(ip) {
+            if cached.is_valid(current_inode) {
+                return cached.owner;
+            }
+            if let Some(pid) = cached.owner.pid {
+                PROCESS_NAME_CACHE.lock().unwrap().remove(&pid);
+            }
+            cache.remove(ip);
+        }
+    }
+
+    // 如果找到 inode，查询 PID；UID 优先使用 socket 表中的值，否则取进程的 UID
+    let pid = socket.and_then(|s| find_pid_by_inode(s.inode)).map(|p| p as i32);
+    let uid = socket
+        .map(|s| s.uid)
+        .or_else(|| pid.and_then(|p| Process::new(p).ok()?.uid().ok()));
+    let owner = IpOwner { pid, uid };
+
+    {
+        let mut cache = OWNER_CACHE.lock().unwrap();
+        cache.insert(
+            ip.to_string(),
+            CachedOwner {
+                owner,
+                inode: current_inode,
+                start_time: pid.and_then(process_start_time),
+                resolved_at: Instant::now(),
+            },
+        );
+    }
+
+    owner
+}
+
+/// 只保留 keep 返回 true 的远程 IP 的归属缓存（Top-K 模式下清理已淘汰的流）
+pub fn retain_owners(keep: impl Fn(&str) -> bool) {
+    let mut cache = OWNER_CACHE.lock().unwrap();
+    let mut process_names = PROCESS_NAME_CACHE.lock().unwrap();
+    cache.retain(|ip, cached| {
+        let keep = keep(ip);
+        if !keep {
+            if let Some(pid) = cached.owner.pid {
+                process_names.remove(&pid);
+            }
+        }
+        keep
+    });
+}
+
+/// 从 socket 表中查询 IP 当前对应的 socket，必要时刷新连接表
+fn current_socket_for_ip(ip: &str) -> Option<SocketInfo> {
+    let mut socket_cache = SOCKET_TABLE_CACHE.lock().unwrap();
+
+    let expired = socket_cache
+        .0
+        .is_none_or(|refreshed_at| refreshed_at.elapsed() >= SOCKET_TABLE_REFRESH);
+    if expired {
+        socket_cache.1 = build_ip_to_socket_map();
+        socket_cache.0 = Some(Instant::now());
+
+        let live_inodes: HashSet<u32> = socket_cache.1.values().map(|s| s.inode).collect();
+        evict_closed_sockets(&live_inodes);
+    }
+
+    socket_cache.1.get(ip).copied()
+}
+
+/// 移除 socket 已从 socket 表中消失的缓存条目，以及已过期的未命中结果
+fn evict_closed_sockets(live_inodes: &HashSet<u32>) {
+    let mut cache = OWNER_CACHE.lock().unwrap();
+    let mut process_names = PROCESS_NAME_CACHE.lock().unwrap();
+
+    cache.retain(|_, cached| {
+        let keep = match cached.inode {
+            Some(inode) => live_inodes.contains(&inode),
+            None => cached.resolved_at.elapsed() < NEGATIVE_TTL,
+        };
+        if !keep {
+            if let Some(pid) = cached.owner.pid {
+                process_names.remove(&pid);
+            }
+        }
+        keep
+    });
+}
+
+/// 读取进程启动时间（用于识别 PID 复用）
+fn process_start_time(pid: i32) -> Option<u64> {
+    Process::new(pid).ok()?.stat().ok().map(|stat| stat.starttime)
+}
+
+// 读取的 socket 表（TCP / UDP，IPv4 / IPv6）
+const SOCKET_TABLES: [&str; 4] = ["/proc/net/tcp", "/proc/net/tcp6", "/proc/net/udp", "/proc/net/udp6"];
+
+// 批量读取 /proc/net/{tcp,tcp6,udp,udp6}，建立 IP -> socket（inode、UID）映射
+fn build_ip_to_socket_map() -> HashMap<String, SocketInfo> {
+    let mut map = HashMap::new();
+
+    for path in SOCKET_TABLES {
+        let Ok(content) = std::fs::read_to_string(path) else {
+            continue;
+        };
+        for line in content.lines().skip(1) {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 10 {
+                continue;
+            }
+            // 解析远程地址；未连接的 UDP socket 远程地址为全 0
+            let Some(ip) = parts[2].split(':').next().and_then(parse_proc_addr) else {
+                continue;
+            };
+            if ip.is_unspecified() {
+                continue;
+            }
+
+            // 解析 UID（第 8 列）和 inode（第 10 列），inode 为 0 表示 TIME_WAIT 等无归属连接
+            if let (Ok(uid), Ok(inode)) = (parts[7].parse::<u32>(), parts[9].parse::<u32>()) {
+                if inode != 0 {
+                    map.entry(ip.to_string()).or_insert(SocketInfo { inode, uid });
+                }
+            }
+        }
+    }
+
+    map
+}
+
+// 解析 /proc/net 中的十六进制地址（按 32 位字以主机字节序输出）；IPv4 映射的 IPv6 地址转换为 IPv4
+fn parse_proc_addr(hex: &str) -> Option<IpAddr> {
+    match hex.len() {
+        8 => {
+            let addr_num = u32::from_str_radix(hex, 16).ok()?;
+            Some(IpAddr::V4(Ipv4Addr::from(addr_num.to_ne_bytes())))
+        }
+        32 => {
+            let mut octets = [0u8; 16];
+            for (i, chunk) in octets.chunks_mut(4).enumerate() {
+                let word = u32::from_str_radix(&hex[i * 8..i * 8 + 8], 16).ok()?;
+                chunk.copy_from_slice(&word.to_ne_bytes());
+            }
+            let ip = Ipv6Addr::from(octets);
+            Some(ip.to_ipv4_mapped().map_or(IpAddr::V6(ip), IpAddr::V4))
+        }
+        _ => None,
+    }
+}
+
+// 通过 inode 查找 PID
+fn find_pid_by_inode(inode: u32) -> Option<u32> {
+    std::fs::read_dir("/proc")
+        .ok()?
+        .flatten()
+        .find_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse::<u32>().ok()?;
+            Process::new(pid as i32)
+                .ok()?
+                .fd()
+                .ok()?
+                .flatten()
+                .find_map(|fd| match &fd.target {
+                    procfs::process::FDTarget::Socket(socket_inode) => {
+                        if *socket_inode == inode as u64 {
+                            Some(pid)
+                        } else {
+                            None
+                        }
+                    }
+                    _ => None,
+                })
+        })
+}
+
+/// 根据 PID 获取进程名称（带缓存）
+pub fn get_process_name(pid: i32) -> Option<String> {
+    // 先检查缓存
+    {
+        let cache = PROCESS_NAME_CACHE.lock().unwrap();
+        if let Some(name) = cache.get(&pid) {
+            return Some(name.clone());
+        }
+    }
+
+    // 从 /proc 读取进程名
+    let process_name = Process::new(pid)
+        .ok()?
+        .stat()
+        .ok()
+        .map(|stat| stat.comm)?;
+
+    // 保存到缓存
+    {
+        let mut cache = PROCESS_NAME_CACHE.lock().unwrap();
+        cache.insert(pid, process_name.clone());
+    }
+
+    Some(process_name)
+}
+
+/// 根据 UID 获取用户名（带缓存，读取 /etc/passwd）
+pub fn get_username(uid: u32) -> String {
+    {
+        let cache = USER_NAME_CACHE.lock().unwrap();
+        if let Some(name) = cache.get(&uid) {
+            return name.clone();
+        }
+    }
+
+    let username = std::fs::read_to_string("/etc/passwd")
+        .ok()
+        .and_then(|content| {
+            content.lines().find_map(|line| {
+                let mut fields = line.split(':');
+                let name = fields.next()?;
+                let entry_uid = fields.nth(1)?.parse::<u32>().ok()?;
+                (entry_uid == uid).then(|| name.to_string())
+            })
+        })
+        .unwrap_or_else(|| uid.to_string());
+
+    {
+        let mut cache = USER_NAME_CACHE.lock().unwrap();
+        cache.insert(uid, username.clone());
+    }
+
+    username
+}
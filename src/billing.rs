@@ -0,0 +1,289 @@
+use crate::address_filter::Cidr;
+use crate::monitor::{FlowKey, TrafficStats};
+use chrono::{DateTime, Datelike, Local, Months, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// 计费采样周期（5 分钟）
+pub const SAMPLE_SECONDS: i64 = 300;
+
+// 计费日的取值范围（避免 29~31 日在短月份不存在）
+pub const MAX_BILLING_DAY: u32 = 28;
+
+/// 计费统计的对象类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetKind {
+    Interface,
+    Network,
+}
+
+impl TargetKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Interface => "interface",
+            Self::Network => "network",
+        }
+    }
+}
+
+/// 一个 5 分钟采样：(采样开始时间戳, TX 比特率, RX 比特率)
+type Sample = (i64, f64, f64);
+
+/// 持久化文件格式
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BillingState {
+    period_start: i64,
+    samples: BTreeMap<String, TargetSamples>,
+    // 最后一个已结束窗口的开始时间，用于补记停机期间缺失的窗口
+    #[serde(default)]
+    last_bucket: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TargetSamples {
+    kind: TargetKind,
+    samples: Vec<Sample>,
+}
+
+/// 某个对象在当前计费周期内的统计结果（单位：比特/秒）
+#[derive(Debug, Clone, Serialize)]
+pub struct BillingReport {
+    pub target: String,
+    pub kind: TargetKind,
+    pub samples: usize,
+    pub tx_p95_bps: f64,
+    pub tx_p99_bps: f64,
+    pub tx_max_bps: f64,
+    pub rx_p95_bps: f64,
+    pub rx_p99_bps: f64,
+    pub rx_max_bps: f64,
+    // 按 TX / RX 中较大者计费时的 95 值
+    pub billable_p95_bps: f64,
+}
+
+/// 95 计费统计
+///
+/// 按墙上时间对齐的 5 分钟窗口累计每个网卡和每个指定网段的流量，窗口结束时记录一个平均比特率样本。
+/// 计费周期从每月的计费日 0 点开始；样本在每个窗口结束时写入状态文件，重启后同一计费周期内的样本会被恢复。
+/// 停机或未采集期间缺失的窗口按 0 记录。
+pub struct BillingTracker {
+    billing_day: u32,
+    networks: Vec<Cidr>,
+    state_path: Option<String>,
+    state: BillingState,
+    // 当前窗口的开始时间和累计流量
+    bucket_start: i64,
+    bucket: HashMap<(TargetKind, String), TrafficStats>,
+    // 进程启动时间（第一个窗口只覆盖启动之后的部分）
+    started: i64,
+}
+
+impl BillingTracker {
+    pub fn new(billing_day: u32, networks: Vec<Cidr>, state_path: Option<String>) -> Result<Self, Box<dyn Error>> {
+        if !(1..=MAX_BILLING_DAY).contains(&billing_day) {
+            return Err(format!("计费日必须在 1~{} 之间", MAX_BILLING_DAY).into());
+        }
+        let now = Local::now();
+        let period_start = period_start(now, billing_day).timestamp();
+
+        let mut state = BillingState {
+            period_start,
+            ..Default::default()
+        };
+        if let Some(path) = state_path.as_deref().filter(|p| Path::new(p).exists()) {
+            let content = fs::read_to_string(path).map_err(|e| format!("无法读取计费状态文件 {}: {}", path, e))?;
+            let saved: BillingState =
+                serde_json::from_str(&content).map_err(|e| format!("计费状态文件 {} 格式错误: {}", path, e))?;
+            // 上一个计费周期的样本不再参与计算
+            if saved.period_start == period_start {
+                state = saved;
+            } else {
+                state.last_bucket = saved.last_bucket;
+            }
+        }
+
+        let now = now.timestamp();
+        Ok(Self {
+            billing_day,
+            networks,
+            state_path,
+            state,
+            bucket_start: now - now.rem_euclid(SAMPLE_SECONDS),
+            bucket: HashMap::new(),
+            started: now,
+        })
+    }
+
+    /// 已恢复的样本数
+    pub fn restored_samples(&self) -> usize {
+        self.state.samples.values().map(|t| t.samples.len()).sum()
+    }
+
+    /// 累加一个周期的流量；跨过 5 分钟边界时先结束上一个窗口
+    pub fn record<'a>(&mut self, flows: impl IntoIterator<Item = (&'a FlowKey, &'a TrafficStats)>) -> Result<(), Box<dyn Error>> {
+        let now = Local::now();
+        let timestamp = now.timestamp();
+        if timestamp >= self.bucket_start + SAMPLE_SECONDS {
+            let next_start = timestamp - timestamp.rem_euclid(SAMPLE_SECONDS);
+            self.close_bucket(now, next_start)?;
+            self.bucket_start = next_start;
+        }
+
+        for (key, traffic) in flows {
+            self.bucket
+                .entry((TargetKind::Interface, key.interface.clone()))
+                .or_default()
+                .add(traffic);
+            let Ok(remote) = key.remote_ip.parse::<IpAddr>() else {
+                continue;
+            };
+            for network in self.networks.iter().filter(|n| n.contains(&remote)) {
+                self.bucket
+                    .entry((TargetKind::Network, network.to_string()))
+                    .or_default()
+                    .add(traffic);
+            }
+        }
+        Ok(())
+    }
+
+    /// 结束当前窗口：为所有已知对象记录样本（没有流量的对象记为 0），并写入状态文件
+    ///
+    /// 上一个已结束窗口与当前窗口之间、当前窗口与 next_start 之间缺失的窗口都记为 0。
+    fn close_bucket(&mut self, now: DateTime<Local>, next_start: i64) -> Result<(), Box<dyn Error>> {
+        let covered = (self.bucket_start + SAMPLE_SECONDS - self.bucket_start.max(self.started)).max(1) as f64;
+        let period_start = period_start(now, self.billing_day).timestamp();
+        if period_start != self.state.period_start {
+            self.state = BillingState {
+                period_start,
+                last_bucket: self.state.last_bucket,
+                ..Default::default()
+            };
+        }
+
+        // 指定的网段和出现过的网卡都参与计费
+        for network in &self.networks {
+            self.bucket.entry((TargetKind::Network, network.to_string())).or_default();
+        }
+        for (target, samples) in &self.state.samples {
+            self.bucket.entry((samples.kind, target.clone())).or_default();
+        }
+
+        let targets: Vec<(TargetKind, String)> = self.bucket.keys().cloned().collect();
+        if let Some(last_bucket) = self.state.last_bucket {
+            self.push_zero_samples(&targets, last_bucket + SAMPLE_SECONDS, self.bucket_start);
+        }
+
+        // 窗口开始时间早于计费周期时只计入新周期（周期切换后的第一个窗口）
+        let sample_time = self.bucket_start.max(self.state.period_start);
+        for ((kind, target), traffic) in self.bucket.drain() {
+            let tx_bps = traffic.tx_bytes as f64 * 8.0 / covered;
+            let rx_bps = traffic.rx_bytes as f64 * 8.0 / covered;
+            self.state
+                .samples
+                .entry(target)
+                .or_insert_with(|| TargetSamples { kind, samples: Vec::new() })
+                .samples
+                .push((sample_time, tx_bps, rx_bps));
+        }
+
+        self.push_zero_samples(&targets, self.bucket_start + SAMPLE_SECONDS, next_start);
+        self.state.last_bucket = Some(next_start - SAMPLE_SECONDS);
+
+        self.save()
+    }
+
+    /// 为 [from, to) 内属于当前计费周期的每个窗口记录 0 样本
+    fn push_zero_samples(&mut self, targets: &[(TargetKind, String)], from: i64, to: i64) {
+        let from = from.max(self.state.period_start);
+        // 周期内的窗口按 5 分钟对齐（计费周期从 0 点开始，总是对齐的）
+        let from = from + (SAMPLE_SECONDS - from.rem_euclid(SAMPLE_SECONDS)) % SAMPLE_SECONDS;
+        for start in (from..to).step_by(SAMPLE_SECONDS as usize) {
+            for (kind, target) in targets {
+                self.state
+                    .samples
+                    .entry(target.clone())
+                    .or_insert_with(|| TargetSamples { kind: *kind, samples: Vec::new() })
+                    .samples
+                    .push((start, 0.0, 0.0));
+            }
+        }
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        let Some(path) = self.state_path.as_deref() else {
+            return Ok(());
+        };
+        // 先写临时文件再改名，避免写入过程中退出导致文件损坏
+        let tmp_path = format!("{}.tmp", path);
+        fs::write(&tmp_path, serde_json::to_vec(&self.state)?)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// 当前计费周期的开始和结束时间
+    pub fn period(&self) -> (DateTime<Local>, DateTime<Local>) {
+        let start = period_start(Local::now(), self.billing_day);
+        (start, start + Months::new(1))
+    }
+
+    /// 当前计费周期内每个对象的统计结果
+    pub fn reports(&self) -> Vec<BillingReport> {
+        let mut reports: Vec<BillingReport> = self
+            .state
+            .samples
+            .iter()
+            .map(|(target, samples)| {
+                let tx: Vec<f64> = samples.samples.iter().map(|s| s.1).collect();
+                let rx: Vec<f64> = samples.samples.iter().map(|s| s.2).collect();
+                let billable: Vec<f64> = samples.samples.iter().map(|s| s.1.max(s.2)).collect();
+                BillingReport {
+                    target: target.clone(),
+                    kind: samples.kind,
+                    samples: samples.samples.len(),
+                    tx_p95_bps: percentile(&tx, 0.95),
+                    tx_p99_bps: percentile(&tx, 0.99),
+                    tx_max_bps: percentile(&tx, 1.0),
+                    rx_p95_bps: percentile(&rx, 0.95),
+                    rx_p99_bps: percentile(&rx, 0.99),
+                    rx_max_bps: percentile(&rx, 1.0),
+                    billable_p95_bps: percentile(&billable, 0.95),
+                }
+            })
+            .collect();
+        reports.sort_by(|a, b| (a.kind, &a.target).cmp(&(b.kind, &b.target)));
+        reports
+    }
+}
+
+/// 包含 now 的计费周期的开始时间（计费日 0 点）
+pub fn period_start(now: DateTime<Local>, billing_day: u32) -> DateTime<Local> {
+    let this_month = NaiveDate::from_ymd_opt(now.year(), now.month(), billing_day).expect("计费日不超过 28");
+    let start = if now.day() >= billing_day {
+        this_month
+    } else {
+        this_month - Months::new(1)
+    };
+    let midnight = start.and_hms_opt(0, 0, 0).expect("0 点总是有效的时间");
+    // 夏令时切换导致 0 点不存在时取最早的有效时间
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .unwrap_or_else(|| Local.from_utc_datetime(&midnight))
+}
+
+/// 最近秩法百分位数：去掉最高的 (1 - p) 部分样本后的最大值
+fn percentile(values: &[f64], p: f64) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
@@ -1,4 +1,5 @@
-use crate::monitor::{TrafficMonitor, TrafficStats};
+use crate::address_filter::AddressFilter;
+use crate::monitor::{FlowKey, InterfaceSelector, TrafficMonitor, TrafficStats, ANY_ADDRESS, ANY_INTERFACE};
 use std::collections::HashMap;
 use std::error::Error;
 use std::io::{BufRead, BufReader};
@@ -8,21 +9,32 @@ use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
 
+type StatsReceiver = Receiver<HashMap<FlowKey, TrafficStats>>;
+
 /// 基于 bpftrace 的流量监控器
 pub struct BpftraceMonitor {
     sample_interval: u32,
     script_path: Option<String>,
+    address_filter: AddressFilter,
+    interfaces: InterfaceSelector,
     child_process: Option<Child>,
     running: Arc<AtomicBool>,
-    stats_receiver: Option<Arc<Mutex<Receiver<HashMap<String, TrafficStats>>>>>,
+    stats_receiver: Option<Arc<Mutex<StatsReceiver>>>,
     output_thread: Option<thread::JoinHandle<()>>,
 }
 
 impl BpftraceMonitor {
-    pub fn new(sample_interval: u32, script_path: Option<String>) -> Self {
+    pub fn new(
+        sample_interval: u32,
+        script_path: Option<String>,
+        address_filter: AddressFilter,
+        interfaces: InterfaceSelector,
+    ) -> Self {
         Self {
             sample_interval,
             script_path,
+            address_filter,
+            interfaces,
             child_process: None,
             running: Arc::new(AtomicBool::new(false)),
             stats_receiver: None,
@@ -44,10 +56,11 @@ tracepoint:net:netif_receive_skb
     $skb = (struct sk_buff *)args->skbaddr;
     $iph = (struct iphdr *)($skb->head + $skb->network_header);
     $saddr = $iph->saddr;
+    $daddr = $iph->daddr;
     $len = args->len;
     
-    // 统计从远程IP接收的字节数（下行流量）
-    @rx_bytes[$saddr] = sum($len);
+    // 按网卡、本地IP统计从远程IP接收的字节数（下行流量）
+    @rx_bytes[str(args->name), $daddr, $saddr] = sum($len);
 }}
 
 // 监控发送流量（上行）
@@ -56,10 +69,11 @@ tracepoint:net:net_dev_start_xmit
 {{
     $skb = (struct sk_buff *)args->skbaddr;
     $iph = (struct iphdr *)($skb->head + $skb->network_header);
+    $saddr = $iph->saddr;
     $daddr = $iph->daddr;
     $len = args->len;
     
-    @tx_bytes[$daddr] = sum($len);
+    @tx_bytes[str(args->name), $saddr, $daddr] = sum($len);
 }}
 
 interval:s:{} {{
@@ -78,90 +92,26 @@ interval:s:{} {{
         )
     }
 
-    /// 检查 IP 地址是否为公网 IP（过滤私有、保留、本地地址）
-    fn is_valid_ip(ip: &str) -> bool {
-        // 尝试解析为标准 IP 地址格式
-        if let Ok(addr) = ip.parse::<std::net::IpAddr>() {
-            match addr {
-                std::net::IpAddr::V4(ipv4) => {
-                    let octets = ipv4.octets();
-
-                    // 过滤 0.0.0.0/8 (当前网络)
-                    if octets[0] == 0 {
-                        return false;
-                    }
-
-                    // 过滤 10.0.0.0/8 (私有网络 A 类)
-                    if octets[0] == 10 {
-                        return false;
-                    }
-
-                    // 过滤 127.0.0.0/8 (本地回环)
-                    if octets[0] == 127 {
-                        return false;
-                    }
-
-                    // 过滤 172.16.0.0/12 (私有网络 B 类)
-                    if octets[0] == 172 && octets[1] >= 16 && octets[1] <= 31 {
-                        return false;
-                    }
-
-                    // 过滤 192.168.0.0/16 (私有网络 C 类)
-                    if octets[0] == 192 && octets[1] == 168 {
-                        return false;
-                    }
-
-                    // 过滤 169.254.0.0/16 (链路本地地址)
-                    if octets[0] == 169 && octets[1] == 254 {
-                        return false;
-                    }
-
-                    // 过滤 224.0.0.0/4 (组播地址)
-                    if octets[0] >= 224 && octets[0] <= 239 {
-                        return false;
-                    }
-
-                    // 过滤 240.0.0.0/4 (保留地址)
-                    if octets[0] >= 240 {
-                        return false;
-                    }
-
-                    // 过滤 255.255.255.255 (广播地址)
-                    if octets == [255, 255, 255, 255] {
-                        return false;
-                    }
-
-                    // 其他地址视为公网 IP
-                    true
-                }
-                std::net::IpAddr::V6(ipv6) => {
-                    // IPv6: 过滤本地和特殊地址
-                    if ipv6.is_loopback() || ipv6.is_unspecified() || ipv6.is_multicast() {
-                        return false;
-                    }
-                    // 过滤链路本地地址 (fe80::/10)
-                    let segments = ipv6.segments();
-                    if segments[0] & 0xffc0 == 0xfe80 {
-                        return false;
-                    }
-                    // 过滤唯一本地地址 (fc00::/7)
-                    if segments[0] & 0xfe00 == 0xfc00 {
-                        return false;
-                    }
-                    true
-                }
-            }
-        } else {
-            // 无法解析为 IP 地址
-            false
-        }
+    /// 将 bpftrace 输出的数字地址（网络字节序的 u32）转换为 IP 字符串
+    fn addr_to_ip(addr_str: &str) -> Option<String> {
+        let addr = addr_str.parse::<u32>().ok()?;
+        // 网络字节序转主机字节序并转换为 IP 字符串
+        let octets = [
+            (addr & 0xFF) as u8,
+            ((addr >> 8) & 0xFF) as u8,
+            ((addr >> 16) & 0xFF) as u8,
+            ((addr >> 24) & 0xFF) as u8,
+        ];
+        Some(format!("{}.{}.{}.{}", octets[0], octets[1], octets[2], octets[3]))
     }
 
     /// 解析 bpftrace 输出行（静态方法）
     fn parse_output_line(
         line: &str,
         current_section: &mut String,
-        stats: &mut HashMap<String, TrafficStats>,
+        stats: &mut HashMap<FlowKey, TrafficStats>,
+        address_filter: &AddressFilter,
+        interfaces: &InterfaceSelector,
     ) {
         let line = line.trim();
 
@@ -177,27 +127,38 @@ interval:s:{} {{
             && line.contains("]:")
         {
             // 解析 bpftrace map 输出格式: @map_name[key]: value
-            // 例如: @tx_bytes[16777343]: 1234 (使用数字地址作为 key)
+            // 例如: @tx_bytes[eth0, 16777343, 134744072]: 1234 (使用网卡名、本地地址、远程地址作为 key)
+            // 自定义脚本省略网卡或本地地址时，对应字段记为 any
             if let Some(bracket_start) = line.find('[') {
                 if let Some(bracket_end) = line.find("]:") {
-                    let addr_str = &line[bracket_start + 1..bracket_end];
+                    let key_str = &line[bracket_start + 1..bracket_end];
+                    let fields: Vec<&str> = key_str.split(',').map(str::trim).collect();
+                    let (interface, local_str, addr_str) = match fields.as_slice() {
+                        [iface, local, remote] => (*iface, Some(*local), *remote),
+                        [iface, remote] => (*iface, None, *remote),
+                        [remote] => (ANY_INTERFACE, None, *remote),
+                        _ => return,
+                    };
+
+                    if interface != ANY_INTERFACE && !interfaces.matches(interface) {
+                        return;
+                    }
                     
                     // 将数字地址转换为 IP 字符串
-                    let ip = if let Ok(addr) = addr_str.parse::<u32>() {
-                        // 网络字节序转主机字节序并转换为 IP 字符串
-                        let octets = [
-                            (addr & 0xFF) as u8,
-                            ((addr >> 8) & 0xFF) as u8,
-                            ((addr >> 16) & 0xFF) as u8,
-                            ((addr >> 24) & 0xFF) as u8,
-                        ];
-                        format!("{}.{}.{}.{}", octets[0], octets[1], octets[2], octets[3])
-                    } else {
-                        return;
+                    let ip = match Self::addr_to_ip(addr_str) {
+                        Some(ip) => ip,
+                        None => return,
+                    };
+                    let local_ip = match local_str {
+                        Some(local) => match Self::addr_to_ip(local) {
+                            Some(ip) => ip,
+                            None => return,
+                        },
+                        None => ANY_ADDRESS.to_string(),
                     };
 
-                    // 过滤无效 IP 地址
-                    if !Self::is_valid_ip(&ip) {
+                    // 按地址分类规则过滤
+                    if !address_filter.is_allowed_str(&ip) {
                         return;
                     }
 
@@ -205,8 +166,8 @@ interval:s:{} {{
 
                     if let Ok(value) = value_str.parse::<u64>() {
                         let entry = stats
-                            .entry(ip.to_string())
-                            .or_insert_with(TrafficStats::default);
+                            .entry(FlowKey::new(interface, local_ip, ip))
+                            .or_default();
 
                         match current_section.as_str() {
                             "tx_bytes" => entry.tx_bytes = value,
@@ -249,7 +210,7 @@ impl TrafficMonitor for BpftraceMonitor {
         self.running.store(true, Ordering::SeqCst);
 
         let mut child = Command::new("stdbuf")
-            .args(&[
+            .args([
                 "-o0",
                 "-e0",
                 "bpftrace",
@@ -266,19 +227,18 @@ impl TrafficMonitor for BpftraceMonitor {
         let stdout = child.stdout.take().ok_or("无法获取 bpftrace stdout")?;
 
         // 创建通道用于接收统计数据
-        let (tx, rx): (
-            Sender<HashMap<String, TrafficStats>>,
-            Receiver<HashMap<String, TrafficStats>>,
-        ) = mpsc::channel();
+        let (tx, rx): (Sender<HashMap<FlowKey, TrafficStats>>, StatsReceiver) = mpsc::channel();
         self.stats_receiver = Some(Arc::new(Mutex::new(rx)));
 
         let running = Arc::clone(&self.running);
+        let address_filter = self.address_filter.clone();
+        let interfaces = self.interfaces.clone();
 
         // 启动后台线程持续读取 bpftrace 输出
         let output_thread = thread::spawn(move || {
             let reader = BufReader::new(stdout);
             let mut current_section = String::new();
-            let mut temp_stats: HashMap<String, TrafficStats> = HashMap::new();
+            let mut temp_stats: HashMap<FlowKey, TrafficStats> = HashMap::new();
 
             let mut line_iter = reader.lines();
             loop {
@@ -309,7 +269,13 @@ impl TrafficMonitor for BpftraceMonitor {
                         }
 
                         // 解析输出行
-                        Self::parse_output_line(&line, &mut current_section, &mut temp_stats);
+                        Self::parse_output_line(
+                            &line,
+                            &mut current_section,
+                            &mut temp_stats,
+                            &address_filter,
+                            &interfaces,
+                        );
                     }
                     Some(Err(e)) => {
                         eprintln!("[错误] 读取 bpftrace 输出失败: {}", e);
@@ -337,7 +303,7 @@ impl TrafficMonitor for BpftraceMonitor {
         Ok(())
     }
 
-    fn start(&mut self) -> Result<HashMap<String, TrafficStats>, Box<dyn Error>> {
+    fn start(&mut self) -> Result<HashMap<FlowKey, TrafficStats>, Box<dyn Error>> {
         // 从通道接收最新的统计数据
         let receiver = self
             .stats_receiver
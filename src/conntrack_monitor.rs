@@ -0,0 +1,389 @@
+use crate::address_filter::{host_addresses, AddressFilter, LocalNetworks};
+use crate::monitor::{FlowKey, TrafficMonitor, TrafficStats, ANY_INTERFACE};
+use crate::nfnetlink::{self, NlaIter};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const ACCT_SYSCTL: &str = "/proc/sys/net/netfilter/nf_conntrack_acct";
+const PROC_CONNTRACK: &str = "/proc/net/nf_conntrack";
+
+// ctnetlink 常量（linux/netfilter/nfnetlink_conntrack.h）
+const NFNL_SUBSYS_CTNETLINK: u16 = 1;
+const IPCTNL_MSG_CT_GET: u16 = 1;
+
+const CTA_TUPLE_ORIG: u16 = 1;
+const CTA_TUPLE_REPLY: u16 = 2;
+const CTA_COUNTERS_ORIG: u16 = 9;
+const CTA_COUNTERS_REPLY: u16 = 10;
+const CTA_ID: u16 = 12;
+const CTA_TUPLE_IP: u16 = 1;
+const CTA_TUPLE_PROTO: u16 = 2;
+const CTA_IP_V4_SRC: u16 = 1;
+const CTA_IP_V4_DST: u16 = 2;
+const CTA_IP_V6_SRC: u16 = 3;
+const CTA_IP_V6_DST: u16 = 4;
+const CTA_PROTO_NUM: u16 = 1;
+const CTA_PROTO_SRC_PORT: u16 = 2;
+const CTA_PROTO_DST_PORT: u16 = 3;
+const CTA_COUNTERS_PACKETS: u16 = 1;
+const CTA_COUNTERS_BYTES: u16 = 2;
+
+// 连接消失后保留计数器的时间（同一个五元组可能很快被复用）
+const ENTRY_TTL: Duration = Duration::from_secs(300);
+
+/// 连接跟踪中的一个方向（五元组）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+struct Tuple {
+    src: Option<IpAddr>,
+    dst: Option<IpAddr>,
+    protocol: u8,
+    src_port: u16,
+    dst_port: u16,
+}
+
+/// 一条连接跟踪记录的累计计数
+#[derive(Debug, Clone, Default)]
+struct ConntrackEntry {
+    id: Option<u32>,
+    original: Tuple,
+    reply: Tuple,
+    orig_bytes: u64,
+    orig_packets: u64,
+    reply_bytes: u64,
+    reply_packets: u64,
+}
+
+impl ConntrackEntry {
+    /// 用于跨周期匹配同一条连接的键（ctnetlink 提供连接 ID，/proc 只能使用五元组）
+    fn key(&self) -> (Option<u32>, Tuple) {
+        (self.id, self.original)
+    }
+}
+
+/// 上一次读取到的累计计数
+struct PreviousCounters {
+    orig_bytes: u64,
+    orig_packets: u64,
+    reply_bytes: u64,
+    reply_packets: u64,
+    last_seen: Instant,
+}
+
+/// 读取连接跟踪计数的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Netlink,
+    Proc,
+}
+
+/// 基于 nf_conntrack 流量计数的监控器
+///
+/// 读取连接跟踪表中每条连接两个方向的累计字节数和包数，计算周期增量。
+/// 本地地址取 NAT 之前的原始地址，因此在 NAT 网关上可以统计到具体的局域网主机。
+pub struct ConntrackMonitor {
+    sample_interval: u32,
+    address_filter: AddressFilter,
+    local_networks: LocalNetworks,
+    host_addrs: HashSet<IpAddr>,
+    source: Source,
+    previous: HashMap<(Option<u32>, Tuple), PreviousCounters>,
+    original_acct: Option<String>,
+    last_collect: Option<Instant>,
+}
+
+impl ConntrackMonitor {
+    pub fn new(sample_interval: u32, address_filter: AddressFilter, local_networks: LocalNetworks) -> Self {
+        Self {
+            sample_interval,
+            address_filter,
+            local_networks,
+            host_addrs: HashSet::new(),
+            source: Source::Netlink,
+            previous: HashMap::new(),
+            original_acct: None,
+            last_collect: None,
+        }
+    }
+
+    /// 确保开启了 nf_conntrack_acct，否则连接跟踪不记录字节数
+    fn enable_accounting(&mut self) -> Result<(), Box<dyn Error>> {
+        let current = std::fs::read_to_string(ACCT_SYSCTL)
+            .map_err(|e| format!("读取 {} 失败（nf_conntrack 模块未加载？）: {}", ACCT_SYSCTL, e))?;
+        let current = current.trim().to_string();
+        if current != "1" {
+            std::fs::write(ACCT_SYSCTL, "1").map_err(|e| format!("开启 nf_conntrack_acct 失败: {}", e))?;
+            println!("已开启 nf_conntrack_acct（退出时恢复为 {}），之前建立的连接没有计数", current);
+            self.original_acct = Some(current);
+        }
+        Ok(())
+    }
+
+    fn dump(&self) -> Result<Vec<ConntrackEntry>, Box<dyn Error>> {
+        match self.source {
+            Source::Netlink => Self::dump_netlink(),
+            Source::Proc => Self::dump_proc(),
+        }
+    }
+
+    /// 通过 ctnetlink 导出完整的连接跟踪表（AF_UNSPEC 表示导出所有协议族）
+    fn dump_netlink() -> Result<Vec<ConntrackEntry>, Box<dyn Error>> {
+        let mut entries = Vec::new();
+        nfnetlink::dump(
+            "ctnetlink",
+            (NFNL_SUBSYS_CTNETLINK << 8) | IPCTNL_MSG_CT_GET,
+            libc::AF_UNSPEC as u8,
+            &[],
+            |attrs| entries.extend(Self::parse_netlink_entry(attrs)),
+        )?;
+        Ok(entries)
+    }
+
+    fn parse_netlink_entry(attrs: &[u8]) -> Option<ConntrackEntry> {
+        let mut entry = ConntrackEntry::default();
+        for (attr_type, value) in NlaIter::new(attrs) {
+            match attr_type {
+                CTA_TUPLE_ORIG => entry.original = Self::parse_netlink_tuple(value),
+                CTA_TUPLE_REPLY => entry.reply = Self::parse_netlink_tuple(value),
+                CTA_COUNTERS_ORIG => (entry.orig_packets, entry.orig_bytes) = Self::parse_netlink_counters(value),
+                CTA_COUNTERS_REPLY => (entry.reply_packets, entry.reply_bytes) = Self::parse_netlink_counters(value),
+                CTA_ID => entry.id = value.get(..4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]])),
+                _ => {}
+            }
+        }
+        entry.original.src.and(entry.original.dst).map(|_| entry)
+    }
+
+    fn parse_netlink_tuple(attrs: &[u8]) -> Tuple {
+        let mut tuple = Tuple::default();
+        for (attr_type, value) in NlaIter::new(attrs) {
+            match attr_type {
+                CTA_TUPLE_IP => {
+                    for (ip_type, addr) in NlaIter::new(value) {
+                        let ip = match (ip_type, addr.len()) {
+                            (CTA_IP_V4_SRC | CTA_IP_V4_DST, 4) => {
+                                IpAddr::V4(Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3]))
+                            }
+                            (CTA_IP_V6_SRC | CTA_IP_V6_DST, 16) => {
+                                let octets: [u8; 16] = addr.try_into().unwrap_or_default();
+                                IpAddr::V6(Ipv6Addr::from(octets))
+                            }
+                            _ => continue,
+                        };
+                        if matches!(ip_type, CTA_IP_V4_SRC | CTA_IP_V6_SRC) {
+                            tuple.src = Some(ip);
+                        } else {
+                            tuple.dst = Some(ip);
+                        }
+                    }
+                }
+                CTA_TUPLE_PROTO => {
+                    for (proto_type, value) in NlaIter::new(value) {
+                        match (proto_type, value.len()) {
+                            (CTA_PROTO_NUM, 1) => tuple.protocol = value[0],
+                            (CTA_PROTO_SRC_PORT, 2) => tuple.src_port = u16::from_be_bytes([value[0], value[1]]),
+                            (CTA_PROTO_DST_PORT, 2) => tuple.dst_port = u16::from_be_bytes([value[0], value[1]]),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        tuple
+    }
+
+    /// 解析计数器，返回 (包数, 字节数)
+    fn parse_netlink_counters(attrs: &[u8]) -> (u64, u64) {
+        let mut packets = 0;
+        let mut bytes = 0;
+        for (attr_type, value) in NlaIter::new(attrs) {
+            let Ok(raw) = <[u8; 8]>::try_from(value) else {
+                continue;
+            };
+            match attr_type {
+                CTA_COUNTERS_PACKETS => packets = u64::from_be_bytes(raw),
+                CTA_COUNTERS_BYTES => bytes = u64::from_be_bytes(raw),
+                _ => {}
+            }
+        }
+        (packets, bytes)
+    }
+
+    /// 读取 /proc/net/nf_conntrack（内核未提供 ctnetlink 时使用）
+    fn dump_proc() -> Result<Vec<ConntrackEntry>, Box<dyn Error>> {
+        let content = std::fs::read_to_string(PROC_CONNTRACK)
+            .map_err(|e| format!("读取 {} 失败: {}", PROC_CONNTRACK, e))?;
+        Ok(content.lines().filter_map(Self::parse_proc_line).collect())
+    }
+
+    /// 解析一行连接跟踪记录，例如：
+    /// ipv4 2 tcp 6 431999 ESTABLISHED src=192.168.1.10 dst=1.1.1.1 sport=50000 dport=443 packets=10 bytes=1200
+    ///   src=1.1.1.1 dst=203.0.113.1 sport=443 dport=50000 packets=8 bytes=5600 [ASSURED] mark=0 use=1
+    fn parse_proc_line(line: &str) -> Option<ConntrackEntry> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let protocol = fields.get(3)?.parse::<u8>().ok()?;
+
+        let mut entry = ConntrackEntry::default();
+        entry.original.protocol = protocol;
+        entry.reply.protocol = protocol;
+
+        // 第一次出现的 src/dst/sport/dport/packets/bytes 属于原始方向，第二次属于应答方向
+        let mut seen: HashSet<&str> = HashSet::new();
+        for field in &fields[4..] {
+            let Some((name, value)) = field.split_once('=') else {
+                continue;
+            };
+            let is_reply = !seen.insert(name);
+            let (tuple, packets, bytes) = if is_reply {
+                (&mut entry.reply, &mut entry.reply_packets, &mut entry.reply_bytes)
+            } else {
+                (&mut entry.original, &mut entry.orig_packets, &mut entry.orig_bytes)
+            };
+            match name {
+                "src" => tuple.src = value.parse().ok(),
+                "dst" => tuple.dst = value.parse().ok(),
+                "sport" => tuple.src_port = value.parse().unwrap_or(0),
+                "dport" => tuple.dst_port = value.parse().unwrap_or(0),
+                "packets" => *packets = value.parse().unwrap_or(0),
+                "bytes" => *bytes = value.parse().unwrap_or(0),
+                _ => {}
+            }
+        }
+
+        entry.original.src.and(entry.original.dst).map(|_| entry)
+    }
+
+    fn is_local(&self, ip: &IpAddr) -> bool {
+        self.host_addrs.contains(ip) || self.local_networks.contains(ip)
+    }
+
+    /// 确定连接的本地和远程地址，返回 (本地IP, 远程IP, 原始方向是否为发送)
+    ///
+    /// 本地发起的连接：原始方向的源地址（NAT 之前）是本地地址；
+    /// 外部发起的连接（端口转发）：应答方向的源地址（DNAT 之后的内网服务器）是本地地址。
+    /// 两端都是本地地址（内网互访）或都不是（转发的过境流量）时丢弃。
+    fn classify(&self, entry: &ConntrackEntry) -> Option<(IpAddr, IpAddr, bool)> {
+        let orig_src = entry.original.src?;
+        let orig_dst = entry.original.dst?;
+        match (self.is_local(&orig_src), self.is_local(&orig_dst)) {
+            (true, false) => Some((orig_src, orig_dst, true)),
+            (false, true) => Some((entry.reply.src.unwrap_or(orig_dst), orig_src, false)),
+            _ => None,
+        }
+    }
+
+    /// 根据本次读取的累计计数计算增量
+    fn compute_deltas(&mut self, entries: Vec<ConntrackEntry>) -> HashMap<FlowKey, TrafficStats> {
+        let now = Instant::now();
+        let mut stats: HashMap<FlowKey, TrafficStats> = HashMap::new();
+
+        for entry in entries {
+            let current = PreviousCounters {
+                orig_bytes: entry.orig_bytes,
+                orig_packets: entry.orig_packets,
+                reply_bytes: entry.reply_bytes,
+                reply_packets: entry.reply_packets,
+                last_seen: now,
+            };
+
+            // 计数减少说明五元组被新连接复用，新计数全部计入增量
+            let delta = |current: u64, previous: Option<u64>| match previous {
+                Some(prev) if current >= prev => current - prev,
+                _ => current,
+            };
+            let previous = self.previous.get(&entry.key());
+            let orig_bytes = delta(current.orig_bytes, previous.map(|p| p.orig_bytes));
+            let orig_packets = delta(current.orig_packets, previous.map(|p| p.orig_packets));
+            let reply_bytes = delta(current.reply_bytes, previous.map(|p| p.reply_bytes));
+            let reply_packets = delta(current.reply_packets, previous.map(|p| p.reply_packets));
+            self.previous.insert(entry.key(), current);
+
+            if orig_bytes == 0 && reply_bytes == 0 {
+                continue;
+            }
+            let Some((local_ip, remote_ip, orig_is_tx)) = self.classify(&entry) else {
+                continue;
+            };
+            if !self.address_filter.is_allowed(&remote_ip) {
+                continue;
+            }
+
+            let stat = stats
+                .entry(FlowKey::new(ANY_INTERFACE, local_ip.to_string(), remote_ip.to_string()))
+                .or_default();
+            if orig_is_tx {
+                stat.tx_bytes += orig_bytes;
+                stat.tx_packets += orig_packets;
+                stat.rx_bytes += reply_bytes;
+                stat.rx_packets += reply_packets;
+            } else {
+                stat.tx_bytes += reply_bytes;
+                stat.tx_packets += reply_packets;
+                stat.rx_bytes += orig_bytes;
+                stat.rx_packets += orig_packets;
+            }
+        }
+
+        self.previous.retain(|_, p| now.duration_since(p.last_seen) < ENTRY_TTL);
+        stats
+    }
+}
+
+impl TrafficMonitor for ConntrackMonitor {
+    fn init(&mut self) -> Result<(), Box<dyn Error>> {
+        self.enable_accounting()?;
+        self.host_addrs = host_addresses();
+
+        // 优先使用 ctnetlink，失败时回退到 /proc/net/nf_conntrack
+        let entries = match Self::dump_netlink() {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("[警告] ctnetlink 不可用（{}），改为读取 {}", e, PROC_CONNTRACK);
+                self.source = Source::Proc;
+                Self::dump_proc()?
+            }
+        };
+
+        // 以当前计数作为基准，已有连接的历史流量不计入第一个周期
+        let count = entries.len();
+        self.compute_deltas(entries);
+        println!(
+            "conntrack 监控器初始化成功（{}），当前 {} 条连接",
+            if self.source == Source::Netlink { "ctnetlink" } else { PROC_CONNTRACK },
+            count
+        );
+        self.last_collect = Some(Instant::now());
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<HashMap<FlowKey, TrafficStats>, Box<dyn Error>> {
+        // 等待到本采样周期结束
+        let interval = Duration::from_secs(self.sample_interval as u64);
+        if let Some(last) = self.last_collect {
+            let elapsed = last.elapsed();
+            if elapsed < interval {
+                thread::sleep(interval - elapsed);
+            }
+        }
+        self.last_collect = Some(Instant::now());
+
+        let entries = self.dump()?;
+        Ok(self.compute_deltas(entries))
+    }
+
+    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(original) = self.original_acct.take() {
+            if let Err(e) = std::fs::write(ACCT_SYSCTL, &original) {
+                eprintln!("[警告] 恢复 nf_conntrack_acct 失败: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "conntrack"
+    }
+}
@@ -0,0 +1,311 @@
+use crate::address_filter::Cidr;
+use crate::nft;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+const TABLE_FAMILY: &str = "inet";
+const TABLE_NAME: &str = "ip_traffic_enforce";
+
+// 限速计量集合的容量和空闲元素的过期时间
+const METER_SIZE: u32 = 65535;
+const METER_TIMEOUT: &str = "1m";
+
+/// 对违规 IP 采取的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnforceAction {
+    /// 丢弃全部流量
+    Drop,
+    /// 按每个 IP 限速，超出部分丢弃
+    Limit,
+}
+
+impl FromStr for EnforceAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "drop" => Ok(Self::Drop),
+            "limit" => Ok(Self::Limit),
+            _ => Err(format!("不支持的处置动作: {}，可选值为 drop、limit", s)),
+        }
+    }
+}
+
+/// 触发处置的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockReason {
+    /// IP 或网段配额用尽
+    Quota,
+    /// 异常检测发现流量突增
+    Anomaly,
+}
+
+impl FromStr for BlockReason {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "quota" => Ok(Self::Quota),
+            "anomaly" => Ok(Self::Anomaly),
+            _ => Err(format!("不支持的处置触发条件: {}，可选值为 quota、anomaly", s)),
+        }
+    }
+}
+
+/// 一条生效中的处置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Block {
+    pub target: String,
+    pub reason: BlockReason,
+    pub detail: String,
+    pub created: i64,
+    pub expires: i64,
+}
+
+/// 通过 nftables 集合封禁或限速违规 IP
+///
+/// 安装一个独立的 nftables 表，input / output / forward 钩子上匹配 block_v4 / block_v6 集合中的远程地址；
+/// 集合元素带超时，到期后由内核自动删除。生效中的处置同时保存在内存和状态文件中，
+/// 每个周期清理到期的记录，重启后恢复未到期的处置；shutdown() 时删除整张表。
+pub struct Enforcer {
+    action: EnforceAction,
+    rate_bytes: u64,
+    duration: Duration,
+    reasons: Vec<BlockReason>,
+    exempt: Vec<Cidr>,
+    state_path: Option<String>,
+    blocks: BTreeMap<String, Block>,
+    table_installed: bool,
+}
+
+impl Enforcer {
+    pub fn new(
+        action: EnforceAction,
+        rate_bytes: u64,
+        duration: Duration,
+        reasons: Vec<BlockReason>,
+        exempt: Vec<Cidr>,
+        state_path: Option<String>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let version = nft::version()?;
+
+        // 先读取并校验状态文件再安装处置表，状态文件损坏时不会留下已安装的表
+        let now = Local::now().timestamp();
+        let mut blocks = BTreeMap::new();
+        let restore_path = state_path.clone().filter(|p| Path::new(p).exists());
+        if let Some(path) = &restore_path {
+            let content = fs::read_to_string(path).map_err(|e| format!("无法读取处置状态文件 {}: {}", path, e))?;
+            let saved: Vec<Block> =
+                serde_json::from_str(&content).map_err(|e| format!("处置状态文件 {} 格式错误: {}", path, e))?;
+            for block in saved.into_iter().filter(|b| b.expires > now) {
+                block
+                    .target
+                    .parse::<Cidr>()
+                    .map_err(|e| format!("处置状态文件 {} 中的目标 {} 无效: {}", path, block.target, e))?;
+                blocks.insert(block.target.clone(), block);
+            }
+        }
+
+        let mut enforcer = Self {
+            action,
+            rate_bytes,
+            duration,
+            reasons,
+            exempt,
+            state_path,
+            blocks,
+            table_installed: false,
+        };
+        // 未到期的处置与规则一起原子安装，任何一条失败时整张表都不会生效
+        let mut script = enforcer.generate_ruleset();
+        for block in enforcer.blocks.values() {
+            let cidr: Cidr = block.target.parse()?;
+            script.push_str(&element_script("add", &cidr, block.expires - now));
+        }
+        nft::run_script(&script)?;
+        enforcer.table_installed = true;
+        println!("nftables 处置表已安装: {}（表 {} {}）", version, TABLE_FAMILY, TABLE_NAME);
+
+        if restore_path.is_some() {
+            if let Err(e) = enforcer.save() {
+                // 初始化失败时调用方拿不到 Enforcer，这里删除已安装的表
+                if let Err(cleanup) = enforcer.shutdown() {
+                    eprintln!("[警告] 删除 nftables 表 {} {} 失败: {}", TABLE_FAMILY, TABLE_NAME, cleanup);
+                }
+                return Err(e);
+            }
+        }
+        Ok(enforcer)
+    }
+
+    pub fn action(&self) -> EnforceAction {
+        self.action
+    }
+
+    /// 是否对该原因采取处置
+    pub fn handles(&self, reason: BlockReason) -> bool {
+        self.reasons.contains(&reason)
+    }
+
+    /// 生成处置表的规则脚本（先删除同名旧表，整体原子替换）
+    fn generate_ruleset(&self) -> String {
+        // 命中集合的流量：drop 模式直接丢弃；limit 模式按远程地址分别计量，超出速率的部分丢弃
+        let verdict = |family: &str, addr: &str, meter: &str| match self.action {
+            EnforceAction::Drop => "drop".to_string(),
+            EnforceAction::Limit => format!(
+                "update @{meter} {{ {family} {addr} limit rate over {} bytes/second }} drop",
+                self.rate_bytes
+            ),
+        };
+        let rules = |chain: &str, hook: &str, directions: &[(&str, &str)]| {
+            let mut chain_rules = format!("    chain {chain} {{\n        type filter hook {hook} priority -10; policy accept;\n");
+            for (addr, meter) in directions {
+                for (family, version) in [("ip", "v4"), ("ip6", "v6")] {
+                    chain_rules.push_str(&format!(
+                        "        {family} {addr} @block_{version} {}\n",
+                        verdict(family, addr, &format!("{meter}_{version}"))
+                    ));
+                }
+            }
+            chain_rules.push_str("    }\n");
+            chain_rules
+        };
+
+        let mut script = format!("table {TABLE_FAMILY} {TABLE_NAME}\ndelete table {TABLE_FAMILY} {TABLE_NAME}\n");
+        script.push_str(&format!("table {TABLE_FAMILY} {TABLE_NAME} {{\n"));
+        for (version, addr_type) in [("v4", "ipv4_addr"), ("v6", "ipv6_addr")] {
+            script.push_str(&format!("    set block_{version} {{ type {addr_type}; flags interval,timeout; }}\n"));
+            if self.action == EnforceAction::Limit {
+                for meter in ["rx", "tx"] {
+                    script.push_str(&format!(
+                        "    set {meter}_{version} {{ type {addr_type}; size {METER_SIZE}; flags dynamic,timeout; timeout {METER_TIMEOUT}; }}\n"
+                    ));
+                }
+            }
+        }
+        script.push_str(&rules("input", "input", &[("saddr", "rx")]));
+        script.push_str(&rules("output", "output", &[("daddr", "tx")]));
+        script.push_str(&rules("forward", "forward", &[("saddr", "rx"), ("daddr", "tx")]));
+        script.push_str("}\n");
+        script
+    }
+
+    /// 处置一个 IP 或网段；已被生效中的处置覆盖或与豁免网段重叠时返回 Ok(None)
+    ///
+    /// 集合带 interval 标志，不能包含重叠的元素：新网段覆盖的已有处置会先被删除，由新网段替代。
+    pub fn block(&mut self, target: &Cidr, reason: BlockReason, detail: String) -> Result<Option<Block>, Box<dyn Error>> {
+        let key = target.to_string();
+        if self.exempt.iter().any(|net| net.overlaps(target)) {
+            return Ok(None);
+        }
+        let mut covered = Vec::new();
+        for existing in self.blocks.keys() {
+            let cidr: Cidr = existing.parse()?;
+            if cidr.covers(target) {
+                return Ok(None);
+            }
+            if target.covers(&cidr) {
+                covered.push(cidr);
+            }
+        }
+
+        let seconds = self.duration.as_secs().max(1) as i64;
+        // 与 expire() 相同，先重新声明被覆盖的元素再删除，避免内核已按超时删除时执行失败
+        let mut script = String::new();
+        for cidr in &covered {
+            script.push_str(&element_script("add", cidr, 1));
+            script.push_str(&element_script("delete", cidr, 0));
+        }
+        script.push_str(&element_script("add", target, seconds));
+        nft::run_script(&script)?;
+        for cidr in &covered {
+            self.blocks.remove(&cidr.to_string());
+        }
+
+        let now = Local::now().timestamp();
+        let block = Block {
+            target: key.clone(),
+            reason,
+            detail,
+            created: now,
+            expires: now + seconds,
+        };
+        self.blocks.insert(key, block.clone());
+        self.save()?;
+        Ok(Some(block))
+    }
+
+    /// 删除到期的处置，返回被删除的记录
+    pub fn expire(&mut self) -> Result<Vec<Block>, Box<dyn Error>> {
+        let now = Local::now().timestamp();
+        let expired: Vec<String> = self
+            .blocks
+            .values()
+            .filter(|b| b.expires <= now)
+            .map(|b| b.target.clone())
+            .collect();
+        if expired.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut removed = Vec::new();
+        for target in expired {
+            let Some(block) = self.blocks.remove(&target) else {
+                continue;
+            };
+            // 内核可能已经按超时删除了元素，先重新声明再删除，保证元素不存在时也能执行成功
+            let cidr: Cidr = block.target.parse()?;
+            let script = element_script("add", &cidr, 1) + &element_script("delete", &cidr, 0);
+            if let Err(e) = nft::run_script(&script) {
+                eprintln!("[警告] 删除处置 {} 失败: {}", block.target, e);
+            }
+            removed.push(block);
+        }
+        self.save()?;
+        Ok(removed)
+    }
+
+    /// 生效中的处置
+    pub fn blocks(&self) -> Vec<Block> {
+        self.blocks.values().cloned().collect()
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        let Some(path) = self.state_path.as_deref() else {
+            return Ok(());
+        };
+        let tmp_path = format!("{}.tmp", path);
+        fs::write(&tmp_path, serde_json::to_vec(&self.blocks())?)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// 删除处置表（解除全部处置，状态文件保留，下次启动时恢复未到期的记录）
+    pub fn shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.table_installed {
+            nft::delete_table(TABLE_FAMILY, TABLE_NAME)?;
+            self.table_installed = false;
+            println!("已删除 nftables 表 {} {}", TABLE_FAMILY, TABLE_NAME);
+        }
+        Ok(())
+    }
+}
+
+/// 生成添加或删除集合元素的脚本；添加时带超时（秒）
+fn element_script(verb: &str, target: &Cidr, timeout_secs: i64) -> String {
+    let set = match target.addr() {
+        IpAddr::V4(_) => "block_v4",
+        IpAddr::V6(_) => "block_v6",
+    };
+    let timeout = if verb == "add" { format!(" timeout {}s", timeout_secs.max(1)) } else { String::new() };
+    format!("{verb} element {TABLE_FAMILY} {TABLE_NAME} {set} {{ {target}{timeout} }}\n")
+}
@@ -0,0 +1,225 @@
+use crate::address_filter::{AddressFilter, LocalNetworks};
+use crate::monitor::{FlowKey, TrafficMonitor, TrafficStats};
+use std::collections::HashMap;
+use std::error::Error;
+use std::net::{IpAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+type StatsReceiver = Receiver<HashMap<FlowKey, TrafficStats>>;
+
+// 接收线程向主线程发送累计数据的间隔
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 流量采集报文中解码出的一条流记录（已按采样率还原）
+#[derive(Debug, Clone)]
+pub struct FlowRecord {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub bytes: u64,
+    pub packets: u64,
+}
+
+/// 流量采集报文解码器（NetFlow / IPFIX / sFlow）
+pub trait DatagramDecoder: Send + Sync {
+    /// 解码一个 UDP 报文，exporter 为发送报文的设备地址
+    fn decode(&mut self, exporter: IpAddr, data: &[u8]) -> Result<Vec<FlowRecord>, String>;
+
+    /// 解码结果是否为按采样率估算的值
+    fn is_sampled(&self) -> bool {
+        false
+    }
+}
+
+/// 接收路由器 / 交换机导出的流量记录的监控器
+///
+/// 在 UDP 端口上接收报文，交给解码器还原为流记录，再按本地地址规则判断方向。
+/// 网卡维度使用导出设备的地址。
+pub struct CollectorMonitor {
+    name: &'static str,
+    listen: String,
+    sample_interval: u32,
+    address_filter: AddressFilter,
+    local_networks: LocalNetworks,
+    decoder: Option<Box<dyn DatagramDecoder>>,
+    sampled: bool,
+    running: Arc<AtomicBool>,
+    receive_thread: Option<thread::JoinHandle<()>>,
+    stats_receiver: Option<Arc<Mutex<StatsReceiver>>>,
+    last_collect: Option<Instant>,
+}
+
+impl CollectorMonitor {
+    pub fn new(
+        name: &'static str,
+        listen: String,
+        sample_interval: u32,
+        address_filter: AddressFilter,
+        local_networks: LocalNetworks,
+        decoder: Box<dyn DatagramDecoder>,
+    ) -> Self {
+        Self {
+            name,
+            listen,
+            sample_interval,
+            address_filter,
+            local_networks,
+            sampled: decoder.is_sampled(),
+            decoder: Some(decoder),
+            running: Arc::new(AtomicBool::new(false)),
+            receive_thread: None,
+            stats_receiver: None,
+            last_collect: None,
+        }
+    }
+
+    /// 判断流记录方向，返回 (本地IP, 远程IP, 是否为发送)；两端都是或都不是本地地址时丢弃
+    fn classify(local_networks: &LocalNetworks, record: &FlowRecord) -> Option<(IpAddr, IpAddr, bool)> {
+        match (local_networks.contains(&record.src), local_networks.contains(&record.dst)) {
+            (true, false) => Some((record.src, record.dst, true)),
+            (false, true) => Some((record.dst, record.src, false)),
+            _ => None,
+        }
+    }
+
+    /// 接收循环（在后台线程中运行）
+    fn receive_loop(
+        socket: UdpSocket,
+        mut decoder: Box<dyn DatagramDecoder>,
+        address_filter: AddressFilter,
+        local_networks: LocalNetworks,
+        running: Arc<AtomicBool>,
+        tx: Sender<HashMap<FlowKey, TrafficStats>>,
+    ) {
+        let mut buf = vec![0u8; 65535];
+        let mut pending: HashMap<FlowKey, TrafficStats> = HashMap::new();
+        let mut last_flush = Instant::now();
+        let mut decode_errors = 0u64;
+        let mut last_error = String::new();
+
+        while running.load(Ordering::SeqCst) {
+            match socket.recv_from(&mut buf) {
+                Ok((len, peer)) => match decoder.decode(peer.ip(), &buf[..len]) {
+                    Ok(records) => {
+                        let exporter = peer.ip().to_string();
+                        for record in records {
+                            let Some((local_ip, remote_ip, is_tx)) = Self::classify(&local_networks, &record) else {
+                                continue;
+                            };
+                            if !address_filter.is_allowed(&remote_ip) {
+                                continue;
+                            }
+                            let entry = pending
+                                .entry(FlowKey::new(exporter.as_str(), local_ip.to_string(), remote_ip.to_string()))
+                                .or_default();
+                            if is_tx {
+                                entry.tx_bytes = entry.tx_bytes.saturating_add(record.bytes);
+                                entry.tx_packets = entry.tx_packets.saturating_add(record.packets);
+                            } else {
+                                entry.rx_bytes = entry.rx_bytes.saturating_add(record.bytes);
+                                entry.rx_packets = entry.rx_packets.saturating_add(record.packets);
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        decode_errors += 1;
+                        last_error = format!("{}（来自 {}）", e, peer);
+                    }
+                },
+                Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {}
+                Err(e) => {
+                    eprintln!("[错误] 接收流量记录失败: {}", e);
+                    break;
+                }
+            }
+
+            if last_flush.elapsed() >= FLUSH_INTERVAL {
+                if decode_errors > 0 {
+                    eprintln!("[警告] {} 个报文解码失败，最近一次: {}", decode_errors, last_error);
+                    decode_errors = 0;
+                }
+                if !pending.is_empty() && tx.send(std::mem::take(&mut pending)).is_err() {
+                    break;
+                }
+                last_flush = Instant::now();
+            }
+        }
+    }
+}
+
+impl TrafficMonitor for CollectorMonitor {
+    fn init(&mut self) -> Result<(), Box<dyn Error>> {
+        let socket = UdpSocket::bind(&self.listen).map_err(|e| format!("监听 {} 失败: {}", self.listen, e))?;
+        socket.set_read_timeout(Some(Duration::from_secs(1)))?;
+        let decoder = self.decoder.take().ok_or("监控器已经初始化")?;
+
+        let (tx, rx) = mpsc::channel();
+        self.stats_receiver = Some(Arc::new(Mutex::new(rx)));
+        self.running.store(true, Ordering::SeqCst);
+
+        let address_filter = self.address_filter.clone();
+        let local_networks = self.local_networks.clone();
+        let running = Arc::clone(&self.running);
+        self.receive_thread = Some(thread::spawn(move || {
+            Self::receive_loop(socket, decoder, address_filter, local_networks, running, tx);
+        }));
+
+        println!("{} 采集器初始化成功，监听 UDP {}", self.name, self.listen);
+        self.last_collect = Some(Instant::now());
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<HashMap<FlowKey, TrafficStats>, Box<dyn Error>> {
+        // 等待到本采样周期结束
+        let interval = Duration::from_secs(self.sample_interval as u64);
+        if let Some(last) = self.last_collect {
+            let elapsed = last.elapsed();
+            if elapsed < interval {
+                thread::sleep(interval - elapsed);
+            }
+        }
+        self.last_collect = Some(Instant::now());
+
+        let receiver = self
+            .stats_receiver
+            .as_ref()
+            .ok_or("stats_receiver 未初始化")?;
+
+        // 汇总本周期内收到的全部数据
+        let mut stats: HashMap<FlowKey, TrafficStats> = HashMap::new();
+        let recv_guard = receiver.lock().unwrap();
+        while let Ok(batch) = recv_guard.try_recv() {
+            for (key, delta) in batch {
+                stats.entry(key).or_default().add(&delta);
+            }
+        }
+
+        Ok(stats)
+    }
+
+    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        self.running.store(false, Ordering::SeqCst);
+
+        // 接收线程最多 1 秒（读取超时）后退出
+        if let Some(handle) = self.receive_thread.take() {
+            let _ = handle.join();
+        }
+
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn is_local(&self) -> bool {
+        false
+    }
+
+    fn is_sampled(&self) -> bool {
+        self.sampled
+    }
+}
@@ -1,185 +1,343 @@
-use crate::monitor::{TrafficMonitor, TrafficStats};
-use std::collections::HashMap;
+use crate::address_filter::AddressFilter;
+use crate::monitor::{FlowKey, InterfaceSelector, TrafficMonitor, TrafficStats};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::io::{BufRead, BufReader};
-use std::net::Ipv4Addr;
-use std::process::{Command, Stdio};
-use std::str::FromStr;
+use std::net::IpAddr;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+type StatsReceiver = Receiver<HashMap<FlowKey, TrafficStats>>;
+
+// iftop 文本模式每次最多输出的连接数（默认只有 10 条）
+const IFTOP_MAX_LINES: &str = "1000";
+
+// 连接在 iftop 输出中消失多久后丢弃其累计值
+const CUMULATIVE_EXPIRY: Duration = Duration::from_secs(300);
+
+/// 单个连接上一次看到的累计字节数
+struct CumulativeEntry {
+    tx_bytes: u64,
+    rx_bytes: u64,
+    last_seen: Instant,
+}
 
 /// 基于 iftop 的流量监控器
+///
+/// 每个网卡保持一个文本模式的 iftop 进程持续运行，后台线程读取其周期性输出，
+/// 根据累计列（cumulative）计算字节增量后通过通道发送给主线程。
 pub struct IftopMonitor {
-    interface: String,
+    interfaces: InterfaceSelector,
     sample_interval: u32,
-    local_ip: Option<String>,
+    address_filter: AddressFilter,
+    running: Arc<AtomicBool>,
+    child_processes: Vec<Child>,
+    output_threads: Vec<thread::JoinHandle<()>>,
+    stats_receiver: Option<Arc<Mutex<StatsReceiver>>>,
+    last_collect: Option<Instant>,
 }
 
 impl IftopMonitor {
-    pub fn new(interface: String, sample_interval: u32) -> Self {
+    pub fn new(interfaces: InterfaceSelector, sample_interval: u32, address_filter: AddressFilter) -> Self {
         Self {
-            interface,
+            interfaces,
             sample_interval,
-            local_ip: None,
+            address_filter,
+            running: Arc::new(AtomicBool::new(false)),
+            child_processes: Vec::new(),
+            output_threads: Vec::new(),
+            stats_receiver: None,
+            last_collect: None,
         }
     }
 
-    /// 获取本地IP地址
-    fn get_local_ip(&self) -> Result<String, Box<dyn Error>> {
+    /// 获取网卡上的全部本地IP地址（包括辅助地址和 IPv6 地址）
+    fn get_local_ips(interface: &str) -> Result<HashSet<IpAddr>, Box<dyn Error>> {
         let output = Command::new("ip")
-            .args(&["addr", "show", &self.interface])
+            .args(["addr", "show", interface])
             .output()?;
 
         let output_str = String::from_utf8_lossy(&output.stdout);
-        for line in output_str.lines() {
-            if line.trim().starts_with("inet ") && !line.contains("127.0.0.1") {
-                let parts: Vec<&str> = line.trim().split_whitespace().collect();
-                if let Some(ip_with_mask) = parts.get(1) {
-                    if let Some(ip) = ip_with_mask.split('/').next() {
-                        return Ok(ip.to_string());
-                    }
-                }
-            }
+        let local_ips: HashSet<IpAddr> = output_str
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("inet ") || line.starts_with("inet6 "))
+            .filter_map(|line| line.split_whitespace().nth(1))
+            .filter_map(|ip_with_mask| ip_with_mask.split('/').next()?.parse().ok())
+            .collect();
+
+        if local_ips.is_empty() {
+            return Err(format!("无法获取网卡 {} 的IP地址", interface).into());
         }
-        Err(format!("无法获取网卡 {} 的IP地址", self.interface).into())
+        Ok(local_ips)
     }
 
-    /// 解析速率字符串为每秒字节数
-    fn parse_rate_to_bytes_per_sec(rate_str: &str) -> Option<f64> {
-        let rate_str = rate_str.trim();
-        if rate_str.is_empty() || rate_str == "0" {
-            return Some(0.0);
+    /// 解析 iftop 累计列（如 316B、1.14KB、2.5MB）为字节数
+    fn parse_size_to_bytes(size_str: &str) -> Option<u64> {
+        let size_str = size_str.trim();
+        if size_str.is_empty() || size_str == "0" {
+            return Some(0);
         }
 
-        let (number_part, unit) = if rate_str.ends_with("Kb") {
-            (rate_str.trim_end_matches("Kb"), 1024.0 / 8.0)
-        } else if rate_str.ends_with("Mb") {
-            (rate_str.trim_end_matches("Mb"), 1024.0 * 1024.0 / 8.0)
-        } else if rate_str.ends_with("Gb") {
-            (rate_str.trim_end_matches("Gb"), 1024.0 * 1024.0 * 1024.0 / 8.0)
-        } else if rate_str.ends_with("b") {
-            (rate_str.trim_end_matches("b"), 1.0 / 8.0)
-        } else if rate_str.ends_with("B") {
-            (rate_str.trim_end_matches("B"), 1.0)
+        let (number_part, unit) = if let Some(n) = size_str.strip_suffix("KB") {
+            (n, 1024.0)
+        } else if let Some(n) = size_str.strip_suffix("MB") {
+            (n, 1024.0 * 1024.0)
+        } else if let Some(n) = size_str.strip_suffix("GB") {
+            (n, 1024.0 * 1024.0 * 1024.0)
+        } else if let Some(n) = size_str.strip_suffix("TB") {
+            (n, 1024.0 * 1024.0 * 1024.0 * 1024.0)
+        } else if let Some(n) = size_str.strip_suffix('B') {
+            (n, 1.0)
         } else {
-            (rate_str, 1.0 / 8.0)
+            (size_str, 1.0)
         };
 
-        number_part.parse::<f64>().ok().map(|n| n * unit)
+        number_part.parse::<f64>().ok().map(|n| (n * unit) as u64)
     }
 
-    /// 解析 iftop 输出
-    fn parse_iftop_output(&self, output: &str) -> HashMap<String, TrafficStats> {
-        let mut stats_map = HashMap::new();
-        let local_ip = match &self.local_ip {
-            Some(ip) => ip,
-            None => return stats_map,
-        };
+    /// 从 iftop 输出行中提取主机地址和累计列（最后一列）
+    fn parse_host_line<'a>(line: &'a str, arrow: &str) -> Option<(&'a str, &'a str)> {
+        let (left_part, right_part) = line.split_once(arrow)?;
+        let host = left_part.split_whitespace().last()?;
+        let columns: Vec<&str> = right_part.split_whitespace().collect();
+        if columns.len() < 4 {
+            return None;
+        }
+        Some((host, columns[3]))
+    }
 
-        let lines: Vec<&str> = output.lines().collect();
-        let mut i = 0;
-
-        while i < lines.len() {
-            let line = lines[i].trim();
-
-            if line.contains("=>") && line.contains(local_ip) {
-                let parts: Vec<&str> = line.split("=>").collect();
-                if parts.len() == 2 {
-                    let right_part = parts[1].trim();
-                    let rate_tokens: Vec<&str> = right_part.split_whitespace().collect();
-
-                    if rate_tokens.len() >= 4 {
-                        let tx_rate_str = rate_tokens[0];
-
-                        if let Some(tx_rate) = Self::parse_rate_to_bytes_per_sec(tx_rate_str) {
-                            if i + 1 < lines.len() {
-                                let next_line = lines[i + 1].trim();
-                                if next_line.contains("<=") {
-                                    let rx_parts: Vec<&str> = next_line.split("<=").collect();
-                                    if rx_parts.len() == 2 {
-                                        let left_part = rx_parts[0].trim();
-                                        let right_part = rx_parts[1].trim();
-
-                                        let ip_tokens: Vec<&str> = left_part.split_whitespace().collect();
-                                        if let Some(&remote_ip) = ip_tokens.last() {
-                                            if Ipv4Addr::from_str(remote_ip).is_ok() {
-                                                let rx_rate_tokens: Vec<&str> =
-                                                    right_part.split_whitespace().collect();
-                                                let rx_rate = if !rx_rate_tokens.is_empty() {
-                                                    Self::parse_rate_to_bytes_per_sec(rx_rate_tokens[0])
-                                                        .unwrap_or(0.0)
-                                                } else {
-                                                    0.0
-                                                };
-
-                                                let tx_bytes =
-                                                    (tx_rate * self.sample_interval as f64) as u64;
-                                                let rx_bytes =
-                                                    (rx_rate * self.sample_interval as f64) as u64;
-
-                                                stats_map.insert(
-                                                    remote_ip.to_string(),
-                                                    TrafficStats {
-                                                        tx_bytes,
-                                                        rx_bytes,
-                                                        tx_packets: 0, // iftop 不提供包数
-                                                        rx_packets: 0,
-                                                    },
-                                                );
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+    /// 解析一次 iftop 输出，返回 (本地IP, 远程IP) -> (累计发送字节, 累计接收字节)
+    ///
+    /// iftop 每个连接输出两行：`host_a => ...`（a 发往 b）和 `host_b <= ...`（b 发往 a）。
+    /// 本地地址可能出现在任意一侧（辅助地址 iftop 无法识别方向），两侧都不是本地地址的连接忽略。
+    fn parse_iftop_block(
+        lines: &[String],
+        local_ips: &HashSet<IpAddr>,
+        address_filter: &AddressFilter,
+    ) -> HashMap<(String, String), (u64, u64)> {
+        let mut cumulative = HashMap::new();
+
+        for pair in lines.windows(2) {
+            let Some((left_host, left_to_right)) = Self::parse_host_line(&pair[0], "=>") else {
+                continue;
+            };
+            let Some((right_host, right_to_left)) = Self::parse_host_line(&pair[1], "<=") else {
+                continue;
+            };
+            let (Ok(left_ip), Ok(right_ip)) = (left_host.parse::<IpAddr>(), right_host.parse::<IpAddr>()) else {
+                continue;
+            };
+
+            let (local_ip, remote_ip, tx_str, rx_str) = if local_ips.contains(&left_ip) {
+                (left_ip, right_ip, left_to_right, right_to_left)
+            } else if local_ips.contains(&right_ip) {
+                (right_ip, left_ip, right_to_left, left_to_right)
+            } else {
+                continue;
+            };
+
+            if !address_filter.is_allowed(&remote_ip) {
+                continue;
             }
-            i += 1;
+
+            let tx_bytes = Self::parse_size_to_bytes(tx_str).unwrap_or(0);
+            let rx_bytes = Self::parse_size_to_bytes(rx_str).unwrap_or(0);
+
+            let entry: &mut (u64, u64) = cumulative
+                .entry((local_ip.to_string(), remote_ip.to_string()))
+                .or_default();
+            entry.0 += tx_bytes;
+            entry.1 += rx_bytes;
         }
 
-        stats_map
+        cumulative
     }
-}
 
-impl TrafficMonitor for IftopMonitor {
-    fn init(&mut self) -> Result<(), Box<dyn Error>> {
-        self.local_ip = Some(self.get_local_ip()?);
-        println!("iftop 监控器初始化成功，本地IP: {}", self.local_ip.as_ref().unwrap());
-        Ok(())
+    /// 根据本次累计值和上次累计值计算增量（累计值变小说明 iftop 已重新开始统计该连接）
+    fn compute_deltas(
+        interface: &str,
+        cumulative: HashMap<(String, String), (u64, u64)>,
+        previous: &mut HashMap<(String, String), CumulativeEntry>,
+    ) -> HashMap<FlowKey, TrafficStats> {
+        let now = Instant::now();
+        let mut deltas = HashMap::new();
+
+        for ((local_ip, remote_ip), (tx_total, rx_total)) in cumulative {
+            let (tx_delta, rx_delta) = match previous.get(&(local_ip.clone(), remote_ip.clone())) {
+                Some(prev) => (
+                    tx_total.checked_sub(prev.tx_bytes).unwrap_or(tx_total),
+                    rx_total.checked_sub(prev.rx_bytes).unwrap_or(rx_total),
+                ),
+                None => (tx_total, rx_total),
+            };
+
+            if tx_delta > 0 || rx_delta > 0 {
+                deltas.insert(
+                    FlowKey::new(interface, local_ip.as_str(), remote_ip.as_str()),
+                    TrafficStats {
+                        tx_bytes: tx_delta,
+                        rx_bytes: rx_delta,
+                        tx_packets: 0, // iftop 不提供包数
+                        rx_packets: 0,
+                    },
+                );
+            }
+
+            previous.insert(
+                (local_ip, remote_ip),
+                CumulativeEntry {
+                    tx_bytes: tx_total,
+                    rx_bytes: rx_total,
+                    last_seen: now,
+                },
+            );
+        }
+
+        previous.retain(|_, entry| now.duration_since(entry.last_seen) < CUMULATIVE_EXPIRY);
+
+        deltas
     }
 
-    fn start(&mut self) -> Result<HashMap<String, TrafficStats>, Box<dyn Error>> {
-        let mut child = Command::new("iftop")
-            .args(&[
+    /// 为单个网卡启动 iftop 进程和读取线程
+    fn spawn_iftop(
+        &mut self,
+        interface: String,
+        local_ips: HashSet<IpAddr>,
+        tx: Sender<HashMap<FlowKey, TrafficStats>>,
+    ) -> Result<(), Box<dyn Error>> {
+        // 文本模式不带 -s 时 iftop 会持续周期性输出，stdbuf 改为行缓冲以便及时读取
+        let mut child = Command::new("stdbuf")
+            .args([
+                "-oL",
+                "iftop",
                 "-i",
-                &self.interface,
+                &interface,
                 "-t",
-                "-s",
-                &self.sample_interval.to_string(),
                 "-n",
                 "-N",
+                "-L",
+                IFTOP_MAX_LINES,
             ])
+            .stdin(Stdio::null())
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
+            .stderr(Stdio::null())
             .spawn()?;
 
-        let mut output = String::new();
-        if let Some(stdout) = child.stdout.take() {
+        let stdout = child.stdout.take().ok_or("无法获取 iftop stdout")?;
+        let running = Arc::clone(&self.running);
+        let address_filter = self.address_filter.clone();
+
+        // 启动后台线程持续读取 iftop 输出
+        let output_thread = thread::spawn(move || {
             let reader = BufReader::new(stdout);
+            let mut block: Vec<String> = Vec::new();
+            let mut previous: HashMap<(String, String), CumulativeEntry> = HashMap::new();
+
             for line in reader.lines() {
-                if let Ok(line) = line {
-                    output.push_str(&line);
-                    output.push('\n');
+                if !running.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                match line {
+                    Ok(line) => {
+                        // 每次输出以一行 "=" 结束
+                        if line.starts_with("====") {
+                            let cumulative = Self::parse_iftop_block(&block, &local_ips, &address_filter);
+                            let deltas = Self::compute_deltas(&interface, cumulative, &mut previous);
+                            if !deltas.is_empty() && tx.send(deltas).is_err() {
+                                break;
+                            }
+                            block.clear();
+                        } else {
+                            block.push(line.trim().to_string());
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("[错误] 读取 iftop 输出失败: {}", e);
+                        break;
+                    }
                 }
             }
+        });
+
+        self.child_processes.push(child);
+        self.output_threads.push(output_thread);
+        Ok(())
+    }
+}
+
+impl TrafficMonitor for IftopMonitor {
+    fn init(&mut self) -> Result<(), Box<dyn Error>> {
+        let (tx, rx) = mpsc::channel();
+        self.stats_receiver = Some(Arc::new(Mutex::new(rx)));
+        self.running.store(true, Ordering::SeqCst);
+
+        for interface in self.interfaces.resolve()? {
+            match Self::get_local_ips(&interface) {
+                Ok(ips) => {
+                    let mut ip_list: Vec<String> = ips.iter().map(IpAddr::to_string).collect();
+                    ip_list.sort();
+                    println!("iftop 监控网卡 {}，本地IP: {}", interface, ip_list.join(", "));
+                    self.spawn_iftop(interface, ips, tx.clone())?;
+                }
+                Err(e) => eprintln!("警告: {}，跳过该网卡", e),
+            }
         }
 
-        let _ = child.wait();
+        if self.child_processes.is_empty() {
+            return Err("没有可监控的网卡".into());
+        }
+        println!("iftop 监控器初始化成功，共 {} 个网卡", self.child_processes.len());
+        self.last_collect = Some(Instant::now());
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<HashMap<FlowKey, TrafficStats>, Box<dyn Error>> {
+        // 等待到本采样周期结束
+        let interval = Duration::from_secs(self.sample_interval as u64);
+        if let Some(last) = self.last_collect {
+            let elapsed = last.elapsed();
+            if elapsed < interval {
+                thread::sleep(interval - elapsed);
+            }
+        }
+        self.last_collect = Some(Instant::now());
+
+        let receiver = self
+            .stats_receiver
+            .as_ref()
+            .ok_or("stats_receiver 未初始化")?;
+
+        // 汇总本周期内收到的全部增量
+        let mut stats: HashMap<FlowKey, TrafficStats> = HashMap::new();
+        let recv_guard = receiver.lock().unwrap();
+        while let Ok(deltas) = recv_guard.try_recv() {
+            for (key, delta) in deltas {
+                stats.entry(key).or_default().add(&delta);
+            }
+        }
 
-        Ok(self.parse_iftop_output(&output))
+        Ok(stats)
     }
 
     fn stop(&mut self) -> Result<(), Box<dyn Error>> {
-        // iftop 是同步执行的，不需要额外停止操作
+        self.running.store(false, Ordering::SeqCst);
+
+        // 先结束 iftop 进程，读取线程随后会读到 EOF 退出
+        for mut child in self.child_processes.drain(..) {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+
+        for handle in self.output_threads.drain(..) {
+            let _ = handle.join();
+        }
+
         Ok(())
     }
 
@@ -187,5 +345,3 @@ impl TrafficMonitor for IftopMonitor {
         "iftop"
     }
 }
-
-
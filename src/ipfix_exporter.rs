@@ -0,0 +1,229 @@
+use crate::monitor::{FlowKey, TrafficStats};
+use crate::netflow::{
+    IE_DESTINATION_IPV4_ADDRESS, IE_DESTINATION_IPV6_ADDRESS, IE_EGRESS_INTERFACE, IE_FLOW_END_SECONDS,
+    IE_FLOW_START_SECONDS, IE_INGRESS_INTERFACE, IE_OCTET_DELTA_COUNT, IE_PACKET_DELTA_COUNT,
+    IE_SOURCE_IPV4_ADDRESS, IE_SOURCE_IPV6_ADDRESS, IPFIX_HEADER_LEN, IPFIX_TEMPLATE_SET, IPFIX_VERSION,
+    MIN_DATA_SET_ID,
+};
+use std::collections::HashMap;
+use std::error::Error;
+use std::ffi::CString;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const TEMPLATE_ID_V4: u16 = MIN_DATA_SET_ID;
+const TEMPLATE_ID_V6: u16 = MIN_DATA_SET_ID + 1;
+
+// 单个 UDP 报文的最大长度（避免 IP 分片）
+const MAX_MESSAGE_LEN: usize = 1400;
+
+const OBSERVATION_DOMAIN_ID: u32 = 1;
+
+/// 模板字段：(信息元素 ID, 长度)
+const TEMPLATE_V4: [(u16, u16); 8] = [
+    (IE_SOURCE_IPV4_ADDRESS, 4),
+    (IE_DESTINATION_IPV4_ADDRESS, 4),
+    (IE_INGRESS_INTERFACE, 4),
+    (IE_EGRESS_INTERFACE, 4),
+    (IE_OCTET_DELTA_COUNT, 8),
+    (IE_PACKET_DELTA_COUNT, 8),
+    (IE_FLOW_START_SECONDS, 4),
+    (IE_FLOW_END_SECONDS, 4),
+];
+const TEMPLATE_V6: [(u16, u16); 8] = [
+    (IE_SOURCE_IPV6_ADDRESS, 16),
+    (IE_DESTINATION_IPV6_ADDRESS, 16),
+    (IE_INGRESS_INTERFACE, 4),
+    (IE_EGRESS_INTERFACE, 4),
+    (IE_OCTET_DELTA_COUNT, 8),
+    (IE_PACKET_DELTA_COUNT, 8),
+    (IE_FLOW_START_SECONDS, 4),
+    (IE_FLOW_END_SECONDS, 4),
+];
+
+/// 一条单向流记录
+struct ExportRecord {
+    src: IpAddr,
+    dst: IpAddr,
+    ingress: u32,
+    egress: u32,
+    bytes: u64,
+    packets: u64,
+}
+
+/// IPFIX 导出器：把每个周期的流量统计作为 IPFIX 记录发送到采集器（nfdump、ntopng 等）
+///
+/// 每条流按方向拆成两条记录（TX：本地 -> 远程，RX：远程 -> 本地），模板在首次发送和每个刷新间隔后重发。
+pub struct IpfixExporter {
+    socket: UdpSocket,
+    collector: String,
+    template_refresh: Duration,
+    last_template: Option<Instant>,
+    last_export: Option<u32>,
+    sequence: u32,
+    ifindex_cache: HashMap<String, u32>,
+}
+
+impl IpfixExporter {
+    pub fn new(collector: &str, template_refresh: Duration) -> Result<Self, Box<dyn Error>> {
+        let addr = collector
+            .to_socket_addrs()
+            .map_err(|e| format!("无效的 IPFIX 采集器地址 {}: {}", collector, e))?
+            .next()
+            .ok_or_else(|| format!("无法解析 IPFIX 采集器地址: {}", collector))?;
+        let socket = UdpSocket::bind(if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+        socket.connect(addr)?;
+        Ok(Self {
+            socket,
+            collector: collector.to_string(),
+            template_refresh,
+            last_template: None,
+            last_export: None,
+            sequence: 0,
+            ifindex_cache: HashMap::new(),
+        })
+    }
+
+    pub fn collector(&self) -> &str {
+        &self.collector
+    }
+
+    /// 导出一个周期的流量（流的开始时间为上一次导出的时间）
+    pub fn export<'a>(
+        &mut self,
+        flows: impl IntoIterator<Item = (&'a FlowKey, &'a TrafficStats)>,
+    ) -> Result<(), Box<dyn Error>> {
+        let end = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as u32;
+        let start = self.last_export.replace(end).unwrap_or(end);
+
+        let mut v4_records = Vec::new();
+        let mut v6_records = Vec::new();
+        for (key, traffic) in flows {
+            let Ok(remote) = key.remote_ip.parse::<IpAddr>() else {
+                continue;
+            };
+            // 无法确定本地地址时使用同协议族的未指定地址
+            let local = key.local_ip.parse::<IpAddr>().unwrap_or(match remote {
+                IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            });
+            let ifindex = self.ifindex(&key.interface);
+
+            let mut records = Vec::with_capacity(2);
+            if traffic.tx_bytes > 0 {
+                records.push(ExportRecord {
+                    src: local,
+                    dst: remote,
+                    ingress: 0,
+                    egress: ifindex,
+                    bytes: traffic.tx_bytes,
+                    packets: traffic.tx_packets,
+                });
+            }
+            if traffic.rx_bytes > 0 {
+                records.push(ExportRecord {
+                    src: remote,
+                    dst: local,
+                    ingress: ifindex,
+                    egress: 0,
+                    bytes: traffic.rx_bytes,
+                    packets: traffic.rx_packets,
+                });
+            }
+            match (local, remote) {
+                (IpAddr::V4(_), IpAddr::V4(_)) => v4_records.extend(records),
+                (IpAddr::V6(_), IpAddr::V6(_)) => v6_records.extend(records),
+                _ => {}
+            }
+        }
+
+        let needs_template = self
+            .last_template
+            .is_none_or(|last| last.elapsed() >= self.template_refresh);
+        if needs_template {
+            self.send_message(end, Self::template_set())?;
+            self.last_template = Some(Instant::now());
+        }
+
+        self.send_data(end, TEMPLATE_ID_V4, &v4_records, start)?;
+        self.send_data(end, TEMPLATE_ID_V6, &v6_records, start)?;
+        Ok(())
+    }
+
+    /// 网卡名对应的 ifIndex（未知网卡为 0）
+    fn ifindex(&mut self, interface: &str) -> u32 {
+        *self.ifindex_cache.entry(interface.to_string()).or_insert_with(|| {
+            CString::new(interface)
+                .map(|name| unsafe { libc::if_nametoindex(name.as_ptr()) })
+                .unwrap_or(0)
+        })
+    }
+
+    fn template_set() -> Vec<u8> {
+        let mut set = Vec::new();
+        for (template_id, fields) in [(TEMPLATE_ID_V4, &TEMPLATE_V4), (TEMPLATE_ID_V6, &TEMPLATE_V6)] {
+            set.extend_from_slice(&template_id.to_be_bytes());
+            set.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+            for (id, len) in fields {
+                set.extend_from_slice(&id.to_be_bytes());
+                set.extend_from_slice(&len.to_be_bytes());
+            }
+        }
+        Self::wrap_set(IPFIX_TEMPLATE_SET, set)
+    }
+
+    /// 按报文长度上限分批发送数据记录
+    fn send_data(&mut self, export_time: u32, template_id: u16, records: &[ExportRecord], start: u32) -> Result<(), Box<dyn Error>> {
+        let fields = if template_id == TEMPLATE_ID_V4 { &TEMPLATE_V4 } else { &TEMPLATE_V6 };
+        let record_len: usize = fields.iter().map(|(_, len)| *len as usize).sum();
+        let per_message = ((MAX_MESSAGE_LEN - IPFIX_HEADER_LEN - 4) / record_len).max(1);
+
+        for chunk in records.chunks(per_message) {
+            let mut body = Vec::with_capacity(chunk.len() * record_len);
+            for record in chunk {
+                match (record.src, record.dst) {
+                    (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                        body.extend_from_slice(&src.octets());
+                        body.extend_from_slice(&dst.octets());
+                    }
+                    (IpAddr::V6(src), IpAddr::V6(dst)) => {
+                        body.extend_from_slice(&src.octets());
+                        body.extend_from_slice(&dst.octets());
+                    }
+                    _ => continue,
+                }
+                body.extend_from_slice(&record.ingress.to_be_bytes());
+                body.extend_from_slice(&record.egress.to_be_bytes());
+                body.extend_from_slice(&record.bytes.to_be_bytes());
+                body.extend_from_slice(&record.packets.to_be_bytes());
+                body.extend_from_slice(&start.to_be_bytes());
+                body.extend_from_slice(&export_time.to_be_bytes());
+            }
+            self.send_message(export_time, Self::wrap_set(template_id, body))?;
+            // 序列号为此前发送的数据记录总数
+            self.sequence = self.sequence.wrapping_add(chunk.len() as u32);
+        }
+        Ok(())
+    }
+
+    fn wrap_set(set_id: u16, body: Vec<u8>) -> Vec<u8> {
+        let mut set = Vec::with_capacity(body.len() + 4);
+        set.extend_from_slice(&set_id.to_be_bytes());
+        set.extend_from_slice(&((body.len() + 4) as u16).to_be_bytes());
+        set.extend_from_slice(&body);
+        set
+    }
+
+    fn send_message(&self, export_time: u32, set: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        let mut message = Vec::with_capacity(IPFIX_HEADER_LEN + set.len());
+        message.extend_from_slice(&IPFIX_VERSION.to_be_bytes());
+        message.extend_from_slice(&((IPFIX_HEADER_LEN + set.len()) as u16).to_be_bytes());
+        message.extend_from_slice(&export_time.to_be_bytes());
+        message.extend_from_slice(&self.sequence.to_be_bytes());
+        message.extend_from_slice(&OBSERVATION_DOMAIN_ID.to_be_bytes());
+        message.extend_from_slice(&set);
+        // 采集器未启动时 send 可能返回 ECONNREFUSED，下个周期重试即可
+        self.socket.send(&message)?;
+        Ok(())
+    }
+}
@@ -1,19 +1,62 @@
 mod monitor;
 mod iftop_monitor;
 mod bpftrace_monitor;
+mod attribution;
+mod address_filter;
+mod packet_parser;
+mod packet_monitor;
+mod pcap_reader;
+mod pcap_monitor;
+mod nfnetlink;
+mod conntrack_monitor;
+mod nft;
+mod nftables_monitor;
+mod flow_collector;
+mod netflow;
+mod sflow;
+mod ipfix_exporter;
+mod mock_monitor;
+mod rate;
+mod billing;
+mod quota;
+mod alert;
+mod anomaly;
+mod enforcement;
+mod shaping;
+mod topk;
 
 use chrono::Local;
 use clap::Parser;
-use monitor::{TrafficMonitor, TrafficStats, format_bytes};
+use monitor::{FlowKey, InterfaceSelector, TrafficMonitor, TrafficStats, format_bps, format_bytes};
 use iftop_monitor::{IftopMonitor};
 use bpftrace_monitor::BpftraceMonitor;
+use packet_monitor::PacketMonitor;
+use pcap_monitor::PcapMonitor;
+use conntrack_monitor::ConntrackMonitor;
+use nftables_monitor::NftablesMonitor;
+use flow_collector::CollectorMonitor;
+use netflow::NetflowDecoder;
+use sflow::SflowDecoder;
+use ipfix_exporter::IpfixExporter;
+use mock_monitor::{Distribution, MockConfig, MockMonitor, TraceWriter};
+use rate::{RateTracker, RATE_WINDOWS};
+use billing::BillingTracker;
+use quota::{parse_size, Quota, QuotaScope, QuotaTracker};
+use alert::{parse_duration, AlertEngine, AlertFlow};
+use anomaly::{Anomaly, AnomalyDetector, AnomalyScope};
+use enforcement::{BlockReason, EnforceAction, Enforcer};
+use shaping::{ShapePolicy, Shaper};
+use topk::{FlowStore, SpaceSaving};
+use address_filter::{AddressFilter, Cidr, FlowFilter, LocalNetworks};
+use attribution::{get_owner_for_ip, get_process_name, get_username, retain_owners, IpOwner};
 use std::thread;
-use std::time::Duration;
-use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use actix_web::{web, App, HttpServer, HttpResponse, middleware::Compress};
 use maxminddb::{geoip2, Reader};
 use once_cell::sync::Lazy;
+use serde::Serialize;
 use std::sync::Mutex;
 use std::sync::atomic::{AtomicBool, Ordering};
 
@@ -30,15 +73,19 @@ fn check_root_permission() -> Result<(), String> {
 
 // ==================== 命令行参数定义 ====================
 #[derive(Parser, Debug)]
-#[command(author, version, about = "IP 流量统计工具（支持 iftop 和 bpftrace）", long_about = None)]
+#[command(author, version, about = "IP 流量统计工具（支持 iftop、bpftrace、AF_PACKET、conntrack、nftables、NetFlow/IPFIX/sFlow 采集、pcap 回放和模拟流量）", long_about = None)]
 struct Cli {
-    /// 监控后端（iftop 或 bpftrace）
-    #[arg(short = 'b', long, default_value = "iftop", help = "监控后端: iftop 或 bpftrace")]
+    /// 监控后端（iftop、bpftrace、packet、conntrack、nftables、netflow、sflow、pcap 或 mock）
+    #[arg(short = 'b', long, default_value = "iftop", help = "监控后端: iftop、bpftrace、packet（AF_PACKET 抓包）、conntrack（连接跟踪计数）、nftables（内核计数器）、netflow（NetFlow v5/v9/IPFIX 采集）、sflow（sFlow v5 采集）、pcap（回放抓包文件）或 mock（模拟流量）")]
     backend: String,
 
-    /// 出口网卡名（iftop 模式必填，通过 ip addr 查看）
-    #[arg(short, long, help = "示例：eth0、ens33、enp2s0")]
-    iface: Option<String>,
+    /// 监控的网卡列表（通过 ip addr 查看，不指定时监控除 --exclude-iface 外的全部网卡）
+    #[arg(short, long, value_delimiter = ',', help = "示例：eth0、ens33、enp2s0、eth0,wg0")]
+    iface: Vec<String>,
+
+    /// 不监控的网卡列表
+    #[arg(long, value_delimiter = ',', help = "示例：lo,docker0")]
+    exclude_iface: Vec<String>,
 
     /// 监控时长（单位：秒，默认 30 秒，设置为 0 表示永久运行）
     #[arg(short, long, default_value_t = 30, help = "示例：60（监控 1 分钟），0（永久运行）")]
@@ -56,13 +103,197 @@ struct Cli {
     #[arg(short = 'g', long, help = "GeoIP2 City 数据库文件路径，例如：GeoLite2-City.mmdb")]
     geoip_db: Option<String>,
 
+    /// GeoIP2 ASN 数据库文件路径（可选，用于 ASN 和运营商查询）
+    #[arg(long, help = "GeoIP2 ASN 数据库文件路径，例如：GeoLite2-ASN.mmdb")]
+    geoip_asn_db: Option<String>,
+
     /// Prometheus metrics 流量阈值（单位：字节，默认 1MB）
     #[arg(short = 't', long, default_value_t = 1024 * 1024, help = "低于此阈值的流量不会导出到 Prometheus")]
     prometheus_export_threshold: u64,
 
+    /// 最多导出的按流序列数（按累计字节数取前 N 个）
+    #[arg(long, help = "限制 Prometheus 序列数，例如：500")]
+    prometheus_max_series: Option<usize>,
+
+    /// 按流指标去掉 remote_ip 标签，按国家 / 城市 / ISP / ASN 等其余标签汇总
+    #[arg(long)]
+    prometheus_drop_remote_ip: bool,
+
+    /// 超过该时长没有流量的流不再导出
+    #[arg(long, help = "支持 s/m/h/d 后缀，例如：1h")]
+    prometheus_idle_expiry: Option<String>,
+
     /// 自定义 bpftrace 脚本路径（仅 bpftrace 模式）
     #[arg(long, help = "自定义 bpftrace 脚本文件路径")]
     bpftrace_script: Option<String>,
+
+    /// 额外统计的地址分类或网段（默认只统计公网地址）
+    #[arg(long, value_delimiter = ',', help = "分类名称（public/private/loopback/link-local/multicast/reserved/broadcast/ula/unspecified/lan）或 CIDR，例如：lan,100.64.0.0/10")]
+    include_addr: Vec<String>,
+
+    /// 不统计的地址分类或网段（优先级高于 --include-addr）
+    #[arg(long, value_delimiter = ',', help = "分类名称或 CIDR，例如：public,192.168.1.1")]
+    exclude_addr: Vec<String>,
+
+    /// 只统计这些网段的流量（在累加到全局统计之前过滤）
+    #[arg(long, value_delimiter = ',', help = "CIDR 列表，例如：203.0.113.0/24,2001:db8::/32")]
+    filter_include: Vec<String>,
+
+    /// 忽略这些网段的流量（优先级高于 --filter-include）
+    #[arg(long, value_delimiter = ',', help = "CIDR 列表，例如：198.51.100.7（监控服务器）")]
+    filter_exclude: Vec<String>,
+
+    /// IPv4 远程地址聚合前缀长度
+    #[arg(long, help = "例如：24（按 /24 网段合并统计）")]
+    aggregate_v4_prefix: Option<u8>,
+
+    /// IPv6 远程地址聚合前缀长度
+    #[arg(long, help = "例如：48（按 /48 网段合并统计）")]
+    aggregate_v6_prefix: Option<u8>,
+
+    /// 回放的抓包文件（仅 pcap 模式）
+    #[arg(long, help = "pcap / pcapng 文件路径，例如：capture.pcap")]
+    pcap_file: Option<String>,
+
+    /// 流量采集器监听地址（仅 netflow / sflow 模式）
+    #[arg(long, help = "UDP 监听地址，默认：0.0.0.0:2055（netflow）、0.0.0.0:6343（sflow）")]
+    listen: Option<String>,
+
+    /// IPFIX 采集器地址（把每个周期的流量作为 IPFIX 记录导出）
+    #[arg(long, help = "例如：127.0.0.1:4739（nfdump / ntopng）")]
+    ipfix_collector: Option<String>,
+
+    /// IPFIX 模板重发间隔（单位：秒）
+    #[arg(long, default_value_t = 60, help = "UDP 传输时定期重发模板，便于采集器重启后恢复解析")]
+    ipfix_template_refresh: u64,
+
+    /// 模拟的远程地址数量（仅 mock 模式）
+    #[arg(long, default_value_t = 20, help = "合成流量中的远程 IP 数量")]
+    mock_ips: usize,
+
+    /// 模拟的平均总流量（单位：字节/秒，仅 mock 模式）
+    #[arg(long, default_value_t = 1024 * 1024, help = "所有远程地址的 TX + RX 平均速率")]
+    mock_rate: u64,
+
+    /// 模拟流量在远程地址间的分布（仅 mock 模式）
+    #[arg(long, default_value = "zipf", help = "uniform（均匀）或 zipf（少数地址占大部分流量）")]
+    mock_distribution: Distribution,
+
+    /// 模拟流量中 IPv6 地址的比例（仅 mock 模式）
+    #[arg(long, default_value_t = 0.2, help = "0~1 之间，例如：0.5")]
+    mock_ipv6_ratio: f64,
+
+    /// 每个远程地址每周期开始突发流量的概率（仅 mock 模式）
+    #[arg(long, default_value_t = 0.05, help = "0~1 之间，突发时流量放大 5~20 倍，持续 1~3 个周期")]
+    mock_burst_prob: f64,
+
+    /// 随机数种子（仅 mock 模式，指定后每次运行生成相同的流量）
+    #[arg(long)]
+    mock_seed: Option<u64>,
+
+    /// 回放的流量记录文件（仅 mock 模式，由 --record-trace 生成）
+    #[arg(long, help = "JSON Lines 文件，每行一个周期的流量")]
+    mock_trace: Option<String>,
+
+    /// 把每个周期的原始流量记录到文件（可用 mock 模式回放）
+    #[arg(long, help = "JSON Lines 文件路径，例如：trace.jsonl")]
+    record_trace: Option<String>,
+
+    /// 启用 95 计费统计（每 5 分钟一个采样）
+    #[arg(long)]
+    billing: bool,
+
+    /// 每月计费周期的开始日
+    #[arg(long, default_value_t = 1, help = "1~28，计费周期从该日 0 点开始")]
+    billing_day: u32,
+
+    /// 需要单独计费的 IP 或网段（网卡总是单独计费）
+    #[arg(long, value_delimiter = ',', help = "CIDR 列表，例如：203.0.113.0/24,198.51.100.7")]
+    billing_target: Vec<String>,
+
+    /// 计费样本的保存文件（重启后恢复本计费周期的样本）
+    #[arg(long, default_value = "ip_traffic_billing.json")]
+    billing_state: String,
+
+    /// 月流量配额（可重复指定）
+    #[arg(long, value_delimiter = ',', help = "例如：global=1T、ip:1.2.3.4=100G@tx、cidr:10.0.0.0/8=500G、process:nginx=200G")]
+    quota: Vec<String>,
+
+    /// 每月配额重置日
+    #[arg(long, default_value_t = 1, help = "1~28，配额用量在该日 0 点清零")]
+    quota_reset_day: u32,
+
+    /// 配额用量的保存文件（重启后恢复本月用量）
+    #[arg(long, default_value = "ip_traffic_quota.json")]
+    quota_state: String,
+
+    /// 告警规则文件（JSON，包含规则和 webhook）
+    #[arg(long, help = "例如：alerts.json，格式见 README")]
+    alert_rules: Option<String>,
+
+    /// 向告警规则文件中的全部 webhook 发送一条测试告警后退出
+    #[arg(long, requires = "alert_rules")]
+    alert_test: bool,
+
+    /// 启用流量异常检测（按远程 IP 和进程学习每周期流量的基线）
+    #[arg(long)]
+    anomaly: bool,
+
+    /// 超过基线均值多少个标准差视为异常
+    #[arg(long, default_value_t = 3.0)]
+    anomaly_sigma: f64,
+
+    /// 基线的 EWMA 平滑系数（越大越偏向最近的周期）
+    #[arg(long, default_value_t = 0.1, help = "0~1")]
+    anomaly_alpha: f64,
+
+    /// 基线至少学习多少个周期后才开始判断异常
+    #[arg(long, default_value_t = 10)]
+    anomaly_warmup: u64,
+
+    /// 超出基线均值的流量不足该值（字节/周期）时不视为异常
+    #[arg(long, default_value = "1M", help = "支持 K/M/G 后缀")]
+    anomaly_min_bytes: String,
+
+    /// 对超出配额或流量异常的 IP 自动处置（通过 nftables 集合，需要 root 权限）
+    #[arg(long, help = "drop（封禁）或 limit（限速）")]
+    enforce: Option<String>,
+
+    /// 触发处置的条件
+    #[arg(long, value_delimiter = ',', default_value = "quota,anomaly", help = "quota（IP / 网段配额用尽）、anomaly（IP 流量异常）")]
+    enforce_on: Vec<String>,
+
+    /// 每次处置的持续时间
+    #[arg(long, default_value = "1h", help = "支持 s/m/h/d 后缀")]
+    enforce_duration: String,
+
+    /// limit 模式下每个 IP 每个方向的速率上限（字节/秒）
+    #[arg(long, default_value = "1M", help = "支持 K/M/G 后缀")]
+    enforce_rate: String,
+
+    /// 永远不处置的 IP 或网段
+    #[arg(long, value_delimiter = ',', help = "CIDR 列表，例如：192.168.0.0/16,10.0.0.1")]
+    enforce_exempt: Vec<String>,
+
+    /// 生效中处置的保存文件（重启后恢复未到期的处置）
+    #[arg(long, default_value = "ip_traffic_blocks.json")]
+    enforce_state: String,
+
+    /// 限速策略：TX 速率超过阈值的 IP 或进程通过 tc HTB 限速（需要 root 权限）
+    #[arg(long, value_delimiter = ',', help = "<ip|process>:<阈值>=<限速>（字节/秒），例如：ip:10M=2M,process:50M=10M")]
+    shape: Vec<String>,
+
+    /// 速率持续低于阈值多久后解除限速
+    #[arg(long, default_value = "5m", help = "支持 s/m/h/d 后缀")]
+    shape_hold: String,
+
+    /// 只跟踪累计字节数最大的 K 个流（Space-Saving 算法，内存固定），其余流量汇总为 other
+    #[arg(long, help = "扫描等大量远程 IP 的场景下限制内存占用")]
+    top_k: Option<usize>,
+
+    /// 本地网段（非本机数据源用来判断流量方向）
+    #[arg(long, value_delimiter = ',', help = "CIDR 列表，例如：203.0.113.10,192.168.0.0/16")]
+    local_net: Vec<String>,
 }
 
 // ==================== Prometheus Exporter 相关 ====================
@@ -72,31 +303,98 @@ static GEOIP_READER: Lazy<Mutex<Option<Reader<memmap2::Mmap>>>> = Lazy::new(|| M
 // 全局退出标志
 static RUNNING: AtomicBool = AtomicBool::new(true);
 
-// 全局 IP 流量统计存储（IP -> 累计流量统计）
-type IpTrafficStore = Arc<Mutex<HashMap<String, TrafficStats>>>;
-static IP_TRAFFIC_STATS: Lazy<IpTrafficStore> = Lazy::new(|| Arc::new(Mutex::new(HashMap::new())));
+// 全局 IP 流量统计存储（网卡 + IP -> 累计流量统计；指定 --top-k 时只保存流量最大的 K 个流）
+type IpTrafficStore = Arc<Mutex<FlowStore>>;
+static IP_TRAFFIC_STATS: Lazy<IpTrafficStore> = Lazy::new(|| Arc::new(Mutex::new(FlowStore::default())));
+
+// 全局 GeoIP ASN 数据库读取器（未指定 --geoip-asn-db 时为 None）
+static GEOIP_ASN_READER: Lazy<Mutex<Option<Reader<memmap2::Mmap>>>> = Lazy::new(|| Mutex::new(None));
+
+// 每个流最近一次有流量的时间（用于 --prometheus-idle-expiry）
+static FLOW_LAST_SEEN: Lazy<Mutex<HashMap<FlowKey, Instant>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 
 // IP 地理信息缓存（减少重复查询 GeoIP 数据库）
 static GEO_CACHE: Lazy<Mutex<HashMap<String, IpGeoInfo>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 
-// IP -> PID 缓存（减少 /proc 遍历），带时间戳实现 1 小时过期
-static PID_CACHE: Lazy<Mutex<HashMap<String, (Option<i32>, std::time::Instant)>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+// 全局用户流量统计存储（用户名 -> 累计流量统计）
+static USER_TRAFFIC_STATS: Lazy<Mutex<HashMap<String, TrafficStats>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// 按国家 / 城市 / ASN 汇总的累计流量（包含低于导出阈值和被 Top-K 淘汰的流，区域合计是精确值）
+static REGION_TRAFFIC_STATS: Lazy<Mutex<RegionTrafficStats>> = Lazy::new(|| Mutex::new(RegionTrafficStats::default()));
+
+// 每个流的滑动窗口吞吐量（1m/5m/15m）
+static RATE_TRACKER: Lazy<Mutex<RateTracker>> = Lazy::new(|| Mutex::new(RateTracker::new()));
+
+// IPFIX 导出器（未指定 --ipfix-collector 时为 None）
+static IPFIX_EXPORTER: Lazy<Mutex<Option<IpfixExporter>>> = Lazy::new(|| Mutex::new(None));
+
+// 95 计费统计（未指定 --billing 时为 None）
+static BILLING_TRACKER: Lazy<Mutex<Option<BillingTracker>>> = Lazy::new(|| Mutex::new(None));
+
+// 月流量配额（未指定 --quota 时为 None）
+static QUOTA_TRACKER: Lazy<Mutex<Option<QuotaTracker>>> = Lazy::new(|| Mutex::new(None));
+
+// 告警引擎（未指定 --alert-rules 时为 None）
+static ALERT_ENGINE: Lazy<Mutex<Option<AlertEngine>>> = Lazy::new(|| Mutex::new(None));
+
+// 流量异常检测（未指定 --anomaly 时为 None）
+static ANOMALY_DETECTOR: Lazy<Mutex<Option<AnomalyDetector>>> = Lazy::new(|| Mutex::new(None));
+
+// 违规 IP 处置（未指定 --enforce 时为 None）
+static ENFORCER: Lazy<Mutex<Option<Enforcer>>> = Lazy::new(|| Mutex::new(None));
+
+// tc 限速（未指定 --shape 时为 None）
+static SHAPER: Lazy<Mutex<Option<Shaper>>> = Lazy::new(|| Mutex::new(None));
+
+// 流量记录文件（未指定 --record-trace 时为 None）
+static TRACE_WRITER: Lazy<Mutex<Option<TraceWriter>>> = Lazy::new(|| Mutex::new(None));
 
-// PID -> 进程名缓存（减少 /proc 文件读取）
-static PROCESS_NAME_CACHE: Lazy<Mutex<HashMap<i32, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+// 当前数据源的流量是否为采样估算值（决定 metrics 是否带 sampled 标签）
+static SAMPLED_DATA: AtomicBool = AtomicBool::new(false);
 
-// /proc/net/tcp 缓存（减少文件读取）
-static TCP_CONNECTIONS_CACHE: Lazy<Mutex<(std::time::Instant, HashMap<String, u32>)>> = Lazy::new(|| {
-    Mutex::new((std::time::Instant::now(), HashMap::new()))
-});
+// 未能归属到用户的流量使用的用户名
+const UNKNOWN_USER: &str = "Unknown";
+
+// 一个周期内（过滤、聚合后）的单个流，供告警评估使用
+struct CycleFlow {
+    key: FlowKey,
+    traffic: TrafficStats,
+    process: Option<String>,
+}
 
 // IP 地理信息结构
 #[derive(Debug, Clone)]
 struct IpGeoInfo {
     country: String,
+    country_code: String,  // ISO 3166-1 代码，例如 CN（用于告警规则匹配）
     province: String,
     city: String,
     isp: String,
+    asn: String,  // 例如 AS13335（需要 --geoip-asn-db）
+}
+
+// 按地理区域和 ASN 预先汇总的流量
+#[derive(Default)]
+struct RegionTrafficStats {
+    country: HashMap<String, TrafficStats>,
+    // (国家, 省份, 城市)，同名城市按国家和省份区分
+    city: HashMap<(String, String, String), TrafficStats>,
+    // (ASN, ISP)
+    asn: HashMap<(String, String), TrafficStats>,
+}
+
+impl RegionTrafficStats {
+    fn add(&mut self, geo_info: &IpGeoInfo, traffic: &TrafficStats) {
+        self.country.entry(geo_info.country.clone()).or_default().add(traffic);
+        self.city
+            .entry((geo_info.country.clone(), geo_info.province.clone(), geo_info.city.clone()))
+            .or_default()
+            .add(traffic);
+        self.asn
+            .entry((geo_info.asn.clone(), geo_info.isp.clone()))
+            .or_default()
+            .add(traffic);
+    }
 }
 
 fn init_geoip_db(db_path: &str) -> Result<(), String> {
@@ -117,6 +415,29 @@ fn init_geoip_db(db_path: &str) -> Result<(), String> {
     Ok(())
 }
 
+fn init_geoip_asn_db(db_path: &str) -> Result<(), String> {
+    let file = std::fs::File::open(db_path)
+        .map_err(|e| format!("无法打开 GeoIP ASN 数据库文件: {}", e))?;
+    let mmap = unsafe { memmap2::Mmap::map(&file) }
+        .map_err(|e| format!("无法映射 GeoIP ASN 数据库文件: {}", e))?;
+    let reader = Reader::from_source(mmap)
+        .map_err(|e| format!("GeoIP ASN 数据库加载失败: {}", e))?;
+    
+    *GEOIP_ASN_READER.lock().unwrap() = Some(reader);
+    println!("GeoIP ASN 数据库加载成功（使用 mmap）: {}", db_path);
+    Ok(())
+}
+
+// 查询 ASN 和所属组织
+fn lookup_asn(ip: std::net::IpAddr) -> Option<(String, Option<String>)> {
+    let reader_guard = GEOIP_ASN_READER.lock().unwrap();
+    let asn = reader_guard.as_ref()?.lookup::<geoip2::Asn>(ip).ok()?;
+    Some((
+        format!("AS{}", asn.autonomous_system_number?),
+        asn.autonomous_system_organization.map(str::to_string),
+    ))
+}
+
 fn get_ip_geo_info(ip_str: &str) -> IpGeoInfo {
     // 先检查缓存
     {
@@ -126,24 +447,37 @@ fn get_ip_geo_info(ip_str: &str) -> IpGeoInfo {
         }
     }
     
-    let default_info = IpGeoInfo {
+    let mut default_info = IpGeoInfo {
         country: "Unknown".to_string(),
+        country_code: "Unknown".to_string(),
         province: "Unknown".to_string(),
         city: "Unknown".to_string(),
         isp: "Unknown".to_string(),
+        asn: "Unknown".to_string(),
+    };
+
+    // 解析 IP 地址（聚合后的网段使用网络地址查询）
+    let ip: std::net::IpAddr = match ip_str.split('/').next().unwrap_or(ip_str).parse() {
+        Ok(ip) => ip,
+        Err(_) => return default_info,
     };
+    
+    // ASN 数据库独立于 City 数据库，组织名称同时作为 ISP
+    if let Some((asn, org)) = lookup_asn(ip) {
+        default_info.asn = asn;
+        if let Some(org) = org {
+            default_info.isp = org;
+        }
+    }
 
-    // 如果没有加载 GeoIP 数据库，返回默认值
+    // 如果没有加载 GeoIP 数据库，返回默认值（仍然缓存 ASN 查询结果）
     let reader_guard = GEOIP_READER.lock().unwrap();
     let reader = match reader_guard.as_ref() {
         Some(r) => r,
-        None => return default_info,
-    };
-
-    // 解析 IP 地址
-    let ip: std::net::IpAddr = match ip_str.parse() {
-        Ok(ip) => ip,
-        Err(_) => return default_info,
+        None => {
+            GEO_CACHE.lock().unwrap().insert(ip_str.to_string(), default_info.clone());
+            return default_info;
+        }
     };
 
     // 查询 GeoIP 数据库
@@ -192,15 +526,22 @@ fn get_ip_geo_info(ip_str: &str) -> IpGeoInfo {
                 "Unknown".to_string()
             };
 
-            // GeoLite2-City 数据库不包含 ISP 详细信息
-            // 如需 ISP 信息，建议使用纯真 IP 数据库或付费的 GeoIP2-ISP 数据库
-            let isp = "Unknown".to_string();
+            let country_code = city
+                .country
+                .as_ref()
+                .and_then(|c| c.iso_code)
+                .unwrap_or("Unknown")
+                .to_string();
 
+            // GeoLite2-City 数据库不包含 ISP 详细信息，使用 ASN 数据库中的组织名称（未指定时为 Unknown）
+            // 如需更准确的 ISP 信息，建议使用纯真 IP 数据库或付费的 GeoIP2-ISP 数据库
             IpGeoInfo {
                 country,
+                country_code,
                 province,
                 city: city_name,
-                isp,
+                isp: default_info.isp.clone(),
+                asn: default_info.asn.clone(),
             }
         }
         Err(_) => default_info.clone(),
@@ -218,12 +559,86 @@ fn get_ip_geo_info(ip_str: &str) -> IpGeoInfo {
 #[derive(Clone)]
 struct AppState {
     prometheus_export_threshold: u64,
+    series_limits: SeriesLimits,
+}
+
+// 按流导出的 metrics 的基数控制
+#[derive(Clone, Default)]
+struct SeriesLimits {
+    // 最多导出的序列数（按累计字节数取前 N 个）
+    max_series: Option<usize>,
+    // 去掉 remote_ip 标签，按国家 / 省份 / 城市 / ISP / ASN 等其余标签汇总
+    drop_remote_ip: bool,
+    // 超过该时长没有流量的序列（全部成员流都没有流量）不再导出
+    idle_expiry: Option<Duration>,
+}
+
+// 一个按流导出的序列：标签、累计流量和包含的流
+struct FlowSeries<'a> {
+    labels: String,
+    traffic: TrafficStats,
+    keys: Vec<&'a FlowKey>,
+    // 成员流中最近一次有流量的时间
+    last_seen: Option<Instant>,
+}
+
+impl FlowSeries<'_> {
+    // 按整个序列判断过期：只过期部分成员流会让汇总后的计数器变小
+    fn is_idle(&self, limits: &SeriesLimits) -> bool {
+        limits
+            .idle_expiry
+            .is_some_and(|expiry| self.last_seen.is_none_or(|seen| seen.elapsed() > expiry))
+    }
+}
+
+// 按标签把流归并为序列
+fn group_flow_series<'a>(
+    stats: &'a FlowStore,
+    last_seen: &HashMap<FlowKey, Instant>,
+    limits: &SeriesLimits,
+) -> Vec<FlowSeries<'a>> {
+    let mut grouped: HashMap<String, FlowSeries<'a>> = HashMap::new();
+    for (key, traffic) in stats.iter() {
+        let labels = if limits.drop_remote_ip { aggregated_labels(key) } else { flow_labels(key) };
+        let item = grouped.entry(labels.clone()).or_insert_with(|| FlowSeries {
+            labels,
+            traffic: TrafficStats::default(),
+            keys: Vec::new(),
+            last_seen: None,
+        });
+        item.traffic.add(traffic);
+        item.keys.push(key);
+        item.last_seen = item.last_seen.max(last_seen.get(key).copied());
+    }
+    grouped.into_values().collect()
+}
+
+// 按基数控制选出需要导出的序列（至少一个方向超过阈值），按累计字节数从大到小排列
+fn flow_series<'a>(
+    stats: &'a FlowStore,
+    prometheus_export_threshold: u64,
+    limits: &SeriesLimits,
+) -> Vec<FlowSeries<'a>> {
+    let last_seen = FLOW_LAST_SEEN.lock().unwrap();
+    // 过期的序列在每个周期结束时删除，这里过滤掉两次清理之间刚过期的序列
+    let mut series: Vec<FlowSeries> = group_flow_series(stats, &last_seen, limits)
+        .into_iter()
+        .filter(|s| s.traffic.tx_bytes > prometheus_export_threshold || s.traffic.rx_bytes > prometheus_export_threshold)
+        .filter(|s| !s.is_idle(limits))
+        .collect();
+    series.sort_by(|a, b| {
+        b.traffic.total().cmp(&a.traffic.total()).then_with(|| a.labels.cmp(&b.labels))
+    });
+    if let Some(max_series) = limits.max_series {
+        series.truncate(max_series);
+    }
+    series
 }
 
 async fn metrics_handler(data: web::Data<AppState>) -> HttpResponse {
     let prometheus_export_threshold = data.prometheus_export_threshold;
     
-    match get_ip_traffic_metrics(prometheus_export_threshold) {
+    match get_ip_traffic_metrics(prometheus_export_threshold, &data.series_limits) {
         Ok(metrics) => HttpResponse::Ok()
             .content_type("text/plain; version=0.0.4")
             .body(metrics),
@@ -232,29 +647,22 @@ async fn metrics_handler(data: web::Data<AppState>) -> HttpResponse {
     }
 }
 
-fn get_ip_traffic_metrics(prometheus_export_threshold: u64) -> Result<String, String> {
+fn get_ip_traffic_metrics(prometheus_export_threshold: u64, limits: &SeriesLimits) -> Result<String, String> {
     let stats = IP_TRAFFIC_STATS.lock().unwrap();
     
     let mut output = String::new();
     
+    let series = flow_series(&stats, prometheus_export_threshold, limits);
+    
     // TX 流量指标（上行流量：本机发送到远程IP的字节数）
     output.push_str("# HELP ip_traffic_tx_bytes_total Total transmitted bytes to remote IP address (egress/upload traffic)\n");
     output.push_str("# TYPE ip_traffic_tx_bytes_total counter\n");
     
-    for (ip, traffic) in stats.iter() {
-        if traffic.tx_bytes <= prometheus_export_threshold {
-            continue;
-        }
-        let geo_info = get_ip_geo_info(ip);
-        
+    for item in series.iter().filter(|s| s.traffic.tx_bytes > prometheus_export_threshold) {
         output.push_str(&format!(
-            "ip_traffic_tx_bytes_total{{remote_ip=\"{}\",country=\"{}\",province=\"{}\",city=\"{}\",isp=\"{}\"}} {}\n",
-            ip, 
-            escape_label(&geo_info.country),
-            escape_label(&geo_info.province),
-            escape_label(&geo_info.city),
-            escape_label(&geo_info.isp),
-            traffic.tx_bytes
+            "ip_traffic_tx_bytes_total{{{}}} {}\n",
+            item.labels,
+            item.traffic.tx_bytes
         ));
     }
     
@@ -262,19 +670,210 @@ fn get_ip_traffic_metrics(prometheus_export_threshold: u64) -> Result<String, St
     output.push_str("\n# HELP ip_traffic_rx_bytes_total Total received bytes from remote IP address (ingress/download traffic)\n");
     output.push_str("# TYPE ip_traffic_rx_bytes_total counter\n");
     
-    for (ip, traffic) in stats.iter() {
-        if traffic.rx_bytes <= prometheus_export_threshold {
-            continue;
+    for item in series.iter().filter(|s| s.traffic.rx_bytes > prometheus_export_threshold) {
+        output.push_str(&format!(
+            "ip_traffic_rx_bytes_total{{{}}} {}\n",
+            item.labels,
+            item.traffic.rx_bytes
+        ));
+    }
+    
+    // Top-K 统计的误差上界和 Top-K 之外的流量汇总
+    if let (Some(capacity), Some((other, evictions))) = (stats.capacity(), stats.remainder()) {
+        output.push_str("\n# HELP ip_traffic_topk_error_bytes Upper bound of the over-estimation error of a top-K flow's total bytes (TX + RX)\n");
+        output.push_str("# TYPE ip_traffic_topk_error_bytes gauge\n");
+        for item in &series {
+            let error = item.keys.iter().fold(0u64, |sum, key| sum.saturating_add(stats.error(key)));
+            output.push_str(&format!("ip_traffic_topk_error_bytes{{{}}} {}\n", item.labels, error));
         }
-        let geo_info = get_ip_geo_info(ip);
-        
+        output.push_str("\n# HELP ip_traffic_other_tx_bytes_total Transmitted bytes of flows outside the top-K (evicted)\n");
+        output.push_str("# TYPE ip_traffic_other_tx_bytes_total counter\n");
+        output.push_str(&format!("ip_traffic_other_tx_bytes_total {}\n", other.tx_bytes));
+        output.push_str("\n# HELP ip_traffic_other_rx_bytes_total Received bytes of flows outside the top-K (evicted)\n");
+        output.push_str("# TYPE ip_traffic_other_rx_bytes_total counter\n");
+        output.push_str(&format!("ip_traffic_other_rx_bytes_total {}\n", other.rx_bytes));
+        output.push_str("\n# HELP ip_traffic_topk_evictions_total Number of flows evicted from the top-K\n");
+        output.push_str("# TYPE ip_traffic_topk_evictions_total counter\n");
+        output.push_str(&format!("ip_traffic_topk_evictions_total {}\n", evictions));
+        output.push_str("\n# HELP ip_traffic_topk_capacity Number of flows tracked by the top-K\n");
+        output.push_str("# TYPE ip_traffic_topk_capacity gauge\n");
+        output.push_str(&format!("ip_traffic_topk_capacity {}\n", capacity));
+    }
+    
+    // 滑动窗口速率指标（与累计流量使用相同的导出阈值）
+    let rate_tracker = RATE_TRACKER.lock().unwrap();
+    let rate_metrics = [
+        ("ip_traffic_tx_rate_bytes", "Average transmitted bytes per second to remote IP address over the window", true, true),
+        ("ip_traffic_rx_rate_bytes", "Average received bytes per second from remote IP address over the window", false, true),
+        ("ip_traffic_tx_rate_packets", "Average transmitted packets per second to remote IP address over the window", true, false),
+        ("ip_traffic_rx_rate_packets", "Average received packets per second from remote IP address over the window", false, false),
+    ];
+    for (name, help, is_tx, is_bytes) in rate_metrics {
+        output.push_str(&format!("\n# HELP {} {}\n", name, help));
+        output.push_str(&format!("# TYPE {} gauge\n", name));
+        for item in &series {
+            let total = if is_tx { item.traffic.tx_bytes } else { item.traffic.rx_bytes };
+            if total <= prometheus_export_threshold || !item.keys.iter().any(|key| rate_tracker.contains(key)) {
+                continue;
+            }
+            for (window_name, window) in RATE_WINDOWS {
+                let value: f64 = item
+                    .keys
+                    .iter()
+                    .map(|key| {
+                        let rate = rate_tracker.rate(key, window);
+                        match (is_tx, is_bytes) {
+                            (true, true) => rate.tx_bytes,
+                            (false, true) => rate.rx_bytes,
+                            (true, false) => rate.tx_packets,
+                            (false, false) => rate.rx_packets,
+                        }
+                    })
+                    .sum();
+                output.push_str(&format!("{}{{{},window=\"{}\"}} {:.3}\n", name, item.labels, window_name, value));
+            }
+        }
+    }
+    drop(rate_tracker);
+    
+    // 95 计费指标（当前计费周期）
+    if let Some(tracker) = BILLING_TRACKER.lock().unwrap().as_ref() {
+        output.push_str("\n# HELP ip_traffic_billing_bps Percentile of 5-minute average bits per second in the current billing period\n");
+        output.push_str("# TYPE ip_traffic_billing_bps gauge\n");
+        let reports = tracker.reports();
+        for report in &reports {
+            let labels = format!("target=\"{}\",kind=\"{}\"", escape_label(&report.target), report.kind.as_str());
+            let values = [
+                ("tx", "p95", report.tx_p95_bps),
+                ("tx", "p99", report.tx_p99_bps),
+                ("tx", "max", report.tx_max_bps),
+                ("rx", "p95", report.rx_p95_bps),
+                ("rx", "p99", report.rx_p99_bps),
+                ("rx", "max", report.rx_max_bps),
+                ("billable", "p95", report.billable_p95_bps),
+            ];
+            for (direction, stat, value) in values {
+                output.push_str(&format!(
+                    "ip_traffic_billing_bps{{{},direction=\"{}\",stat=\"{}\"}} {:.0}\n",
+                    labels, direction, stat, value
+                ));
+            }
+        }
+        output.push_str("\n# HELP ip_traffic_billing_samples Number of 5-minute samples in the current billing period\n");
+        output.push_str("# TYPE ip_traffic_billing_samples gauge\n");
+        for report in &reports {
+            output.push_str(&format!(
+                "ip_traffic_billing_samples{{target=\"{}\",kind=\"{}\"}} {}\n",
+                escape_label(&report.target),
+                report.kind.as_str(),
+                report.samples
+            ));
+        }
+    }
+    
+    // 流量配额指标（本月用量和限额）
+    if let Some(tracker) = QUOTA_TRACKER.lock().unwrap().as_ref() {
+        let statuses = tracker.statuses();
+        let quota_labels = |status: &quota::QuotaStatus| {
+            format!(
+                "quota=\"{}\",scope=\"{}\",target=\"{}\",direction=\"{}\"",
+                escape_label(&status.name),
+                status.quota.scope.kind(),
+                escape_label(&status.quota.scope.target()),
+                status.quota.direction.as_str()
+            )
+        };
+        output.push_str("\n# HELP quota_used_bytes Bytes used against the quota in the current billing cycle\n");
+        output.push_str("# TYPE quota_used_bytes gauge\n");
+        for status in &statuses {
+            output.push_str(&format!("quota_used_bytes{{{}}} {}\n", quota_labels(status), status.used_bytes));
+        }
+        output.push_str("\n# HELP quota_limit_bytes Quota limit in bytes per billing cycle\n");
+        output.push_str("# TYPE quota_limit_bytes gauge\n");
+        for status in &statuses {
+            output.push_str(&format!("quota_limit_bytes{{{}}} {}\n", quota_labels(status), status.quota.limit_bytes));
+        }
+    }
+    
+    // 流量异常分数（本周期流量偏离基线均值的标准差倍数）
+    if let Some(detector) = ANOMALY_DETECTOR.lock().unwrap().as_ref() {
+        output.push_str("\n# HELP ip_traffic_anomaly_score Deviation of the last cycle's bytes from the EWMA baseline, in standard deviations\n");
+        output.push_str("# TYPE ip_traffic_anomaly_score gauge\n");
+        for score in detector.scores() {
+            output.push_str(&format!(
+                "ip_traffic_anomaly_score{{scope=\"{}\",target=\"{}\",direction=\"{}\"}} {:.3}\n",
+                score.scope.as_str(),
+                escape_label(&score.target),
+                if score.is_tx { "tx" } else { "rx" },
+                score.score
+            ));
+        }
+    }
+    
+    // 按国家 / 城市 / ASN 汇总的流量指标（包含低于阈值的 IP 和 Top-K 之外的流）
+    let region_stats = REGION_TRAFFIC_STATS.lock().unwrap();
+    let country_series: Vec<(String, &TrafficStats)> = region_stats
+        .country
+        .iter()
+        .map(|(country, traffic)| (format!("country=\"{}\"", escape_label(country)), traffic))
+        .collect();
+    let city_series: Vec<(String, &TrafficStats)> = region_stats
+        .city
+        .iter()
+        .map(|((country, province, city), traffic)| {
+            (
+                format!(
+                    "country=\"{}\",province=\"{}\",city=\"{}\"",
+                    escape_label(country),
+                    escape_label(province),
+                    escape_label(city)
+                ),
+                traffic,
+            )
+        })
+        .collect();
+    let asn_series: Vec<(String, &TrafficStats)> = region_stats
+        .asn
+        .iter()
+        .map(|((asn, isp), traffic)| (format!("asn=\"{}\",isp=\"{}\"", escape_label(asn), escape_label(isp)), traffic))
+        .collect();
+    let region_metrics = [
+        ("country", "country", &country_series),
+        ("city", "city", &city_series),
+        ("asn", "autonomous system", &asn_series),
+    ];
+    for (dimension, description, region_series) in region_metrics {
+        for (direction, help) in [("tx", "transmitted bytes to"), ("rx", "received bytes from")] {
+            let name = format!("ip_traffic_{}_{}_bytes_total", dimension, direction);
+            output.push_str(&format!("\n# HELP {} Total {} remote IPs in the {}, including IPs below the export threshold\n", name, help, description));
+            output.push_str(&format!("# TYPE {} counter\n", name));
+            for (labels, traffic) in region_series.iter() {
+                let bytes = if direction == "tx" { traffic.tx_bytes } else { traffic.rx_bytes };
+                output.push_str(&format!("{}{{{}}} {}\n", name, labels, bytes));
+            }
+        }
+    }
+    drop(region_stats);
+    
+    // 按用户汇总的流量指标（包含低于阈值的 IP）
+    let user_stats = USER_TRAFFIC_STATS.lock().unwrap();
+    
+    output.push_str("\n# HELP ip_traffic_user_tx_bytes_total Total transmitted bytes attributed to local user\n");
+    output.push_str("# TYPE ip_traffic_user_tx_bytes_total counter\n");
+    for (user, traffic) in user_stats.iter() {
         output.push_str(&format!(
-            "ip_traffic_rx_bytes_total{{remote_ip=\"{}\",country=\"{}\",province=\"{}\",city=\"{}\",isp=\"{}\"}} {}\n",
-            ip, 
-            escape_label(&geo_info.country),
-            escape_label(&geo_info.province),
-            escape_label(&geo_info.city),
-            escape_label(&geo_info.isp),
+            "ip_traffic_user_tx_bytes_total{{user=\"{}\"}} {}\n",
+            escape_label(user),
+            traffic.tx_bytes
+        ));
+    }
+    
+    output.push_str("\n# HELP ip_traffic_user_rx_bytes_total Total received bytes attributed to local user\n");
+    output.push_str("# TYPE ip_traffic_user_rx_bytes_total counter\n");
+    for (user, traffic) in user_stats.iter() {
+        output.push_str(&format!(
+            "ip_traffic_user_rx_bytes_total{{user=\"{}\"}} {}\n",
+            escape_label(user),
             traffic.rx_bytes
         ));
     }
@@ -282,6 +881,151 @@ fn get_ip_traffic_metrics(prometheus_export_threshold: u64) -> Result<String, St
     Ok(output)
 }
 
+// 单个流的 Prometheus 标签（远程 IP、地理信息、网卡、本地 IP；采样数据源额外带 sampled 标签）
+// 不带 user 标签：同一远程 IP 可能被多个用户访问，按用户的流量见 ip_traffic_user_*
+fn flow_labels(key: &FlowKey) -> String {
+    let geo_info = get_ip_geo_info(&key.remote_ip);
+    let mut labels = format!(
+        "remote_ip=\"{}\",country=\"{}\",province=\"{}\",city=\"{}\",isp=\"{}\",interface=\"{}\",local_ip=\"{}\"",
+        escape_label(&key.remote_ip),
+        escape_label(&geo_info.country),
+        escape_label(&geo_info.province),
+        escape_label(&geo_info.city),
+        escape_label(&geo_info.isp),
+        escape_label(&key.interface),
+        escape_label(&key.local_ip)
+    );
+    if SAMPLED_DATA.load(Ordering::Relaxed) {
+        labels.push_str(",sampled=\"true\"");
+    }
+    labels
+}
+
+// 去掉 remote_ip 后的汇总标签（地理信息、ASN、网卡、本地 IP）
+fn aggregated_labels(key: &FlowKey) -> String {
+    let geo_info = get_ip_geo_info(&key.remote_ip);
+    let mut labels = format!(
+        "country=\"{}\",province=\"{}\",city=\"{}\",isp=\"{}\",asn=\"{}\",interface=\"{}\",local_ip=\"{}\"",
+        escape_label(&geo_info.country),
+        escape_label(&geo_info.province),
+        escape_label(&geo_info.city),
+        escape_label(&geo_info.isp),
+        escape_label(&geo_info.asn),
+        escape_label(&key.interface),
+        escape_label(&key.local_ip)
+    );
+    if SAMPLED_DATA.load(Ordering::Relaxed) {
+        labels.push_str(",sampled=\"true\"");
+    }
+    labels
+}
+
+// JSON 格式的单个 IP 流量记录
+#[derive(Serialize)]
+struct IpTrafficRecord {
+    remote_ip: String,
+    interface: String,
+    local_ip: String,
+    country: String,
+    province: String,
+    city: String,
+    isp: String,
+    tx_bytes: u64,
+    rx_bytes: u64,
+    tx_packets: u64,
+    rx_packets: u64,
+    // Top-K 模式下累计字节数的误差上界
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_bytes: Option<u64>,
+}
+
+// JSON 格式的 Top-K 之外的流量汇总
+#[derive(Serialize)]
+struct OtherTrafficRecord {
+    evictions: u64,
+    tx_bytes: u64,
+    rx_bytes: u64,
+    tx_packets: u64,
+    rx_packets: u64,
+}
+
+// JSON 格式的单个用户流量记录
+#[derive(Serialize)]
+struct UserTrafficRecord {
+    user: String,
+    tx_bytes: u64,
+    rx_bytes: u64,
+    tx_packets: u64,
+    rx_packets: u64,
+}
+
+#[derive(Serialize)]
+struct TrafficReport {
+    // 流量是否为按采样率估算的值
+    sampled: bool,
+    ips: Vec<IpTrafficRecord>,
+    // 仅 Top-K 模式
+    #[serde(skip_serializing_if = "Option::is_none")]
+    other: Option<OtherTrafficRecord>,
+    users: Vec<UserTrafficRecord>,
+}
+
+async fn traffic_json_handler() -> HttpResponse {
+    HttpResponse::Ok().json(get_traffic_report())
+}
+
+fn get_traffic_report() -> TrafficReport {
+    let stats = IP_TRAFFIC_STATS.lock().unwrap();
+    
+    let ips = stats
+        .iter()
+        .map(|(key, traffic)| {
+            let geo_info = get_ip_geo_info(&key.remote_ip);
+            IpTrafficRecord {
+                remote_ip: key.remote_ip.clone(),
+                interface: key.interface.clone(),
+                local_ip: key.local_ip.clone(),
+                country: geo_info.country,
+                province: geo_info.province,
+                city: geo_info.city,
+                isp: geo_info.isp,
+                tx_bytes: traffic.tx_bytes,
+                rx_bytes: traffic.rx_bytes,
+                tx_packets: traffic.tx_packets,
+                rx_packets: traffic.rx_packets,
+                error_bytes: stats.capacity().map(|_| stats.error(key)),
+            }
+        })
+        .collect();
+    let other = stats.remainder().map(|(traffic, evictions)| OtherTrafficRecord {
+        evictions,
+        tx_bytes: traffic.tx_bytes,
+        rx_bytes: traffic.rx_bytes,
+        tx_packets: traffic.tx_packets,
+        rx_packets: traffic.rx_packets,
+    });
+    
+    let users = USER_TRAFFIC_STATS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(user, traffic)| UserTrafficRecord {
+            user: user.clone(),
+            tx_bytes: traffic.tx_bytes,
+            rx_bytes: traffic.rx_bytes,
+            tx_packets: traffic.tx_packets,
+            rx_packets: traffic.rx_packets,
+        })
+        .collect();
+    
+    TrafficReport {
+        sampled: SAMPLED_DATA.load(Ordering::Relaxed),
+        ips,
+        other,
+        users,
+    }
+}
+
 // 转义 Prometheus 标签值中的特殊字符
 fn escape_label(s: &str) -> String {
     s.replace('\\', "\\\\")
@@ -289,17 +1033,23 @@ fn escape_label(s: &str) -> String {
         .replace('\n', "\\n")
 }
 
-async fn start_prometheus_server(port: u16, prometheus_export_threshold: u64) -> std::io::Result<()> {
-    let app_state = AppState { prometheus_export_threshold };
+async fn start_prometheus_server(port: u16, prometheus_export_threshold: u64, series_limits: SeriesLimits) -> std::io::Result<()> {
+    let app_state = AppState { prometheus_export_threshold, series_limits };
     
     println!("启动 Prometheus Exporter 服务，监听端口: {}", port);
     println!("访问 http://localhost:{}/metrics 获取指标数据", port);
+    println!("访问 http://localhost:{}/api/traffic 获取 JSON 格式的流量数据", port);
+    println!("访问 http://localhost:{}/api/billing 获取 95 计费统计（需要 --billing）", port);
+    println!("访问 http://localhost:{}/api/blocks 获取生效中的处置（需要 --enforce）", port);
     
     HttpServer::new(move || {
         App::new()
             .wrap(Compress::default())
             .app_data(web::Data::new(app_state.clone()))
             .route("/metrics", web::get().to(metrics_handler))
+            .route("/api/traffic", web::get().to(traffic_json_handler))
+            .route("/api/billing", web::get().to(billing_json_handler))
+            .route("/api/blocks", web::get().to(blocks_json_handler))
     })
     .bind(("0.0.0.0", port))?
     .run()
@@ -307,12 +1057,26 @@ async fn start_prometheus_server(port: u16, prometheus_export_threshold: u64) ->
 }
 
 // ==================== 执行单次监控周期 ====================
-fn run_monitor_cycle(monitor: &mut Box<dyn TrafficMonitor>, cycle_info: &str) -> Result<(), String> {
+fn run_monitor_cycle(
+    monitor: &mut Box<dyn TrafficMonitor>,
+    cycle_info: &str,
+    flow_filter: &FlowFilter,
+    series_limits: &SeriesLimits,
+) -> Result<(), String> {
     println!("[{}] 正在采集流量数据...", cycle_info);
     
     match monitor.start() {
         Ok(stats) => {
-            process_connections(&stats)?;
+            let flows = process_connections(&stats, flow_filter, monitor.is_local(), monitor.cycle_duration())?;
+            export_ipfix(&stats, flow_filter);
+            record_trace(&stats);
+            record_billing(&stats, flow_filter);
+            let anomalies = detect_anomalies(&flows);
+            evaluate_alerts(&flows, &anomalies);
+            enforce(&anomalies);
+            reconcile_shaping(&flows);
+            let expired = expire_idle_series(series_limits);
+            prune_evicted_flows(flow_filter, expired);
         }
         Err(e) => {
             eprintln!("监控执行失败: {}", e);
@@ -322,141 +1086,388 @@ fn run_monitor_cycle(monitor: &mut Box<dyn TrafficMonitor>, cycle_info: &str) ->
     Ok(())
 }
 
-// ==================== 带缓存的 PID 查询 ====================
-fn get_pid_for_ip(ip: &str) -> Option<i32> {
-    // 先检查 PID 缓存（1 小时有效期）
-    {
-        let mut cache = PID_CACHE.lock().unwrap();
-        if let Some((cached_pid, timestamp)) = cache.get(ip) {
-            // 检查缓存是否过期（1 小时 = 3600 秒）
-            if timestamp.elapsed().as_secs() < 3600 {
-                return *cached_pid;
-            } else {
-                // 缓存过期，移除旧数据
-                cache.remove(ip);
+// 删除空闲过期的序列的全部成员流（累计流量和最近活跃时间），返回是否删除了流
+fn expire_idle_series(limits: &SeriesLimits) -> bool {
+    if limits.idle_expiry.is_none() {
+        return false;
+    }
+    let mut global_stats = IP_TRAFFIC_STATS.lock().unwrap();
+    let mut last_seen = FLOW_LAST_SEEN.lock().unwrap();
+    let expired: Vec<FlowKey> = group_flow_series(&global_stats, &last_seen, limits)
+        .into_iter()
+        .filter(|s| s.is_idle(limits))
+        .flat_map(|s| s.keys.into_iter().cloned())
+        .collect();
+    for key in &expired {
+        global_stats.remove(key);
+        last_seen.remove(key);
+    }
+    !expired.is_empty()
+}
+
+// Top-K 模式下或有流过期后，把按流或按远程 IP 保存的状态限制在统计中的流，避免扫描等大量新地址导致内存持续增长
+fn prune_evicted_flows(flow_filter: &FlowFilter, flows_removed: bool) {
+    let global_stats = IP_TRAFFIC_STATS.lock().unwrap();
+    if global_stats.capacity().is_none() && !flows_removed {
+        return;
+    }
+    let keys: HashSet<&FlowKey> = global_stats.iter().map(|(key, _)| key).collect();
+    let remote_ips: HashSet<&str> = keys.iter().map(|key| key.remote_ip.as_str()).collect();
+
+    // 同一远程 IP 可能属于多个流（不同网卡或本地地址），只有全部被淘汰后才删除
+    GEO_CACHE.lock().unwrap().retain(|ip, _| remote_ips.contains(ip.as_str()));
+    FLOW_LAST_SEEN.lock().unwrap().retain(|key, _| keys.contains(key));
+    RATE_TRACKER.lock().unwrap().retain(|key| keys.contains(key));
+    if let Some(detector) = ANOMALY_DETECTOR.lock().unwrap().as_mut() {
+        detector.retain_ips(|ip| remote_ips.contains(ip));
+    }
+    // 归属缓存按原始 IP 保存，按聚合后的地址判断
+    retain_owners(|ip| flow_filter.storage_key(ip).is_some_and(|key| remote_ips.contains(key.as_str())));
+}
+
+// ==================== IPFIX 导出 ====================
+fn init_ipfix_exporter(collector: &str, template_refresh: u64) -> Result<(), String> {
+    let exporter = IpfixExporter::new(collector, Duration::from_secs(template_refresh))
+        .map_err(|e| format!("IPFIX 导出器初始化失败: {}", e))?;
+    println!("IPFIX 导出器已启用，采集器: {}", exporter.collector());
+    *IPFIX_EXPORTER.lock().unwrap() = Some(exporter);
+    Ok(())
+}
+
+// 导出原始（未聚合）的流，过滤规则排除的地址不导出
+fn export_ipfix(stats: &HashMap<FlowKey, TrafficStats>, flow_filter: &FlowFilter) {
+    let mut exporter = IPFIX_EXPORTER.lock().unwrap();
+    let Some(exporter) = exporter.as_mut() else {
+        return;
+    };
+    let flows = stats
+        .iter()
+        .filter(|(key, _)| flow_filter.storage_key(&key.remote_ip).is_some());
+    if let Err(e) = exporter.export(flows) {
+        eprintln!("[警告] IPFIX 导出失败: {}", e);
+    }
+}
+
+// ==================== 95 计费 ====================
+fn init_billing(billing_day: u32, targets: &[String], state_path: &str) -> Result<(), String> {
+    let networks = targets
+        .iter()
+        .map(|t| t.parse::<Cidr>().map_err(|e| format!("无效的计费网段 \"{}\": {}", t, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let tracker = BillingTracker::new(billing_day, networks, Some(state_path.to_string()))
+        .map_err(|e| format!("计费统计初始化失败: {}", e))?;
+    let (start, end) = tracker.period();
+    println!(
+        "95 计费统计已启用：计费周期 {} ~ {}，已恢复 {} 个样本（{}）",
+        start.format("%Y-%m-%d"),
+        end.format("%Y-%m-%d"),
+        tracker.restored_samples(),
+        state_path
+    );
+    *BILLING_TRACKER.lock().unwrap() = Some(tracker);
+    Ok(())
+}
+
+// 按原始（未聚合）的流累计，过滤规则排除的地址不计费
+fn record_billing(stats: &HashMap<FlowKey, TrafficStats>, flow_filter: &FlowFilter) {
+    let mut tracker = BILLING_TRACKER.lock().unwrap();
+    let Some(tracker) = tracker.as_mut() else {
+        return;
+    };
+    let flows = stats
+        .iter()
+        .filter(|(key, _)| flow_filter.storage_key(&key.remote_ip).is_some());
+    if let Err(e) = tracker.record(flows) {
+        eprintln!("[警告] 保存计费样本失败: {}", e);
+    }
+}
+
+#[derive(Serialize)]
+struct BillingResponse {
+    period_start: String,
+    period_end: String,
+    targets: Vec<billing::BillingReport>,
+}
+
+async fn billing_json_handler() -> HttpResponse {
+    let tracker = BILLING_TRACKER.lock().unwrap();
+    let Some(tracker) = tracker.as_ref() else {
+        return HttpResponse::NotFound().body("95 计费统计未启用（使用 --billing 开启）");
+    };
+    let (start, end) = tracker.period();
+    HttpResponse::Ok().json(BillingResponse {
+        period_start: start.to_rfc3339(),
+        period_end: end.to_rfc3339(),
+        targets: tracker.reports(),
+    })
+}
+
+// ==================== 流量配额 ====================
+fn init_quota(specs: &[String], reset_day: u32, state_path: &str) -> Result<(), String> {
+    let quotas = specs
+        .iter()
+        .map(|spec| spec.parse::<Quota>())
+        .collect::<Result<Vec<_>, _>>()?;
+    let tracker = QuotaTracker::new(quotas, reset_day, Some(state_path.to_string()))
+        .map_err(|e| format!("配额统计初始化失败: {}", e))?;
+    println!("流量配额已启用（每月 {} 日重置，用量保存到 {}）：", reset_day, state_path);
+    for status in tracker.statuses() {
+        println!("  {} | 已用: {} / {}", status.name, format_bytes(status.used_bytes), format_bytes(status.quota.limit_bytes));
+    }
+    *QUOTA_TRACKER.lock().unwrap() = Some(tracker);
+    Ok(())
+}
+
+// ==================== 告警 ====================
+fn init_alerts(path: &str) -> Result<(), String> {
+    let mut engine = AlertEngine::load(path).map_err(|e| e.to_string())?;
+    println!("告警已启用：{} 条规则，{} 个 webhook（{}）", engine.rule_count(), engine.webhook_count(), path);
+    engine.start();
+    *ALERT_ENGINE.lock().unwrap() = Some(engine);
+    Ok(())
+}
+
+fn evaluate_alerts(flows: &[CycleFlow], anomalies: &[Anomaly]) {
+    let mut engine = ALERT_ENGINE.lock().unwrap();
+    let Some(engine) = engine.as_mut() else {
+        return;
+    };
+    let alert_flows: Vec<AlertFlow> = flows
+        .iter()
+        .map(|flow| {
+            let geo_info = get_ip_geo_info(&flow.key.remote_ip);
+            AlertFlow {
+                remote_ip: flow.key.remote_ip.clone(),
+                country: geo_info.country,
+                country_code: geo_info.country_code,
+                process: flow.process.clone(),
+                traffic: flow.traffic.clone(),
             }
-        }
+        })
+        .collect();
+    let mut alerts = engine.evaluate(&alert_flows);
+    alerts.extend(engine.notify_anomalies(anomalies));
+    for alert in alerts {
+        println!("[告警] [{}] {}: {}", alert.severity, alert.rule, alert.message);
     }
-    
-    // 更新 TCP 连接缓存（每 5 秒刷新一次）
-    let inode = {
-        let mut tcp_cache = TCP_CONNECTIONS_CACHE.lock().unwrap();
-        let now = std::time::Instant::now();
-        
-        // 如果缓存超过 5 秒，重新读取
-        if now.duration_since(tcp_cache.0).as_secs() >= 5 {
-            tcp_cache.1 = build_ip_to_inode_map();
-            tcp_cache.0 = now;
-        }
-        
-        tcp_cache.1.get(ip).copied()
+}
+
+fn stop_alerts() {
+    if let Some(engine) = ALERT_ENGINE.lock().unwrap().as_mut() {
+        engine.stop();
+    }
+}
+
+// ==================== 异常检测 ====================
+fn init_anomaly(alpha: f64, sigma: f64, warmup: u64, min_bytes: &str) -> Result<(), String> {
+    let min_bytes = parse_size(min_bytes)?;
+    let detector = AnomalyDetector::new(alpha, sigma, warmup, min_bytes)?;
+    println!(
+        "流量异常检测已启用：{} 倍标准差，EWMA 系数 {}，学习 {} 个周期，最小超出量 {}",
+        sigma, alpha, warmup, format_bytes(min_bytes)
+    );
+    *ANOMALY_DETECTOR.lock().unwrap() = Some(detector);
+    Ok(())
+}
+
+// 用本周期（聚合后）的流量更新基线，打印并返回检测到的异常
+fn detect_anomalies(flows: &[CycleFlow]) -> Vec<Anomaly> {
+    let mut detector = ANOMALY_DETECTOR.lock().unwrap();
+    let Some(detector) = detector.as_mut() else {
+        return Vec::new();
     };
-    
-    // 如果找到 inode，查询 PID
-    let pid = if let Some(inode) = inode {
-        find_pid_by_inode(inode).map(|p| p as i32)
-    } else {
-        None
+    let anomalies = detector.observe(
+        flows
+            .iter()
+            .map(|flow| (flow.key.remote_ip.as_str(), flow.process.as_deref(), &flow.traffic)),
+    );
+    for anomaly in &anomalies {
+        println!("[异常] {}", anomaly.message());
+    }
+    anomalies
+}
+
+// ==================== 违规 IP 处置 ====================
+fn init_enforcement(cli: &Cli, action: &str) -> Result<(), String> {
+    let action: EnforceAction = action.parse()?;
+    let reasons = cli
+        .enforce_on
+        .iter()
+        .map(|r| r.parse::<BlockReason>())
+        .collect::<Result<Vec<_>, _>>()?;
+    let exempt = cli
+        .enforce_exempt
+        .iter()
+        .map(|net| net.parse::<Cidr>().map_err(|e| format!("无效的豁免网段 \"{}\": {}", net, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let duration = parse_duration(&cli.enforce_duration)?;
+    let rate_bytes = parse_size(&cli.enforce_rate)?;
+    let enforcer = Enforcer::new(action, rate_bytes, duration, reasons, exempt, Some(cli.enforce_state.clone()))
+        .map_err(|e| format!("处置功能初始化失败: {}", e))?;
+    let action_desc = match action {
+        EnforceAction::Drop => "封禁".to_string(),
+        EnforceAction::Limit => format!("限速 {}/s", format_bytes(rate_bytes)),
     };
-    
-    // 保存到缓存，带时间戳
-    {
-        let mut cache = PID_CACHE.lock().unwrap();
-        cache.insert(ip.to_string(), (pid, std::time::Instant::now()));
-    }
-    
-    pid
-}
-
-// 批量读取 /proc/net/tcp，建立 IP -> inode 映射
-fn build_ip_to_inode_map() -> HashMap<String, u32> {
-    use std::net::Ipv4Addr;
-    
-    let mut map = HashMap::new();
-    
-    if let Ok(content) = std::fs::read_to_string("/proc/net/tcp") {
-        for line in content.lines().skip(1) {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 10 {
-                // 解析远程地址
-                if let Some(remote_addr) = parts.get(2) {
-                    if let Some(addr_part) = remote_addr.split(':').next() {
-                        // 将十六进制地址转换为 IP
-                        if let Ok(addr_num) = u32::from_str_radix(addr_part, 16) {
-                            let octets = [
-                                (addr_num & 0xFF) as u8,
-                                ((addr_num >> 8) & 0xFF) as u8,
-                                ((addr_num >> 16) & 0xFF) as u8,
-                                ((addr_num >> 24) & 0xFF) as u8,
-                            ];
-                            let ip = Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]);
-                            
-                            // 解析 inode
-                            if let Ok(inode) = parts[9].parse::<u32>() {
-                                map.insert(ip.to_string(), inode);
-                            }
-                        }
-                    }
+    println!(
+        "违规 IP 处置已启用：{}，持续 {}，已恢复 {} 条处置（{}）",
+        action_desc,
+        cli.enforce_duration,
+        enforcer.blocks().len(),
+        cli.enforce_state
+    );
+    *ENFORCER.lock().unwrap() = Some(enforcer);
+    Ok(())
+}
+
+// 处置配额用尽的 IP / 网段和流量异常的 IP，并清理到期的处置
+fn enforce(anomalies: &[Anomaly]) {
+    let mut enforcer = ENFORCER.lock().unwrap();
+    let Some(enforcer) = enforcer.as_mut() else {
+        return;
+    };
+    match enforcer.expire() {
+        Ok(expired) => {
+            for block in expired {
+                println!("[处置] {} 的处置已到期解除", block.target);
+            }
+        }
+        Err(e) => eprintln!("[警告] 清理到期处置失败: {}", e),
+    }
+
+    let mut candidates: Vec<(Cidr, BlockReason, String)> = Vec::new();
+    if enforcer.handles(BlockReason::Quota) {
+        if let Some(tracker) = QUOTA_TRACKER.lock().unwrap().as_ref() {
+            for status in tracker.statuses() {
+                if status.used_bytes < status.quota.limit_bytes {
+                    continue;
+                }
+                let target = match status.quota.scope {
+                    QuotaScope::Ip(ip) => Cidr::new(ip, if ip.is_ipv4() { 32 } else { 128 }),
+                    QuotaScope::Network(cidr) => Ok(cidr),
+                    _ => continue,
+                };
+                if let Ok(target) = target {
+                    let detail = format!("配额 {} 已用尽（{}）", status.name, format_bytes(status.quota.limit_bytes));
+                    candidates.push((target, BlockReason::Quota, detail));
                 }
             }
         }
     }
-    
-    map
+    if enforcer.handles(BlockReason::Anomaly) {
+        for anomaly in anomalies.iter().filter(|a| a.scope == AnomalyScope::Ip) {
+            if let Ok(target) = anomaly.target.parse::<Cidr>() {
+                candidates.push((target, BlockReason::Anomaly, anomaly.message()));
+            }
+        }
+    }
+
+    for (target, reason, detail) in candidates {
+        match enforcer.block(&target, reason, detail) {
+            Ok(Some(block)) => {
+                let action = match enforcer.action() {
+                    EnforceAction::Drop => "封禁",
+                    EnforceAction::Limit => "限速",
+                };
+                println!(
+                    "[处置] 已{} {} 至 {}：{}",
+                    action,
+                    block.target,
+                    chrono::DateTime::from_timestamp(block.expires, 0)
+                        .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string())
+                        .unwrap_or_default(),
+                    block.detail
+                );
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("[警告] 处置 {} 失败: {}", target, e),
+        }
+    }
 }
 
-// 通过 inode 查找 PID（从 iftop_monitor.rs 移到这里）
-fn find_pid_by_inode(inode: u32) -> Option<u32> {
-    use procfs::process::Process;
-    
-    std::fs::read_dir("/proc")
-        .ok()?
-        .flatten()
-        .find_map(|entry| {
-            let pid = entry.file_name().to_str()?.parse::<u32>().ok()?;
-            Process::new(pid as i32)
-                .ok()?
-                .fd()
-                .ok()?
-                .flatten()
-                .find_map(|fd| match &fd.target {
-                    procfs::process::FDTarget::Socket(socket_inode) => {
-                        if *socket_inode == inode as u64 {
-                            Some(pid)
-                        } else {
-                            None
-                        }
-                    }
-                    _ => None,
-                })
-        })
+async fn blocks_json_handler() -> HttpResponse {
+    let enforcer = ENFORCER.lock().unwrap();
+    let Some(enforcer) = enforcer.as_ref() else {
+        return HttpResponse::NotFound().body("违规 IP 处置未启用（使用 --enforce 开启）");
+    };
+    HttpResponse::Ok().json(enforcer.blocks())
 }
 
-// 根据 PID 获取进程名称（带缓存）
-fn get_process_name(pid: i32) -> Option<String> {
-    // 先检查缓存
-    {
-        let cache = PROCESS_NAME_CACHE.lock().unwrap();
-        if let Some(name) = cache.get(&pid) {
-            return Some(name.clone());
+fn stop_enforcement() {
+    if let Some(enforcer) = ENFORCER.lock().unwrap().as_mut() {
+        if let Err(e) = enforcer.shutdown() {
+            eprintln!("[警告] 删除处置表失败: {}", e);
         }
     }
-    
-    // 从 /proc 读取进程名
-    use procfs::process::Process;
-    let process_name = Process::new(pid)
-        .ok()?
-        .stat()
-        .ok()
-        .map(|stat| stat.comm)?;
-    
-    // 保存到缓存
-    {
-        let mut cache = PROCESS_NAME_CACHE.lock().unwrap();
-        cache.insert(pid, process_name.clone());
+}
+
+// ==================== tc 限速 ====================
+fn init_shaping(specs: &[String], hold: &str) -> Result<(), String> {
+    let policies = specs
+        .iter()
+        .map(|spec| spec.parse::<ShapePolicy>())
+        .collect::<Result<Vec<_>, _>>()?;
+    let hold = parse_duration(hold)?;
+    let shaper = Shaper::new(policies.clone(), hold).map_err(|e| format!("限速功能初始化失败: {}", e))?;
+    println!("tc 限速已启用（速率持续 {} 秒低于阈值后解除）：", hold.as_secs());
+    for policy in &policies {
+        println!(
+            "  {} TX 速率超过 {}/s 时限速到 {}/s",
+            policy.scope.label(),
+            format_bytes(policy.threshold),
+            format_bytes(policy.rate)
+        );
+    }
+    *SHAPER.lock().unwrap() = Some(shaper);
+    Ok(())
+}
+
+// 按本周期（聚合后）的 TX 速率更新限速对象，并同步网卡上的 tc 规则
+fn reconcile_shaping(flows: &[CycleFlow]) {
+    let mut shaper = SHAPER.lock().unwrap();
+    let Some(shaper) = shaper.as_mut() else {
+        return;
+    };
+    let cycle_seconds = RATE_TRACKER.lock().unwrap().last_cycle().as_secs_f64();
+    let events = shaper.reconcile(
+        flows.iter().map(|flow| {
+            (
+                flow.key.interface.as_str(),
+                flow.key.remote_ip.as_str(),
+                flow.process.as_deref(),
+                flow.traffic.tx_bytes,
+            )
+        }),
+        cycle_seconds,
+    );
+    for event in events {
+        println!("[限速] {}", event);
+    }
+}
+
+fn stop_shaping() {
+    if let Some(shaper) = SHAPER.lock().unwrap().as_mut() {
+        if let Err(e) = shaper.shutdown() {
+            eprintln!("[警告] 删除限速队列失败: {}", e);
+        }
+    }
+}
+
+// ==================== 流量记录 ====================
+fn init_trace_writer(path: &str) -> Result<(), String> {
+    let writer = TraceWriter::create(path).map_err(|e| e.to_string())?;
+    println!("流量记录已启用，写入: {}", writer.path());
+    *TRACE_WRITER.lock().unwrap() = Some(writer);
+    Ok(())
+}
+
+fn record_trace(stats: &HashMap<FlowKey, TrafficStats>) {
+    let mut writer = TRACE_WRITER.lock().unwrap();
+    let Some(writer) = writer.as_mut() else {
+        return;
+    };
+    if let Err(e) = writer.write_cycle(stats) {
+        eprintln!("[警告] 写入流量记录失败: {}", e);
     }
-    
-    Some(process_name)
 }
 
 // ==================== 主函数 ====================
@@ -466,19 +1477,110 @@ async fn main() -> Result<(), String> {
     
     let is_permanent = cli.duration == 0;
     
+    // 地址分类规则
+    let address_filter = AddressFilter::from_rules(&cli.include_addr, &cli.exclude_addr)?;
+    
+    // 网段过滤与聚合规则
+    let flow_filter = FlowFilter::new(
+        &cli.filter_include,
+        &cli.filter_exclude,
+        cli.aggregate_v4_prefix,
+        cli.aggregate_v6_prefix,
+    )?;
+    
+    // 本地地址规则
+    let local_nets = cli
+        .local_net
+        .iter()
+        .map(|net| net.parse::<Cidr>().map_err(|e| format!("无效的本地网段 \"{}\": {}", net, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let local_networks = LocalNetworks::new(local_nets);
+    
+    // 网卡选择规则
+    let interfaces = InterfaceSelector::new(cli.iface.clone(), cli.exclude_iface.clone());
+    
+    // 只发送测试告警（验证 webhook 配置）
+    if cli.alert_test {
+        let path = cli.alert_rules.as_deref().unwrap_or_default();
+        return AlertEngine::load(path).map_err(|e| e.to_string())?.send_test();
+    }
+    
     // 创建监控器
     let mut monitor: Box<dyn TrafficMonitor> = match cli.backend.to_lowercase().as_str() {
         "iftop" => {
-            let iface = cli.iface.clone().ok_or("iftop 模式需要指定网卡（-i 参数）")?;
-            Box::new(IftopMonitor::new(iface.clone(), cli.sample_interval))
+            Box::new(IftopMonitor::new(interfaces.clone(), cli.sample_interval, address_filter.clone()))
         }
         "bpftrace" => {
-            Box::new(BpftraceMonitor::new(cli.sample_interval, cli.bpftrace_script.clone()))
+            Box::new(BpftraceMonitor::new(
+                cli.sample_interval,
+                cli.bpftrace_script.clone(),
+                address_filter.clone(),
+                interfaces.clone(),
+            ))
+        }
+        "packet" | "af_packet" => {
+            Box::new(PacketMonitor::new(interfaces.clone(), cli.sample_interval, address_filter.clone()))
         }
+        "pcap" => {
+            let path = cli.pcap_file.clone().ok_or("pcap 模式需要通过 --pcap-file 指定抓包文件")?;
+            Box::new(PcapMonitor::new(
+                path,
+                cli.sample_interval,
+                address_filter.clone(),
+                local_networks.clone(),
+                interfaces.clone(),
+            ))
+        }
+        "conntrack" => {
+            Box::new(ConntrackMonitor::new(cli.sample_interval, address_filter.clone(), local_networks.clone()))
+        }
+        "nftables" | "nft" => {
+            Box::new(NftablesMonitor::new(cli.sample_interval, address_filter.clone(), interfaces.clone()))
+        }
+        "netflow" | "ipfix" => {
+            Box::new(CollectorMonitor::new(
+                "NetFlow/IPFIX",
+                cli.listen.clone().unwrap_or_else(|| "0.0.0.0:2055".to_string()),
+                cli.sample_interval,
+                address_filter.clone(),
+                local_networks.clone(),
+                Box::new(NetflowDecoder::new()),
+            ))
+        }
+        "sflow" => {
+            Box::new(CollectorMonitor::new(
+                "sFlow",
+                cli.listen.clone().unwrap_or_else(|| "0.0.0.0:6343".to_string()),
+                cli.sample_interval,
+                address_filter.clone(),
+                local_networks.clone(),
+                Box::new(SflowDecoder::new()),
+            ))
+        }
+        "mock" => match cli.mock_trace.clone() {
+            Some(path) => {
+                Box::new(MockMonitor::replay(path, cli.sample_interval, address_filter.clone(), interfaces.clone()))
+            }
+            None => {
+                if !(0.0..=1.0).contains(&cli.mock_ipv6_ratio) || !(0.0..=1.0).contains(&cli.mock_burst_prob) {
+                    return Err("--mock-ipv6-ratio 和 --mock-burst-prob 必须在 0~1 之间".to_string());
+                }
+                let config = MockConfig {
+                    remote_ips: cli.mock_ips,
+                    bytes_per_second: cli.mock_rate,
+                    distribution: cli.mock_distribution,
+                    ipv6_ratio: cli.mock_ipv6_ratio,
+                    burst_probability: cli.mock_burst_prob,
+                    seed: cli.mock_seed,
+                };
+                Box::new(MockMonitor::synthetic(config, cli.sample_interval, address_filter.clone(), interfaces.clone()))
+            }
+        },
         _ => {
-            return Err(format!("不支持的后端: {}，请使用 iftop 或 bpftrace", cli.backend));
+            return Err(format!("不支持的后端: {}，请使用 iftop、bpftrace、packet、conntrack、nftables、netflow、sflow、pcap 或 mock", cli.backend));
         }
     };
+    SAMPLED_DATA.store(monitor.is_sampled(), Ordering::SeqCst);
     
     println!("IP 流量监控工具（后端: {}）", monitor.name());
     if is_permanent {
@@ -493,11 +1595,23 @@ async fn main() -> Result<(), String> {
     }
     println!("========================================");
 
-    // 检查 root 权限
-    check_root_permission()?;
+    // 检查 root 权限（离线数据源不需要）
+    if monitor.is_local() {
+        check_root_permission()?;
+    } else if cli.enforce.is_some() {
+        // 模拟、回放和采集器数据源中的地址不是本机流量，不能据此修改本机防火墙
+        return Err(format!("--enforce 只支持本机数据源，当前后端为 {}", monitor.name()));
+    } else if !cli.shape.is_empty() {
+        return Err(format!("--shape 只支持本机数据源，当前后端为 {}", monitor.name()));
+    }
+
+    // Prometheus 序列过期时间（在安装处置表和限速规则之前检查参数）
+    let idle_expiry = cli.prometheus_idle_expiry.as_deref().map(parse_duration).transpose()?;
 
     // 初始化监控器
     monitor.init().map_err(|e| e.to_string())?;
+    // 速率统计从监控开始时计时
+    Lazy::force(&RATE_TRACKER);
 
     // 设置 Ctrl+C 信号处理
     ctrlc::set_handler(|| {
@@ -516,14 +1630,77 @@ async fn main() -> Result<(), String> {
     } else {
         println!("未指定 GeoIP 数据库，将不包含地理位置信息");
     }
+    if let Some(ref asn_path) = cli.geoip_asn_db {
+        if let Err(e) = init_geoip_asn_db(asn_path) {
+            eprintln!("警告: {}", e);
+        }
+    }
+    
+    // 按 Top-K 统计流量（替换精确的按流统计）
+    if let Some(k) = cli.top_k {
+        if k == 0 {
+            return Err("--top-k 必须大于 0".to_string());
+        }
+        *IP_TRAFFIC_STATS.lock().unwrap() = FlowStore::TopK(SpaceSaving::new(k));
+        println!("Top-K 统计已启用：只跟踪累计字节数最大的 {} 个流，其余流量汇总为 other", k);
+    }
+    
+    // 初始化 IPFIX 导出器
+    if let Some(ref collector) = cli.ipfix_collector {
+        init_ipfix_exporter(collector, cli.ipfix_template_refresh)?;
+    }
+    
+    // 初始化 95 计费统计
+    if cli.billing {
+        init_billing(cli.billing_day, &cli.billing_target, &cli.billing_state)?;
+    }
+    
+    // 初始化流量配额
+    if !cli.quota.is_empty() {
+        init_quota(&cli.quota, cli.quota_reset_day, &cli.quota_state)?;
+    }
+    
+    // 初始化告警
+    if let Some(ref path) = cli.alert_rules {
+        init_alerts(path)?;
+    }
     
-    // 启动 Prometheus exporter
+    // 初始化流量异常检测
+    if cli.anomaly {
+        init_anomaly(cli.anomaly_alpha, cli.anomaly_sigma, cli.anomaly_warmup, &cli.anomaly_min_bytes)?;
+    }
+    
+    // 初始化流量记录
+    if let Some(ref path) = cli.record_trace {
+        init_trace_writer(path)?;
+    }
+    
+    // 初始化违规 IP 处置
+    if let Some(ref action) = cli.enforce {
+        init_enforcement(&cli, action)?;
+    }
+    
+    // 初始化 tc 限速（失败时删除已安装的处置表）
+    if !cli.shape.is_empty() {
+        if let Err(e) = init_shaping(&cli.shape, &cli.shape_hold) {
+            stop_enforcement();
+            return Err(e);
+        }
+    }
+    
+    // 启动 Prometheus exporter（序列过期的清理在监控周期中进行）
+    let series_limits = SeriesLimits {
+        max_series: cli.prometheus_max_series,
+        drop_remote_ip: cli.prometheus_drop_remote_ip,
+        idle_expiry,
+    };
     if let Some(port) = cli.prometheus_port {
         let prometheus_export_threshold = cli.prometheus_export_threshold;
+        let series_limits = series_limits.clone();
         thread::spawn(move || {
             let rt = tokio::runtime::Runtime::new().unwrap();
             rt.block_on(async move {
-                if let Err(e) = start_prometheus_server(port, prometheus_export_threshold).await {
+                if let Err(e) = start_prometheus_server(port, prometheus_export_threshold, series_limits).await {
                     eprintln!("Prometheus exporter 启动失败: {}", e);
                 }
             });
@@ -531,11 +1708,34 @@ async fn main() -> Result<(), String> {
         thread::sleep(Duration::from_millis(500));
     }
     
-    // 运行监控逻辑
+    // 运行监控逻辑（出错时也要先清理处置表和限速规则再返回）
+    let result = run_monitor_loop(&mut monitor, &cli, is_permanent, &flow_filter, &series_limits);
+    
+    // 停止监控器
+    if let Err(e) = monitor.stop() {
+        eprintln!("[警告] 停止监控器失败: {}", e);
+    }
+    stop_alerts();
+    stop_enforcement();
+    stop_shaping();
+    result?;
+    
+    print_summary();
+    
+    Ok(())
+}
+
+fn run_monitor_loop(
+    monitor: &mut Box<dyn TrafficMonitor>,
+    cli: &Cli,
+    is_permanent: bool,
+    flow_filter: &FlowFilter,
+    series_limits: &SeriesLimits,
+) -> Result<(), String> {
     if is_permanent {
         let mut cycle = 1;
-        while RUNNING.load(Ordering::SeqCst) {
-            run_monitor_cycle(&mut monitor, &format!("周期 {}", cycle))?;
+        while RUNNING.load(Ordering::SeqCst) && !monitor.is_finished() {
+            run_monitor_cycle(monitor, &format!("周期 {}", cycle), flow_filter, series_limits)?;
             cycle += 1;
         }
         println!("监控已停止");
@@ -547,60 +1747,119 @@ async fn main() -> Result<(), String> {
                 println!("\n监控提前终止");
                 break;
             }
-            run_monitor_cycle(&mut monitor, &format!("{}/{}", cycle, cycles))?;
+            if monitor.is_finished() {
+                println!("数据源已读完");
+                break;
+            }
+            run_monitor_cycle(monitor, &format!("{}/{}", cycle, cycles), flow_filter, series_limits)?;
         }
         
         println!("监控完成");
     }
-    
-    // 停止监控器
-    monitor.stop().map_err(|e| e.to_string())?;
-    
     Ok(())
 }
 
 // ==================== 处理连接数据的辅助函数 ====================
-fn process_connections(connections: &HashMap<String, TrafficStats>) -> Result<(), String> {
-    if !connections.is_empty() {
+fn process_connections(
+    connections: &HashMap<FlowKey, TrafficStats>,
+    flow_filter: &FlowFilter,
+    attribute_owner: bool,
+    cycle_duration: Option<Duration>,
+) -> Result<Vec<CycleFlow>, String> {
+    // 按过滤规则丢弃地址，并按聚合前缀把远程地址归并为网段；进程和用户按原始 IP 查询（仅本机数据源）
+    let mut merged: HashMap<FlowKey, (TrafficStats, IpOwner)> = HashMap::new();
+    let mut quota_tracker = QUOTA_TRACKER.lock().unwrap();
+    for (flow, traffic) in connections.iter() {
+        if traffic.tx_bytes == 0 && traffic.rx_bytes == 0 {
+            continue;
+        }
+        let Some(remote_key) = flow_filter.storage_key(&flow.remote_ip) else {
+            continue;
+        };
+        let owner = if attribute_owner { get_owner_for_ip(&flow.remote_ip) } else { IpOwner::default() };
+        let key = FlowKey::new(flow.interface.as_str(), flow.local_ip.as_str(), remote_key);
+        let entry = merged.entry(key).or_insert_with(|| (TrafficStats::default(), owner));
+        entry.0.add(traffic);
+        if entry.1.pid.is_none() {
+            entry.1 = owner;
+        }
+        
+        let user = owner.uid.map(get_username).unwrap_or_else(|| UNKNOWN_USER.to_string());
+        USER_TRAFFIC_STATS.lock().unwrap().entry(user).or_default().add(traffic);
+        
+        if let Some(tracker) = quota_tracker.as_mut() {
+            let process = if tracker.needs_process() { owner.pid.and_then(get_process_name) } else { None };
+            tracker.record(&flow.remote_ip, process.as_deref(), traffic);
+        }
+    }
+    let quota_warnings = match quota_tracker.as_mut().map(|tracker| tracker.check()) {
+        Some(Ok(warnings)) => warnings,
+        Some(Err(e)) => {
+            eprintln!("[警告] 保存配额用量失败: {}", e);
+            Vec::new()
+        }
+        None => Vec::new(),
+    };
+    drop(quota_tracker);
+    
+    // 记录本周期增量，用于计算滑动窗口速率
+    let cycle_seconds = {
+        let mut rate_tracker = RATE_TRACKER.lock().unwrap();
+        rate_tracker.record(
+            &merged.iter().map(|(key, (traffic, _))| (key.clone(), traffic.clone())).collect(),
+            cycle_duration,
+        );
+        rate_tracker.last_cycle().as_secs_f64().max(1.0)
+    };
+    
+    let mut cycle_flows = Vec::with_capacity(merged.len());
+    if !merged.is_empty() {
         println!("[{}] 流量统计：", Local::now().format("%H:%M:%S"));
         
         // 获取全局统计存储的锁
         let mut global_stats = IP_TRAFFIC_STATS.lock().unwrap();
         
         // 按流量排序
-        let mut sorted: Vec<_> = connections.iter().collect();
-        sorted.sort_by(|a, b| (b.1.tx_bytes + b.1.rx_bytes).cmp(&(a.1.tx_bytes + a.1.rx_bytes)));
+        let mut sorted: Vec<_> = merged.iter().collect();
+        sorted.sort_by_key(|(_, (traffic, _))| std::cmp::Reverse(traffic.total()));
         
         // 批量构建输出字符串，减少系统调用
         let mut output = String::with_capacity(sorted.len() * 100);
         
-        for (ip, traffic) in sorted.iter() {
-            if traffic.tx_bytes > 0 || traffic.rx_bytes > 0 {
-                let pid = get_pid_for_ip(ip);
-                let process_name = pid.and_then(|p| get_process_name(p));
-                
-                // 累加到全局统计
-                let global_entry = global_stats.entry(ip.to_string()).or_insert_with(TrafficStats::default);
-                global_entry.tx_bytes += traffic.tx_bytes;
-                global_entry.rx_bytes += traffic.rx_bytes;
-                global_entry.tx_packets += traffic.tx_packets;
-                global_entry.rx_packets += traffic.rx_packets;
-                
-                // 添加到输出字符串
-                use std::fmt::Write;
-                let process_info = match (pid, process_name) {
-                    (Some(p), Some(name)) => format!("{} ({})", p, name),
-                    (Some(p), None) => format!("{}", p),
-                    _ => "0".to_string(),
-                };
-                let _ = write!(output, "  IP: {} | TX(上行): {} | RX(下行): {} | 累计TX: {} | 累计RX: {} | PID: {}\n",
-                       ip,
-                       format_bytes(traffic.tx_bytes),
-                       format_bytes(traffic.rx_bytes),
-                       format_bytes(global_entry.tx_bytes),
-                       format_bytes(global_entry.rx_bytes),
-                       process_info);
-            }
+        for (key, (traffic, owner)) in sorted.iter() {
+            let process_name = owner.pid.and_then(get_process_name);
+            let user = owner.uid.map(get_username).unwrap_or_else(|| UNKNOWN_USER.to_string());
+            
+            // 累加到全局统计；Top-K 模式下被淘汰流的状态在周期结束时统一清理
+            let (global_entry, _) = global_stats.add(key, traffic);
+            FLOW_LAST_SEEN.lock().unwrap().insert((*key).clone(), Instant::now());
+            let geo_info = get_ip_geo_info(&key.remote_ip);
+            REGION_TRAFFIC_STATS.lock().unwrap().add(&geo_info, traffic);
+            cycle_flows.push(CycleFlow {
+                key: (*key).clone(),
+                traffic: traffic.clone(),
+                process: process_name.clone(),
+            });
+            
+            // 添加到输出字符串
+            use std::fmt::Write;
+            let process_info = match (owner.pid, process_name) {
+                (Some(p), Some(name)) => format!("{} ({})", p, name),
+                (Some(p), None) => format!("{}", p),
+                _ => "0".to_string(),
+            };
+            let _ = writeln!(output, "  IP: {} | 网卡: {} | 本地IP: {} | TX(上行): {} ({}) | RX(下行): {} ({}) | 累计TX: {} | 累计RX: {} | PID: {} | 用户: {}",
+                   key.remote_ip,
+                   key.interface,
+                   key.local_ip,
+                   format_bytes(traffic.tx_bytes),
+                   format_bps(traffic.tx_bytes as f64 / cycle_seconds),
+                   format_bytes(traffic.rx_bytes),
+                   format_bps(traffic.rx_bytes as f64 / cycle_seconds),
+                   format_bytes(global_entry.tx_bytes),
+                   format_bytes(global_entry.rx_bytes),
+                   process_info,
+                   user);
         }
         
         // 一次性输出所有内容
@@ -609,5 +1868,50 @@ fn process_connections(connections: &HashMap<String, TrafficStats>) -> Result<()
         println!("[{}] 无活跃网络连接", Local::now().format("%H:%M:%S"));
     }
     
-    Ok(())
+    for warning in quota_warnings {
+        println!("[配额告警] {}", warning);
+    }
+    
+    Ok(cycle_flows)
+}
+
+// ==================== 汇总报告 ====================
+fn print_summary() {
+    let global_stats = IP_TRAFFIC_STATS.lock().unwrap();
+    let user_stats = USER_TRAFFIC_STATS.lock().unwrap();
+    
+    let mut total = TrafficStats::default();
+    for traffic in global_stats.iter().map(|(_, t)| t).chain(global_stats.remainder().map(|(t, _)| t)) {
+        total.add(traffic);
+    }
+    
+    println!("========================================");
+    println!("流量汇总：共 {} 条记录，累计TX: {}，累计RX: {}",
+             global_stats.len(), format_bytes(total.tx_bytes), format_bytes(total.rx_bytes));
+    if let Some((other, evictions)) = global_stats.remainder() {
+        println!("Top-K 之外的流量（{} 次淘汰）：累计TX: {}，累计RX: {}",
+                 evictions, format_bytes(other.tx_bytes), format_bytes(other.rx_bytes));
+    }
+    
+    let mut interface_stats: HashMap<&str, TrafficStats> = HashMap::new();
+    for (key, traffic) in global_stats.iter() {
+        interface_stats.entry(key.interface.as_str()).or_default().add(traffic);
+    }
+    let mut sorted: Vec<_> = interface_stats.into_iter().collect();
+    sorted.sort_by_key(|(_, traffic)| std::cmp::Reverse(traffic.total()));
+    
+    println!("按网卡统计：");
+    for (interface, traffic) in sorted {
+        println!("  网卡: {} | 累计TX: {} | 累计RX: {}",
+                 interface, format_bytes(traffic.tx_bytes), format_bytes(traffic.rx_bytes));
+    }
+    
+    let mut sorted: Vec<_> = user_stats.iter().collect();
+    sorted.sort_by_key(|(_, traffic)| std::cmp::Reverse(traffic.total()));
+    
+    println!("按用户统计：");
+    for (user, traffic) in sorted {
+        println!("  用户: {} | 累计TX: {} | 累计RX: {}",
+                 user, format_bytes(traffic.tx_bytes), format_bytes(traffic.rx_bytes));
+    }
 }
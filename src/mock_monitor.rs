@@ -0,0 +1,437 @@
+use crate::address_filter::AddressFilter;
+use crate::monitor::{FlowKey, InterfaceSelector, TrafficMonitor, TrafficStats};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// 模拟流量使用的本地地址
+const MOCK_LOCAL_V4: &str = "192.168.1.100";
+const MOCK_LOCAL_V6: &str = "fd00::100";
+const MOCK_INTERFACE: &str = "eth0";
+
+// 估算包数使用的平均包长
+const AVG_TX_PACKET_SIZE: u64 = 400;
+const AVG_RX_PACKET_SIZE: u64 = 1200;
+
+// 突发流量的倍数范围和持续周期数
+const BURST_MULTIPLIER: (f64, f64) = (5.0, 20.0);
+const BURST_MAX_CYCLES: u32 = 3;
+
+/// 远程地址的流量分布
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Distribution {
+    /// 每个地址的流量期望相同
+    Uniform,
+    /// 第 k 个地址的流量与 1/k 成正比（少数地址占大部分流量）
+    Zipf,
+}
+
+impl std::str::FromStr for Distribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "uniform" => Ok(Self::Uniform),
+            "zipf" => Ok(Self::Zipf),
+            _ => Err(format!("不支持的流量分布: {}，请使用 uniform 或 zipf", s)),
+        }
+    }
+}
+
+/// 合成流量的参数
+#[derive(Debug, Clone)]
+pub struct MockConfig {
+    pub remote_ips: usize,
+    pub bytes_per_second: u64,
+    pub distribution: Distribution,
+    pub ipv6_ratio: f64,
+    pub burst_probability: f64,
+    pub seed: Option<u64>,
+}
+
+/// 流量记录文件中的一条流
+#[derive(Debug, Serialize, Deserialize)]
+struct TraceFlow {
+    interface: String,
+    local_ip: String,
+    remote_ip: String,
+    #[serde(default)]
+    tx_bytes: u64,
+    #[serde(default)]
+    rx_bytes: u64,
+    #[serde(default)]
+    tx_packets: u64,
+    #[serde(default)]
+    rx_packets: u64,
+}
+
+/// 流量记录文件中的一个周期（JSON Lines，每行一个周期）
+#[derive(Debug, Serialize, Deserialize)]
+struct TraceCycle {
+    #[serde(default)]
+    timestamp: u64,
+    flows: Vec<TraceFlow>,
+}
+
+/// 把每个周期采集到的原始流量写入记录文件，供 mock 后端回放
+pub struct TraceWriter {
+    path: String,
+    writer: BufWriter<File>,
+}
+
+impl TraceWriter {
+    pub fn create(path: &str) -> Result<Self, Box<dyn Error>> {
+        let file = File::create(path).map_err(|e| format!("无法创建流量记录文件 {}: {}", path, e))?;
+        Ok(Self {
+            path: path.to_string(),
+            writer: BufWriter::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn write_cycle(&mut self, stats: &HashMap<FlowKey, TrafficStats>) -> Result<(), Box<dyn Error>> {
+        let mut flows: Vec<TraceFlow> = stats
+            .iter()
+            .map(|(key, traffic)| TraceFlow {
+                interface: key.interface.clone(),
+                local_ip: key.local_ip.clone(),
+                remote_ip: key.remote_ip.clone(),
+                tx_bytes: traffic.tx_bytes,
+                rx_bytes: traffic.rx_bytes,
+                tx_packets: traffic.tx_packets,
+                rx_packets: traffic.rx_packets,
+            })
+            .collect();
+        flows.sort_by(|a, b| a.remote_ip.cmp(&b.remote_ip));
+        let cycle = TraceCycle {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            flows,
+        };
+        serde_json::to_writer(&mut self.writer, &cycle)?;
+        self.writer.write_all(b"\n")?;
+        // 每个周期落盘，进程被强制结束时也能保留已记录的数据
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// 合成流量中的一个远程地址
+struct MockPeer {
+    interface: String,
+    local_ip: &'static str,
+    remote_ip: String,
+    weight: f64,
+    // 接收流量占总流量的比例
+    rx_ratio: f64,
+    burst_cycles: u32,
+    burst_multiplier: f64,
+}
+
+/// xorshift64* 伪随机数生成器（演示用途，不需要密码学强度）
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// [0, 1) 区间的均匀分布
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn range(&mut self, (low, high): (f64, f64)) -> f64 {
+        low + (high - low) * self.next_f64()
+    }
+}
+
+/// 数据来源：合成流量或回放记录文件
+enum Source {
+    Synthetic { config: MockConfig, peers: Vec<MockPeer>, rng: Rng },
+    Trace { path: String, lines: Option<std::io::Lines<BufReader<File>>>, line_number: usize },
+}
+
+/// 模拟流量监控器，用于演示和测试（不需要 root 权限和 bpftrace / iftop）
+///
+/// 合成模式按配置生成 N 个远程地址的流量（可选 IPv4/IPv6 比例、分布和随机突发）；
+/// 回放模式按周期读取 --record-trace 生成的 JSON Lines 记录文件，读完后 is_finished() 返回 true。
+pub struct MockMonitor {
+    sample_interval: u32,
+    address_filter: AddressFilter,
+    interfaces: InterfaceSelector,
+    source: Source,
+    finished: bool,
+    last_collect: Option<Instant>,
+    // 回放时上一个周期的记录时间戳（秒）
+    last_trace_timestamp: Option<u64>,
+    // 上一次 start() 返回的流量覆盖的时长
+    cycle_duration: Option<Duration>,
+}
+
+impl MockMonitor {
+    pub fn synthetic(
+        config: MockConfig,
+        sample_interval: u32,
+        address_filter: AddressFilter,
+        interfaces: InterfaceSelector,
+    ) -> Self {
+        let seed = config.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(1)
+        });
+        Self::with_source(
+            Source::Synthetic { config, peers: Vec::new(), rng: Rng::new(seed) },
+            sample_interval,
+            address_filter,
+            interfaces,
+        )
+    }
+
+    pub fn replay(path: String, sample_interval: u32, address_filter: AddressFilter, interfaces: InterfaceSelector) -> Self {
+        Self::with_source(
+            Source::Trace { path, lines: None, line_number: 0 },
+            sample_interval,
+            address_filter,
+            interfaces,
+        )
+    }
+
+    fn with_source(source: Source, sample_interval: u32, address_filter: AddressFilter, interfaces: InterfaceSelector) -> Self {
+        Self {
+            sample_interval,
+            address_filter,
+            interfaces,
+            source,
+            finished: false,
+            last_collect: None,
+            last_trace_timestamp: None,
+            cycle_duration: None,
+        }
+    }
+
+    /// 生成参与统计的远程地址（默认规则下为公网地址）
+    fn generate_peers(
+        config: &MockConfig,
+        rng: &mut Rng,
+        address_filter: &AddressFilter,
+        interfaces: &[String],
+    ) -> Result<Vec<MockPeer>, Box<dyn Error>> {
+        let mut seen = HashSet::new();
+        let mut peers = Vec::with_capacity(config.remote_ips);
+        let max_attempts = config.remote_ips * 1000;
+        let mut attempts = 0;
+
+        while peers.len() < config.remote_ips {
+            attempts += 1;
+            if attempts > max_attempts {
+                return Err("无法生成足够的远程地址，请检查 --include-addr / --exclude-addr 规则".into());
+            }
+            let (remote, local_ip) = if rng.next_f64() < config.ipv6_ratio {
+                // 全球单播地址 2000::/3
+                let bits = (rng.next_u64() as u128) << 64 | rng.next_u64() as u128;
+                let addr = (bits & !(0b111u128 << 125)) | (0b001u128 << 125);
+                (IpAddr::V6(Ipv6Addr::from(addr)), MOCK_LOCAL_V6)
+            } else {
+                (IpAddr::V4(Ipv4Addr::from(rng.next_u64() as u32)), MOCK_LOCAL_V4)
+            };
+            if !address_filter.is_allowed(&remote) || !seen.insert(remote) {
+                continue;
+            }
+
+            let rank = peers.len() + 1;
+            let weight = match config.distribution {
+                Distribution::Uniform => 1.0,
+                Distribution::Zipf => 1.0 / rank as f64,
+            };
+            peers.push(MockPeer {
+                interface: interfaces[peers.len() % interfaces.len()].clone(),
+                local_ip,
+                remote_ip: remote.to_string(),
+                weight,
+                rx_ratio: rng.range((0.5, 0.95)),
+                burst_cycles: 0,
+                burst_multiplier: 1.0,
+            });
+        }
+        Ok(peers)
+    }
+
+    /// 生成一个周期的合成流量
+    fn generate_cycle(config: &MockConfig, peers: &mut [MockPeer], rng: &mut Rng, interval: u32) -> HashMap<FlowKey, TrafficStats> {
+        let total_weight: f64 = peers.iter().map(|p| p.weight).sum();
+        let cycle_bytes = config.bytes_per_second as f64 * interval as f64 * rng.range((0.7, 1.3));
+
+        let mut stats = HashMap::new();
+        for peer in peers.iter_mut() {
+            if peer.burst_cycles == 0 && rng.next_f64() < config.burst_probability {
+                peer.burst_cycles = 1 + (rng.next_u64() % BURST_MAX_CYCLES as u64) as u32;
+                peer.burst_multiplier = rng.range(BURST_MULTIPLIER);
+            }
+            let multiplier = if peer.burst_cycles > 0 {
+                peer.burst_cycles -= 1;
+                peer.burst_multiplier
+            } else {
+                1.0
+            };
+
+            let bytes = cycle_bytes * peer.weight / total_weight * rng.range((0.5, 1.5)) * multiplier;
+            let rx_bytes = (bytes * peer.rx_ratio) as u64;
+            let tx_bytes = (bytes * (1.0 - peer.rx_ratio)) as u64;
+            if tx_bytes == 0 && rx_bytes == 0 {
+                continue;
+            }
+            stats.insert(
+                FlowKey::new(peer.interface.as_str(), peer.local_ip, peer.remote_ip.as_str()),
+                TrafficStats {
+                    tx_bytes,
+                    rx_bytes,
+                    tx_packets: tx_bytes.div_ceil(AVG_TX_PACKET_SIZE),
+                    rx_packets: rx_bytes.div_ceil(AVG_RX_PACKET_SIZE),
+                },
+            );
+        }
+        stats
+    }
+
+    /// 读取记录文件中的下一个周期，文件读完时返回 None
+    fn next_trace_cycle(&mut self) -> Result<Option<HashMap<FlowKey, TrafficStats>>, Box<dyn Error>> {
+        let Source::Trace { path, lines, line_number } = &mut self.source else {
+            return Ok(None);
+        };
+        let Some(lines) = lines.as_mut() else {
+            return Ok(None);
+        };
+
+        for line in lines.by_ref() {
+            *line_number += 1;
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let cycle: TraceCycle = serde_json::from_str(&line)
+                .map_err(|e| format!("流量记录文件 {} 第 {} 行格式错误: {}", path, line_number, e))?;
+
+            // 周期时长取相邻两条记录的时间差（记录中没有时间戳或为第一条时使用采样间隔）
+            let interval = Duration::from_secs(self.sample_interval as u64);
+            self.cycle_duration = Some(match self.last_trace_timestamp {
+                Some(last) if cycle.timestamp > last => Duration::from_secs(cycle.timestamp - last),
+                _ => interval,
+            });
+            if cycle.timestamp > 0 {
+                self.last_trace_timestamp = Some(cycle.timestamp);
+            }
+
+            let mut stats: HashMap<FlowKey, TrafficStats> = HashMap::new();
+            for flow in cycle.flows {
+                if !self.interfaces.matches(&flow.interface) || !self.address_filter.is_allowed_str(&flow.remote_ip) {
+                    continue;
+                }
+                stats
+                    .entry(FlowKey::new(flow.interface, flow.local_ip, flow.remote_ip))
+                    .or_default()
+                    .add(&TrafficStats {
+                        tx_bytes: flow.tx_bytes,
+                        rx_bytes: flow.rx_bytes,
+                        tx_packets: flow.tx_packets,
+                        rx_packets: flow.rx_packets,
+                    });
+            }
+            return Ok(Some(stats));
+        }
+        Ok(None)
+    }
+}
+
+impl TrafficMonitor for MockMonitor {
+    fn init(&mut self) -> Result<(), Box<dyn Error>> {
+        match &mut self.source {
+            Source::Synthetic { config, peers, rng } => {
+                // 指定了网卡列表时把远程地址分散到这些网卡上
+                let interfaces: Vec<String> = if self.interfaces.includes().is_empty() {
+                    vec![MOCK_INTERFACE.to_string()]
+                } else {
+                    self.interfaces.includes().to_vec()
+                };
+                *peers = Self::generate_peers(config, rng, &self.address_filter, &interfaces)?;
+                println!(
+                    "mock 监控器初始化成功：{} 个远程地址，平均 {} 字节/秒，分布 {:?}",
+                    peers.len(),
+                    config.bytes_per_second,
+                    config.distribution
+                );
+            }
+            Source::Trace { path, lines, .. } => {
+                let file = File::open(&*path).map_err(|e| format!("无法打开流量记录文件 {}: {}", path, e))?;
+                *lines = Some(BufReader::new(file).lines());
+                println!("mock 监控器初始化成功，回放流量记录: {}", path);
+            }
+        }
+        self.last_collect = Some(Instant::now());
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<HashMap<FlowKey, TrafficStats>, Box<dyn Error>> {
+        // 等待到本采样周期结束
+        let interval = Duration::from_secs(self.sample_interval as u64);
+        if let Some(last) = self.last_collect {
+            let elapsed = last.elapsed();
+            if elapsed < interval {
+                thread::sleep(interval - elapsed);
+            }
+        }
+        self.last_collect = Some(Instant::now());
+
+        if let Source::Synthetic { config, peers, rng } = &mut self.source {
+            self.cycle_duration = Some(interval);
+            return Ok(Self::generate_cycle(config, peers, rng, self.sample_interval));
+        }
+        match self.next_trace_cycle()? {
+            Some(stats) => Ok(stats),
+            None => {
+                self.finished = true;
+                self.cycle_duration = Some(Duration::ZERO);
+                Ok(HashMap::new())
+            }
+        }
+    }
+
+    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Source::Trace { lines, .. } = &mut self.source {
+            *lines = None;
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn cycle_duration(&self) -> Option<Duration> {
+        self.cycle_duration
+    }
+
+    fn is_local(&self) -> bool {
+        false
+    }
+}
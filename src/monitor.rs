@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::error::Error;
+use std::time::Duration;
 
 /// 流量统计数据结构
 #[derive(Debug, Clone, Default)]
@@ -10,20 +11,128 @@ pub struct TrafficStats {
     pub rx_packets: u64,    // 接收数据包数
 }
 
+impl TrafficStats {
+    /// 累加另一份统计（饱和加法，采集器收到的计数来自网络，可能任意大）
+    pub fn add(&mut self, other: &TrafficStats) {
+        self.tx_bytes = self.tx_bytes.saturating_add(other.tx_bytes);
+        self.rx_bytes = self.rx_bytes.saturating_add(other.rx_bytes);
+        self.tx_packets = self.tx_packets.saturating_add(other.tx_packets);
+        self.rx_packets = self.rx_packets.saturating_add(other.rx_packets);
+    }
+
+    /// TX + RX 字节数（饱和加法）
+    pub fn total(&self) -> u64 {
+        self.tx_bytes.saturating_add(self.rx_bytes)
+    }
+}
+
+/// 流量统计的键：网卡 + 本地 IP + 远程 IP
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FlowKey {
+    pub interface: String,  // 网卡名，无法确定时为 ANY_INTERFACE
+    pub local_ip: String,   // 本地 IP，无法确定时为 ANY_ADDRESS
+    pub remote_ip: String,  // 远程 IP（或聚合后的网段）
+}
+
+/// 无法确定网卡时使用的网卡名
+pub const ANY_INTERFACE: &str = "any";
+
+/// 无法确定本地地址时使用的地址
+pub const ANY_ADDRESS: &str = "any";
+
+impl FlowKey {
+    pub fn new(
+        interface: impl Into<String>,
+        local_ip: impl Into<String>,
+        remote_ip: impl Into<String>,
+    ) -> Self {
+        Self {
+            interface: interface.into(),
+            local_ip: local_ip.into(),
+            remote_ip: remote_ip.into(),
+        }
+    }
+}
+
+/// 网卡选择规则：指定网卡列表，或除排除列表外的全部网卡
+#[derive(Debug, Clone, Default)]
+pub struct InterfaceSelector {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl InterfaceSelector {
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> Self {
+        Self { include, exclude }
+    }
+
+    /// 指定的网卡列表（为空表示全部网卡）
+    pub fn includes(&self) -> &[String] {
+        &self.include
+    }
+
+    /// 排除的网卡列表
+    pub fn excludes(&self) -> &[String] {
+        &self.exclude
+    }
+
+    /// 判断网卡是否需要监控
+    pub fn matches(&self, interface: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|i| i == interface))
+            && !self.exclude.iter().any(|i| i == interface)
+    }
+
+    /// 列出需要监控的网卡（未指定网卡列表时读取 /sys/class/net）
+    pub fn resolve(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut interfaces: Vec<String> = if self.include.is_empty() {
+            std::fs::read_dir("/sys/class/net")?
+                .flatten()
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .collect()
+        } else {
+            self.include.clone()
+        };
+        interfaces.retain(|i| self.matches(i));
+        interfaces.sort();
+        Ok(interfaces)
+    }
+}
+
 /// 流量监控器接口
 pub trait TrafficMonitor: Send + Sync {
     /// 初始化监控器
     fn init(&mut self) -> Result<(), Box<dyn Error>>;
     
     /// 开始监控（阻塞调用）
-    /// 返回每个 IP 的流量统计
-    fn start(&mut self) -> Result<HashMap<String, TrafficStats>, Box<dyn Error>>;
+    /// 返回每个网卡上每个远程 IP 的流量统计
+    fn start(&mut self) -> Result<HashMap<FlowKey, TrafficStats>, Box<dyn Error>>;
     
     /// 停止监控
     fn stop(&mut self) -> Result<(), Box<dyn Error>>;
     
     /// 获取监控器名称
     fn name(&self) -> &str;
+
+    /// 数据源是否已经读完（离线回放结束后返回 true，实时监控始终为 false）
+    fn is_finished(&self) -> bool {
+        false
+    }
+
+    /// 流量是否来自本机：本机数据源需要 root 权限，并按本机进程关联 PID 和用户
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    /// 上一次 start() 返回的流量覆盖的时长（数据源自己的时间，例如回放时的抓包时间）；
+    /// 返回 None 时按墙上时间计算
+    fn cycle_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    /// 流量是否为按采样率估算的值（sFlow 等采样数据源）
+    fn is_sampled(&self) -> bool {
+        false
+    }
 }
 
 /// 格式化字节数显示
@@ -39,3 +148,17 @@ pub fn format_bytes(bytes: u64) -> String {
         format!("{:.0} B", bytes)
     }
 }
+
+/// 格式化速率显示（字节/秒 -> 比特/秒，十进制单位）
+pub fn format_bps(bytes_per_second: f64) -> String {
+    let bits = bytes_per_second * 8.0;
+    if bits >= 1e9 {
+        format!("{:.2} Gbps", bits / 1e9)
+    } else if bits >= 1e6 {
+        format!("{:.2} Mbps", bits / 1e6)
+    } else if bits >= 1e3 {
+        format!("{:.2} Kbps", bits / 1e3)
+    } else {
+        format!("{:.0} bps", bits)
+    }
+}
@@ -0,0 +1,561 @@
+use crate::flow_collector::{DatagramDecoder, FlowRecord};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// 报文版本号
+const NETFLOW_V5: u16 = 5;
+const NETFLOW_V9: u16 = 9;
+pub const IPFIX_VERSION: u16 = 10;
+
+const V5_HEADER_LEN: usize = 24;
+const V5_RECORD_LEN: usize = 48;
+const V9_HEADER_LEN: usize = 20;
+pub const IPFIX_HEADER_LEN: usize = 16;
+const SET_HEADER_LEN: usize = 4;
+
+// 模板集合 ID
+const V9_TEMPLATE_SET: u16 = 0;
+const V9_OPTIONS_TEMPLATE_SET: u16 = 1;
+pub const IPFIX_TEMPLATE_SET: u16 = 2;
+const IPFIX_OPTIONS_TEMPLATE_SET: u16 = 3;
+pub const MIN_DATA_SET_ID: u16 = 256;
+
+// 信息元素 ID（NetFlow v9 字段类型与 IPFIX IANA 信息元素编号一致）
+pub const IE_OCTET_DELTA_COUNT: u16 = 1;
+pub const IE_PACKET_DELTA_COUNT: u16 = 2;
+pub const IE_SOURCE_IPV4_ADDRESS: u16 = 8;
+pub const IE_INGRESS_INTERFACE: u16 = 10;
+pub const IE_DESTINATION_IPV4_ADDRESS: u16 = 12;
+pub const IE_EGRESS_INTERFACE: u16 = 14;
+const IE_POST_OCTET_DELTA_COUNT: u16 = 23;
+const IE_POST_PACKET_DELTA_COUNT: u16 = 24;
+pub const IE_SOURCE_IPV6_ADDRESS: u16 = 27;
+pub const IE_DESTINATION_IPV6_ADDRESS: u16 = 28;
+const IE_SAMPLING_INTERVAL: u16 = 34;
+pub const IE_FLOW_START_SECONDS: u16 = 150;
+pub const IE_FLOW_END_SECONDS: u16 = 151;
+const IE_SAMPLING_PACKET_INTERVAL: u16 = 305;
+
+const ENTERPRISE_BIT: u16 = 0x8000;
+const VARIABLE_LENGTH: u16 = 0xffff;
+
+// 模板数量上限：采集端口对网络开放，限制任意主机发送大量模板 ID 占用的内存
+const MAX_EXPORTERS: usize = 256;
+const MAX_TEMPLATES_PER_EXPORTER: usize = 1024;
+
+/// 模板中的一个字段
+#[derive(Debug, Clone, Copy)]
+struct TemplateField {
+    id: u16,
+    length: u16,
+    // 企业私有字段不参与解析
+    enterprise: bool,
+}
+
+// (source id / observation domain, 模板 ID)
+type TemplateKey = (u32, u16);
+
+/// NetFlow v5 / v9 和 IPFIX 报文解码器
+///
+/// v9 和 IPFIX 的模板按导出设备地址和 (source id / observation domain, 模板 ID) 保存，
+/// 收到模板之前的数据记录会被丢弃。导出设备数和每个设备的模板数有上限，超出时新模板被拒绝。
+#[derive(Default)]
+pub struct NetflowDecoder {
+    templates: HashMap<IpAddr, HashMap<TemplateKey, Vec<TemplateField>>>,
+}
+
+impl NetflowDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn decode_v5(data: &[u8]) -> Result<Vec<FlowRecord>, String> {
+        if data.len() < V5_HEADER_LEN {
+            return Err("NetFlow v5 报文过短".to_string());
+        }
+        let count = read_u16(data, 2) as usize;
+        if data.len() < V5_HEADER_LEN + count * V5_RECORD_LEN {
+            return Err(format!("NetFlow v5 报文长度 {} 不足 {} 条记录", data.len(), count));
+        }
+        // 低 14 位为采样间隔，0 表示未采样
+        let sampling = (read_u16(data, 22) & 0x3fff).max(1) as u64;
+
+        let mut records = Vec::with_capacity(count);
+        for i in 0..count {
+            let offset = V5_HEADER_LEN + i * V5_RECORD_LEN;
+            let record = &data[offset..offset + V5_RECORD_LEN];
+            records.push(FlowRecord {
+                src: IpAddr::V4(Ipv4Addr::new(record[0], record[1], record[2], record[3])),
+                dst: IpAddr::V4(Ipv4Addr::new(record[4], record[5], record[6], record[7])),
+                bytes: (read_u32(record, 20) as u64).saturating_mul(sampling),
+                packets: (read_u32(record, 16) as u64).saturating_mul(sampling),
+            });
+        }
+        Ok(records)
+    }
+
+    /// 解码 v9 / IPFIX 报文（两者只有报文头和模板集合格式不同）
+    fn decode_templated(&mut self, exporter: IpAddr, data: &[u8], version: u16) -> Result<Vec<FlowRecord>, String> {
+        let (header_len, domain_offset) = if version == NETFLOW_V9 {
+            (V9_HEADER_LEN, 16)
+        } else {
+            (IPFIX_HEADER_LEN, 12)
+        };
+        if data.len() < header_len {
+            return Err(format!("NetFlow v{} 报文过短", version));
+        }
+        let domain = read_u32(data, domain_offset);
+        // IPFIX 报文头中的长度可能小于 UDP 数据长度（尾部填充），大于时报文被截断
+        let end = if version == IPFIX_VERSION {
+            let length = read_u16(data, 2) as usize;
+            if !(header_len..=data.len()).contains(&length) {
+                return Err(format!("IPFIX 报文长度 {} 与实际长度 {} 不符", length, data.len()));
+            }
+            length
+        } else {
+            data.len()
+        };
+
+        let mut records = Vec::new();
+        let mut offset = header_len;
+        while offset + SET_HEADER_LEN <= end {
+            let set_id = read_u16(data, offset);
+            let set_len = read_u16(data, offset + 2) as usize;
+            if set_len < SET_HEADER_LEN || offset + set_len > end {
+                return Err(format!("NetFlow v{} 集合长度无效: {}", version, set_len));
+            }
+            let body = &data[offset + SET_HEADER_LEN..offset + set_len];
+
+            match (version, set_id) {
+                (NETFLOW_V9, V9_TEMPLATE_SET) | (IPFIX_VERSION, IPFIX_TEMPLATE_SET) => {
+                    self.parse_templates(exporter, domain, body, version == IPFIX_VERSION)?;
+                }
+                // 选项模板描述的是导出设备自身的信息，不包含流量记录
+                (NETFLOW_V9, V9_OPTIONS_TEMPLATE_SET) | (IPFIX_VERSION, IPFIX_OPTIONS_TEMPLATE_SET) => {}
+                (_, id) if id >= MIN_DATA_SET_ID => {
+                    if let Some(template) = self.templates.get(&exporter).and_then(|t| t.get(&(domain, id))) {
+                        Self::parse_data_set(template, body, &mut records);
+                    }
+                }
+                _ => {}
+            }
+
+            offset += set_len;
+        }
+        if offset != end {
+            return Err(format!("NetFlow v{} 报文尾部有 {} 字节不完整的集合", version, end - offset));
+        }
+        Ok(records)
+    }
+
+    fn parse_templates(&mut self, exporter: IpAddr, domain: u32, body: &[u8], ipfix: bool) -> Result<(), String> {
+        let mut offset = 0;
+        while offset + 4 <= body.len() {
+            let template_id = read_u16(body, offset);
+            let field_count = read_u16(body, offset + 2) as usize;
+            offset += 4;
+
+            // IPFIX 模板撤回：字段数为 0
+            if field_count == 0 {
+                if let Some(templates) = self.templates.get_mut(&exporter) {
+                    templates.remove(&(domain, template_id));
+                }
+                continue;
+            }
+
+            let mut fields = Vec::with_capacity(field_count);
+            for _ in 0..field_count {
+                if offset + 4 > body.len() {
+                    return Err(format!("模板 {} 被截断", template_id));
+                }
+                let raw_id = read_u16(body, offset);
+                let length = read_u16(body, offset + 2);
+                offset += 4;
+                let enterprise = ipfix && raw_id & ENTERPRISE_BIT != 0;
+                if enterprise {
+                    // 跳过企业编号
+                    offset += 4;
+                }
+                fields.push(TemplateField {
+                    id: raw_id & !ENTERPRISE_BIT,
+                    length,
+                    enterprise,
+                });
+            }
+            if offset > body.len() {
+                return Err(format!("模板 {} 被截断", template_id));
+            }
+            if template_id >= MIN_DATA_SET_ID {
+                self.insert_template(exporter, (domain, template_id), fields)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 保存模板；已有的模板可以更新，新的导出设备或模板超出上限时拒绝
+    fn insert_template(&mut self, exporter: IpAddr, key: TemplateKey, fields: Vec<TemplateField>) -> Result<(), String> {
+        if !self.templates.contains_key(&exporter) && self.templates.len() >= MAX_EXPORTERS {
+            return Err(format!("导出设备数量超过上限 {}，忽略 {} 的模板", MAX_EXPORTERS, exporter));
+        }
+        let templates = self.templates.entry(exporter).or_default();
+        if !templates.contains_key(&key) && templates.len() >= MAX_TEMPLATES_PER_EXPORTER {
+            return Err(format!("导出设备 {} 的模板数量超过上限 {}", exporter, MAX_TEMPLATES_PER_EXPORTER));
+        }
+        templates.insert(key, fields);
+        Ok(())
+    }
+
+    fn parse_data_set(template: &[TemplateField], body: &[u8], records: &mut Vec<FlowRecord>) {
+        let mut offset = 0;
+        while offset < body.len() {
+            let record_start = offset;
+            let mut src = None;
+            let mut dst = None;
+            let mut bytes = 0u64;
+            let mut packets = 0u64;
+            let mut sampling = 1u64;
+
+            for field in template {
+                let length = if field.length == VARIABLE_LENGTH {
+                    // 变长字段：1 字节长度，255 表示后面跟 2 字节长度
+                    let Some(&short_len) = body.get(offset) else {
+                        return;
+                    };
+                    offset += 1;
+                    if short_len == 255 {
+                        let Some(long_len) = body.get(offset..offset + 2) else {
+                            return;
+                        };
+                        offset += 2;
+                        u16::from_be_bytes([long_len[0], long_len[1]]) as usize
+                    } else {
+                        short_len as usize
+                    }
+                } else {
+                    field.length as usize
+                };
+                // 剩余数据不足一条记录时为集合尾部的填充
+                let Some(value) = body.get(offset..offset + length) else {
+                    return;
+                };
+                offset += length;
+
+                if field.enterprise {
+                    continue;
+                }
+                match (field.id, length) {
+                    (IE_SOURCE_IPV4_ADDRESS, 4) => src = Some(IpAddr::V4(Ipv4Addr::new(value[0], value[1], value[2], value[3]))),
+                    (IE_DESTINATION_IPV4_ADDRESS, 4) => dst = Some(IpAddr::V4(Ipv4Addr::new(value[0], value[1], value[2], value[3]))),
+                    (IE_SOURCE_IPV6_ADDRESS, 16) => src = <[u8; 16]>::try_from(value).ok().map(|v| IpAddr::V6(Ipv6Addr::from(v))),
+                    (IE_DESTINATION_IPV6_ADDRESS, 16) => dst = <[u8; 16]>::try_from(value).ok().map(|v| IpAddr::V6(Ipv6Addr::from(v))),
+                    // 入方向计数优先；只有出方向计数时使用出方向计数
+                    (IE_OCTET_DELTA_COUNT, _) => bytes = read_uint(value),
+                    (IE_PACKET_DELTA_COUNT, _) => packets = read_uint(value),
+                    (IE_POST_OCTET_DELTA_COUNT, _) if bytes == 0 => bytes = read_uint(value),
+                    (IE_POST_PACKET_DELTA_COUNT, _) if packets == 0 => packets = read_uint(value),
+                    (IE_SAMPLING_INTERVAL | IE_SAMPLING_PACKET_INTERVAL, _) => sampling = read_uint(value).max(1),
+                    _ => {}
+                }
+            }
+
+            // 模板字段总长度为 0 时无法前进
+            if offset == record_start {
+                return;
+            }
+            if let (Some(src), Some(dst)) = (src, dst) {
+                records.push(FlowRecord {
+                    src,
+                    dst,
+                    bytes: bytes.saturating_mul(sampling),
+                    packets: packets.saturating_mul(sampling),
+                });
+            }
+        }
+    }
+}
+
+impl DatagramDecoder for NetflowDecoder {
+    fn decode(&mut self, exporter: IpAddr, data: &[u8]) -> Result<Vec<FlowRecord>, String> {
+        if data.len() < 2 {
+            return Err("报文过短".to_string());
+        }
+        match read_u16(data, 0) {
+            NETFLOW_V5 => Self::decode_v5(data),
+            version @ (NETFLOW_V9 | IPFIX_VERSION) => self.decode_templated(exporter, data, version),
+            version => Err(format!("不支持的 NetFlow 版本: {}", version)),
+        }
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+/// 读取 1~8 字节的大端无符号整数（计数字段允许缩减长度编码）
+fn read_uint(value: &[u8]) -> u64 {
+    value.iter().take(8).fold(0u64, |acc, &b| (acc << 8) | b as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORTER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+    fn v5_packet(count: u16, sampling: u16, records: &[([u8; 4], [u8; 4], u32, u32)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&NETFLOW_V5.to_be_bytes());
+        data.extend_from_slice(&count.to_be_bytes());
+        data.extend_from_slice(&[0; 16]); // uptime、时间戳、序号
+        data.extend_from_slice(&[0, 0]); // engine
+        data.extend_from_slice(&sampling.to_be_bytes());
+        for (src, dst, packets, bytes) in records {
+            let mut record = [0u8; V5_RECORD_LEN];
+            record[0..4].copy_from_slice(src);
+            record[4..8].copy_from_slice(dst);
+            record[16..20].copy_from_slice(&packets.to_be_bytes());
+            record[20..24].copy_from_slice(&bytes.to_be_bytes());
+            data.extend_from_slice(&record);
+        }
+        data
+    }
+
+    fn set(id: u16, body: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&id.to_be_bytes());
+        data.extend_from_slice(&((body.len() + SET_HEADER_LEN) as u16).to_be_bytes());
+        data.extend_from_slice(body);
+        data
+    }
+
+    /// 模板集合的内容：(模板 ID, [(字段 ID, 长度, 企业编号)])
+    fn template(id: u16, fields: &[(u16, u16, Option<u32>)]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&id.to_be_bytes());
+        body.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+        for (field_id, length, enterprise) in fields {
+            let raw_id = if enterprise.is_some() { field_id | ENTERPRISE_BIT } else { *field_id };
+            body.extend_from_slice(&raw_id.to_be_bytes());
+            body.extend_from_slice(&length.to_be_bytes());
+            if let Some(number) = enterprise {
+                body.extend_from_slice(&number.to_be_bytes());
+            }
+        }
+        body
+    }
+
+    fn v9_packet(sets: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&NETFLOW_V9.to_be_bytes());
+        data.extend_from_slice(&(sets.len() as u16).to_be_bytes());
+        data.extend_from_slice(&[0; 12]); // uptime、时间戳、序号
+        data.extend_from_slice(&7u32.to_be_bytes()); // source id
+        sets.iter().for_each(|s| data.extend_from_slice(s));
+        data
+    }
+
+    fn ipfix_packet(sets: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = sets.concat();
+        let mut data = Vec::new();
+        data.extend_from_slice(&IPFIX_VERSION.to_be_bytes());
+        data.extend_from_slice(&((IPFIX_HEADER_LEN + body.len()) as u16).to_be_bytes());
+        data.extend_from_slice(&[0; 8]); // 导出时间、序号
+        data.extend_from_slice(&1u32.to_be_bytes()); // observation domain
+        data.extend_from_slice(&body);
+        data
+    }
+
+    fn v4(a: u8, b: u8, c: u8, d: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(a, b, c, d))
+    }
+
+    #[test]
+    fn v5_records() {
+        let records = [([10, 0, 0, 1], [8, 8, 8, 8], 3, 1500), ([1, 1, 1, 1], [10, 0, 0, 2], 1, 60)];
+        let mut decoder = NetflowDecoder::new();
+        let decoded = decoder.decode(EXPORTER, &v5_packet(2, 0, &records)).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!((decoded[0].src, decoded[0].dst), (v4(10, 0, 0, 1), v4(8, 8, 8, 8)));
+        assert_eq!((decoded[0].bytes, decoded[0].packets), (1500, 3));
+        assert_eq!((decoded[1].bytes, decoded[1].packets), (60, 1));
+
+        // 采样间隔在低 14 位，高 2 位为采样模式
+        let decoded = decoder.decode(EXPORTER, &v5_packet(1, 0x4000 | 100, &records[..1])).unwrap();
+        assert_eq!((decoded[0].bytes, decoded[0].packets), (150_000, 300));
+    }
+
+    #[test]
+    fn v5_truncated() {
+        let records = [([10, 0, 0, 1], [8, 8, 8, 8], 3, 1500)];
+        let mut decoder = NetflowDecoder::new();
+        assert!(decoder.decode(EXPORTER, &v5_packet(2, 0, &records)).is_err());
+        let packet = v5_packet(1, 0, &records);
+        assert!(decoder.decode(EXPORTER, &packet[..packet.len() - 1]).is_err());
+        assert!(decoder.decode(EXPORTER, &packet[..V5_HEADER_LEN - 1]).is_err());
+    }
+
+    #[test]
+    fn v9_template_and_data() {
+        let fields = [
+            (IE_SOURCE_IPV4_ADDRESS, 4, None),
+            (IE_DESTINATION_IPV4_ADDRESS, 4, None),
+            (IE_OCTET_DELTA_COUNT, 4, None),
+            (IE_PACKET_DELTA_COUNT, 4, None),
+        ];
+        let mut data = Vec::new();
+        for (src, dst, bytes, packets) in [([10, 0, 0, 1], [8, 8, 8, 8], 1000u32, 2u32), ([8, 8, 4, 4], [10, 0, 0, 1], 500, 1)] {
+            data.extend_from_slice(&src);
+            data.extend_from_slice(&dst);
+            data.extend_from_slice(&bytes.to_be_bytes());
+            data.extend_from_slice(&packets.to_be_bytes());
+        }
+        data.extend_from_slice(&[0, 0]); // 集合尾部填充
+        let data_set = set(256, &data);
+
+        // 收到模板之前的数据记录被丢弃
+        let mut decoder = NetflowDecoder::new();
+        assert!(decoder.decode(EXPORTER, &v9_packet(std::slice::from_ref(&data_set))).unwrap().is_empty());
+
+        let packet = v9_packet(&[set(V9_TEMPLATE_SET, &template(256, &fields)), data_set.clone()]);
+        let decoded = decoder.decode(EXPORTER, &packet).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!((decoded[0].src, decoded[0].dst, decoded[0].bytes, decoded[0].packets), (v4(10, 0, 0, 1), v4(8, 8, 8, 8), 1000, 2));
+        assert_eq!((decoded[1].src, decoded[1].bytes), (v4(8, 8, 4, 4), 500));
+
+        // 模板按导出设备区分
+        let other = v4(192, 0, 2, 2);
+        assert!(decoder.decode(other, &v9_packet(&[data_set])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn ipfix_variable_length_and_enterprise_fields() {
+        const IE_INTERFACE_NAME: u16 = 82;
+        let fields = [
+            (IE_SOURCE_IPV6_ADDRESS, 16, None),
+            (IE_DESTINATION_IPV6_ADDRESS, 16, None),
+            (IE_INTERFACE_NAME, VARIABLE_LENGTH, None),
+            (IE_OCTET_DELTA_COUNT, 2, Some(9)), // 企业私有字段不参与解析
+            (IE_OCTET_DELTA_COUNT, 8, None),
+            (IE_SAMPLING_PACKET_INTERVAL, 4, None),
+        ];
+        let src: Ipv6Addr = "fd00::1".parse().unwrap();
+        let dst: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let record = |name: &[u8], long: bool, bytes: u64, sampling: u32| {
+            let mut data = Vec::new();
+            data.extend_from_slice(&src.octets());
+            data.extend_from_slice(&dst.octets());
+            if long {
+                data.push(255);
+                data.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            } else {
+                data.push(name.len() as u8);
+            }
+            data.extend_from_slice(name);
+            data.extend_from_slice(&[0xff, 0xff]);
+            data.extend_from_slice(&bytes.to_be_bytes());
+            data.extend_from_slice(&sampling.to_be_bytes());
+            data
+        };
+        let data = [record(b"eth0", false, 1000, 10), record(&[b'x'; 300], true, u64::MAX, 2)].concat();
+        let packet = ipfix_packet(&[set(IPFIX_TEMPLATE_SET, &template(300, &fields)), set(300, &data)]);
+
+        let decoded = NetflowDecoder::new().decode(EXPORTER, &packet).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!((decoded[0].src, decoded[0].dst), (IpAddr::V6(src), IpAddr::V6(dst)));
+        assert_eq!(decoded[0].bytes, 10_000);
+        // 按采样率放大时饱和
+        assert_eq!(decoded[1].bytes, u64::MAX);
+    }
+
+    #[test]
+    fn ipfix_template_withdrawal() {
+        let fields = [(IE_SOURCE_IPV4_ADDRESS, 4, None), (IE_DESTINATION_IPV4_ADDRESS, 4, None)];
+        let data_set = set(400, &[10, 0, 0, 1, 8, 8, 8, 8]);
+        let mut decoder = NetflowDecoder::new();
+        let packet = ipfix_packet(&[set(IPFIX_TEMPLATE_SET, &template(400, &fields)), data_set.clone()]);
+        assert_eq!(decoder.decode(EXPORTER, &packet).unwrap().len(), 1);
+
+        let packet = ipfix_packet(&[set(IPFIX_TEMPLATE_SET, &template(400, &[])), data_set]);
+        assert!(decoder.decode(EXPORTER, &packet).unwrap().is_empty());
+    }
+
+    #[test]
+    fn truncated_and_garbage_packets() {
+        let fields = [(IE_SOURCE_IPV4_ADDRESS, 4, None), (IE_DESTINATION_IPV4_ADDRESS, 4, None), (IE_OCTET_DELTA_COUNT, 4, None)];
+        let template_set = set(V9_TEMPLATE_SET, &template(256, &fields));
+        let packet = v9_packet(&[template_set.clone(), set(256, &[10, 0, 0, 1, 8, 8, 8, 8, 0, 0, 0, 1])]);
+
+        // 任意截断都不能 panic；截断在报文头或集合中间时返回错误
+        for len in 0..packet.len() {
+            let result = NetflowDecoder::new().decode(EXPORTER, &packet[..len]);
+            let at_set_boundary = len == V9_HEADER_LEN || len == V9_HEADER_LEN + template_set.len();
+            assert_eq!(result.is_err(), !at_set_boundary, "截断长度 {}", len);
+        }
+
+        let mut decoder = NetflowDecoder::new();
+        // 不支持的版本和过短的报文
+        assert!(decoder.decode(EXPORTER, &[0xde, 0xad, 0xbe, 0xef]).is_err());
+        assert!(decoder.decode(EXPORTER, &[0]).is_err());
+        assert!(decoder.decode(EXPORTER, &[]).is_err());
+        // 集合长度小于集合头
+        let mut bad_set = v9_packet(&[]);
+        bad_set.extend_from_slice(&[1, 0, 0, 2]);
+        assert!(decoder.decode(EXPORTER, &bad_set).is_err());
+        // 模板声明的字段数多于实际字段
+        let mut short_template = template(256, &fields[..1]);
+        short_template[3] = 10;
+        assert!(decoder.decode(EXPORTER, &v9_packet(&[set(V9_TEMPLATE_SET, &short_template)])).is_err());
+        // 企业编号被截断
+        let enterprise = template(256, &[(IE_OCTET_DELTA_COUNT, 4, Some(9))]);
+        let packet = ipfix_packet(&[set(IPFIX_TEMPLATE_SET, &enterprise[..enterprise.len() - 2])]);
+        assert!(decoder.decode(EXPORTER, &packet).is_err());
+        // IPFIX 报文头中的长度大于实际数据（报文被截断）
+        let mut packet = ipfix_packet(&[template_set]);
+        packet[2..4].copy_from_slice(&u16::MAX.to_be_bytes());
+        assert!(decoder.decode(EXPORTER, &packet).is_err());
+
+        // 伪随机数据
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        for _ in 0..1000 {
+            let len = (seed % 200) as usize;
+            let mut data: Vec<u8> = (0..len)
+                .map(|_| {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 7;
+                    seed ^= seed << 17;
+                    seed as u8
+                })
+                .collect();
+            if data.len() >= 2 {
+                let version = [NETFLOW_V5, NETFLOW_V9, IPFIX_VERSION][(seed % 3) as usize];
+                data[..2].copy_from_slice(&version.to_be_bytes());
+            }
+            let _ = decoder.decode(EXPORTER, &data);
+        }
+    }
+
+    #[test]
+    fn template_limits() {
+        let fields = [(IE_SOURCE_IPV4_ADDRESS, 4, None)];
+        let mut decoder = NetflowDecoder::new();
+        for id in 0..MAX_TEMPLATES_PER_EXPORTER as u16 {
+            let packet = v9_packet(&[set(V9_TEMPLATE_SET, &template(MIN_DATA_SET_ID + id, &fields))]);
+            decoder.decode(EXPORTER, &packet).unwrap();
+        }
+        let packet = v9_packet(&[set(V9_TEMPLATE_SET, &template(MIN_DATA_SET_ID + MAX_TEMPLATES_PER_EXPORTER as u16, &fields))]);
+        assert!(decoder.decode(EXPORTER, &packet).is_err());
+        // 已有模板可以更新
+        let packet = v9_packet(&[set(V9_TEMPLATE_SET, &template(MIN_DATA_SET_ID, &fields))]);
+        assert!(decoder.decode(EXPORTER, &packet).is_ok());
+
+        for i in 1..MAX_EXPORTERS {
+            decoder.decode(IpAddr::V4(Ipv4Addr::from(i as u32)), &packet).unwrap();
+        }
+        assert!(decoder.decode(v4(198, 51, 100, 1), &packet).is_err());
+        assert_eq!(decoder.templates.len(), MAX_EXPORTERS);
+    }
+}
@@ -0,0 +1,125 @@
+use std::error::Error;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+// nfnetlink 常量（linux/netfilter/nfnetlink.h）
+const NETLINK_NETFILTER: i32 = 12;
+const NLMSG_HEADER_LEN: usize = 16;
+const NFGENMSG_LEN: usize = 4;
+const NLA_HEADER_LEN: usize = 4;
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+/// 发送一个 nfnetlink 导出（dump）请求，对每条响应消息调用 handle（参数为 nfgenmsg 之后的属性）
+///
+/// msg_type 为 (子系统 << 8) | 消息类型，family 为 nfgenmsg 中的协议族，attrs 为请求附带的属性，
+/// name 用于错误信息（例如 ctnetlink、nf_tables）。
+pub fn dump(
+    name: &str,
+    msg_type: u16,
+    family: u8,
+    attrs: &[u8],
+    mut handle: impl FnMut(&[u8]),
+) -> Result<(), Box<dyn Error>> {
+    let raw_fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, NETLINK_NETFILTER) };
+    if raw_fd < 0 {
+        return Err(format!("创建 netlink 套接字失败: {}", std::io::Error::last_os_error()).into());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(raw_fd) };
+
+    // nlmsghdr + nfgenmsg + 属性
+    let msg_len = (NLMSG_HEADER_LEN + NFGENMSG_LEN + attrs.len()) as u32;
+    let flags = (libc::NLM_F_REQUEST | libc::NLM_F_DUMP) as u16;
+    let mut request = Vec::with_capacity(msg_len as usize);
+    request.extend_from_slice(&msg_len.to_ne_bytes());
+    request.extend_from_slice(&msg_type.to_ne_bytes());
+    request.extend_from_slice(&flags.to_ne_bytes());
+    request.extend_from_slice(&1u32.to_ne_bytes()); // seq
+    request.extend_from_slice(&0u32.to_ne_bytes()); // pid
+    request.extend_from_slice(&[family, 0, 0, 0]);
+    request.extend_from_slice(attrs);
+
+    let sent = unsafe { libc::send(fd.as_raw_fd(), request.as_ptr() as *const libc::c_void, request.len(), 0) };
+    if sent < 0 {
+        return Err(format!("发送 {} 请求失败: {}", name, std::io::Error::last_os_error()).into());
+    }
+
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = unsafe { libc::recv(fd.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0) };
+        if n < 0 {
+            return Err(format!("读取 {} 响应失败: {}", name, std::io::Error::last_os_error()).into());
+        }
+
+        let data = &buf[..n as usize];
+        let mut offset = 0;
+        while offset + NLMSG_HEADER_LEN <= data.len() {
+            let len = u32::from_ne_bytes(data[offset..offset + 4].try_into()?) as usize;
+            let msg_type = u16::from_ne_bytes(data[offset + 4..offset + 6].try_into()?);
+            if len < NLMSG_HEADER_LEN || offset + len > data.len() {
+                break;
+            }
+            let payload = &data[offset + NLMSG_HEADER_LEN..offset + len];
+
+            match msg_type as i32 {
+                libc::NLMSG_DONE => return Ok(()),
+                libc::NLMSG_ERROR => {
+                    let code = payload
+                        .get(..4)
+                        .map(|b| i32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+                        .unwrap_or(0);
+                    if code != 0 {
+                        return Err(format!("{} 返回错误: {}", name, std::io::Error::from_raw_os_error(-code)).into());
+                    }
+                }
+                _ => {
+                    if let Some(attrs) = payload.get(NFGENMSG_LEN..) {
+                        handle(attrs);
+                    }
+                }
+            }
+
+            offset += align4(len);
+        }
+    }
+}
+
+/// 追加一个 netlink 属性（值按 4 字节对齐补零）
+pub fn put_attr(buf: &mut Vec<u8>, attr_type: u16, value: &[u8]) {
+    let len = (NLA_HEADER_LEN + value.len()) as u16;
+    buf.extend_from_slice(&len.to_ne_bytes());
+    buf.extend_from_slice(&attr_type.to_ne_bytes());
+    buf.extend_from_slice(value);
+    buf.resize(align4(buf.len()), 0);
+}
+
+/// netlink 属性迭代器，返回 (属性类型, 属性值)
+pub struct NlaIter<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> NlaIter<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for NlaIter<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.len() < NLA_HEADER_LEN {
+            return None;
+        }
+        let len = u16::from_ne_bytes([self.data[0], self.data[1]]) as usize;
+        let attr_type = u16::from_ne_bytes([self.data[2], self.data[3]]) & NLA_TYPE_MASK;
+        if len < NLA_HEADER_LEN || len > self.data.len() {
+            return None;
+        }
+        let value = &self.data[NLA_HEADER_LEN..len];
+        self.data = self.data.get(align4(len)..).unwrap_or(&[]);
+        Some((attr_type, value))
+    }
+}
+
+fn align4(len: usize) -> usize {
+    (len + 3) & !3
+}
@@ -0,0 +1,141 @@
+use crate::nfnetlink::{self, NlaIter};
+use std::error::Error;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// nfgenmsg 中 inet 表的协议族
+pub const NFPROTO_INET: u8 = 1;
+
+// nf_tables netlink 常量（linux/netfilter/nf_tables.h）
+const NFNL_SUBSYS_NFTABLES: u16 = 10;
+const NFT_MSG_GETSETELEM: u16 = 13;
+const NFTA_SET_ELEM_LIST_TABLE: u16 = 1;
+const NFTA_SET_ELEM_LIST_SET: u16 = 2;
+const NFTA_SET_ELEM_LIST_ELEMENTS: u16 = 3;
+const NFTA_LIST_ELEM: u16 = 1;
+const NFTA_SET_ELEM_KEY: u16 = 1;
+const NFTA_SET_ELEM_EXPR: u16 = 7;
+const NFTA_SET_ELEM_EXPRESSIONS: u16 = 11;
+const NFTA_DATA_VALUE: u16 = 1;
+const NFTA_EXPR_NAME: u16 = 1;
+const NFTA_EXPR_DATA: u16 = 2;
+const NFTA_COUNTER_BYTES: u16 = 1;
+const NFTA_COUNTER_PACKETS: u16 = 2;
+
+/// 检查 nft 命令是否可用，返回版本信息
+pub fn version() -> Result<String, Box<dyn Error>> {
+    let output = Command::new("nft")
+        .arg("--version")
+        .output()
+        .map_err(|e| format!("nft 不可用: {}. 请确保已安装 nftables", e))?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// 通过 nft -f - 原子地执行一段规则脚本
+pub fn run_script(script: &str) -> Result<(), Box<dyn Error>> {
+    let mut child = Command::new("nft")
+        .args(["-f", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(script.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(format!("nft 执行失败: {}", String::from_utf8_lossy(&output.stderr).trim()).into());
+    }
+    Ok(())
+}
+
+/// 集合中一个元素的键（内核中的原始字节）和计数器
+pub struct SetElementCounter {
+    pub key: Vec<u8>,
+    pub bytes: u64,
+    pub packets: u64,
+}
+
+/// 通过 nf_tables netlink 读取集合中全部元素的计数器（不启动 nft 子进程，也不经过 JSON）
+pub fn set_counters(family: u8, table: &str, set: &str) -> Result<Vec<SetElementCounter>, Box<dyn Error>> {
+    let nul_terminated = |s: &str| [s.as_bytes(), &[0]].concat();
+    let mut attrs = Vec::new();
+    nfnetlink::put_attr(&mut attrs, NFTA_SET_ELEM_LIST_TABLE, &nul_terminated(table));
+    nfnetlink::put_attr(&mut attrs, NFTA_SET_ELEM_LIST_SET, &nul_terminated(set));
+
+    let mut counters = Vec::new();
+    nfnetlink::dump(
+        "nf_tables",
+        (NFNL_SUBSYS_NFTABLES << 8) | NFT_MSG_GETSETELEM,
+        family,
+        &attrs,
+        |msg| {
+            for (attr_type, elements) in NlaIter::new(msg) {
+                if attr_type != NFTA_SET_ELEM_LIST_ELEMENTS {
+                    continue;
+                }
+                counters.extend(
+                    NlaIter::new(elements)
+                        .filter(|(elem_type, _)| *elem_type == NFTA_LIST_ELEM)
+                        .filter_map(|(_, elem)| parse_set_element(elem)),
+                );
+            }
+        },
+    )?;
+    Ok(counters)
+}
+
+/// 解析一个集合元素：键和 counter 表达式（单个表达式为 NFTA_SET_ELEM_EXPR，多个为 NFTA_SET_ELEM_EXPRESSIONS）
+fn parse_set_element(elem: &[u8]) -> Option<SetElementCounter> {
+    let mut key = None;
+    let mut counter = None;
+    for (attr_type, value) in NlaIter::new(elem) {
+        match attr_type {
+            NFTA_SET_ELEM_KEY => {
+                key = NlaIter::new(value).find(|(t, _)| *t == NFTA_DATA_VALUE).map(|(_, v)| v.to_vec());
+            }
+            NFTA_SET_ELEM_EXPR => counter = counter.or_else(|| parse_counter_expr(value)),
+            NFTA_SET_ELEM_EXPRESSIONS => {
+                counter = counter.or_else(|| {
+                    NlaIter::new(value)
+                        .filter(|(t, _)| *t == NFTA_LIST_ELEM)
+                        .find_map(|(_, expr)| parse_counter_expr(expr))
+                });
+            }
+            _ => {}
+        }
+    }
+    let (bytes, packets) = counter?;
+    Some(SetElementCounter { key: key?, bytes, packets })
+}
+
+/// 解析 counter 表达式，返回 (字节数, 包数)；其他表达式返回 None
+fn parse_counter_expr(expr: &[u8]) -> Option<(u64, u64)> {
+    let mut is_counter = false;
+    let mut counter = (0, 0);
+    for (attr_type, value) in NlaIter::new(expr) {
+        match attr_type {
+            NFTA_EXPR_NAME => is_counter = value.split(|&b| b == 0).next() == Some(b"counter".as_slice()),
+            NFTA_EXPR_DATA => {
+                for (data_type, raw) in NlaIter::new(value) {
+                    let Ok(raw) = <[u8; 8]>::try_from(raw) else {
+                        continue;
+                    };
+                    match data_type {
+                        NFTA_COUNTER_BYTES => counter.0 = u64::from_be_bytes(raw),
+                        NFTA_COUNTER_PACKETS => counter.1 = u64::from_be_bytes(raw),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    is_counter.then_some(counter)
+}
+
+/// 删除表（表不存在时忽略）
+pub fn delete_table(family: &str, table: &str) -> Result<(), Box<dyn Error>> {
+    // 先声明再删除，保证表不存在时也能执行成功
+    run_script(&format!("table {family} {table}\ndelete table {family} {table}\n"))
+}
@@ -0,0 +1,234 @@
+use crate::address_filter::AddressFilter;
+use crate::monitor::{FlowKey, InterfaceSelector, TrafficMonitor, TrafficStats};
+use crate::nft;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TABLE_FAMILY: &str = "inet";
+const TABLE_NAME: &str = "ip_traffic_monitor";
+
+// 动态集合的容量和空闲元素的过期时间
+const SET_SIZE: u32 = 65535;
+const SET_TIMEOUT: &str = "10m";
+
+// 集合键中网卡名的长度（IFNAMSIZ）
+const IFNAME_LEN: usize = 16;
+
+/// 计数集合：(集合名, 是否为发送方向, 地址长度)
+const COUNTER_SETS: [(&str, bool, usize); 4] = [
+    ("tx_v4", true, 4),
+    ("rx_v4", false, 4),
+    ("tx_v6", true, 16),
+    ("rx_v6", false, 16),
+];
+
+/// 基于 nftables 计数器的流量监控器
+///
+/// 安装一个独立的 nftables 表，在 input / output / forward 钩子上用动态集合按 (网卡, 本地IP, 远程IP) 计数，
+/// 计数完全在内核中完成。每个周期通过 nf_tables netlink 读取集合元素的累计计数器并计算增量，stop() 时删除整张表。
+/// 规则仍通过 nft 命令安装（一次性操作，nft 脚本可读性远好于手工拼装的 netlink 表达式）。
+pub struct NftablesMonitor {
+    sample_interval: u32,
+    address_filter: AddressFilter,
+    interfaces: InterfaceSelector,
+    // (是否为发送方向, 流) -> 上次读取的 (字节数, 包数)
+    previous: HashMap<(bool, FlowKey), (u64, u64)>,
+    // 已经写满的集合（写满后新的流不再计数，只在写满时提示一次）
+    full_sets: HashSet<&'static str>,
+    table_installed: bool,
+    last_collect: Option<Instant>,
+}
+
+impl NftablesMonitor {
+    pub fn new(sample_interval: u32, address_filter: AddressFilter, interfaces: InterfaceSelector) -> Self {
+        Self {
+            sample_interval,
+            address_filter,
+            interfaces,
+            previous: HashMap::new(),
+            full_sets: HashSet::new(),
+            table_installed: false,
+            last_collect: None,
+        }
+    }
+
+    /// 生成网卡匹配条件，例如 oifname { "eth0", "wg0" }
+    fn interface_match(&self, meta: &str) -> String {
+        let quote = |names: &[String]| {
+            names
+                .iter()
+                .map(|n| format!("\"{}\"", n))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut rule = String::new();
+        if !self.interfaces.includes().is_empty() {
+            rule.push_str(&format!("{} {{ {} }} ", meta, quote(self.interfaces.includes())));
+        }
+        if !self.interfaces.excludes().is_empty() {
+            rule.push_str(&format!("{} != {{ {} }} ", meta, quote(self.interfaces.excludes())));
+        }
+        rule
+    }
+
+    /// 生成计数表的规则脚本（先删除同名旧表，整体原子替换）
+    fn generate_ruleset(&self) -> String {
+        let set = |name: &str, addr_type: &str| {
+            format!(
+                "    set {name} {{ type ifname . {addr_type} . {addr_type}; size {SET_SIZE}; flags dynamic,timeout; timeout {SET_TIMEOUT}; }}\n"
+            )
+        };
+        let out = self.interface_match("oifname");
+        let inp = self.interface_match("iifname");
+
+        let mut script = format!("table {TABLE_FAMILY} {TABLE_NAME}\ndelete table {TABLE_FAMILY} {TABLE_NAME}\n");
+        script.push_str(&format!("table {TABLE_FAMILY} {TABLE_NAME} {{\n"));
+        script.push_str(&set("tx_v4", "ipv4_addr"));
+        script.push_str(&set("rx_v4", "ipv4_addr"));
+        script.push_str(&set("tx_v6", "ipv6_addr"));
+        script.push_str(&set("rx_v6", "ipv6_addr"));
+        script.push_str(&format!(
+            r#"    chain output {{
+        type filter hook output priority -300; policy accept;
+        {out}update @tx_v4 {{ oifname . ip saddr . ip daddr counter }}
+        {out}update @tx_v6 {{ oifname . ip6 saddr . ip6 daddr counter }}
+    }}
+    chain input {{
+        type filter hook input priority -300; policy accept;
+        {inp}update @rx_v4 {{ iifname . ip daddr . ip saddr counter }}
+        {inp}update @rx_v6 {{ iifname . ip6 daddr . ip6 saddr counter }}
+    }}
+    chain forward {{
+        type filter hook forward priority -300; policy accept;
+        {out}update @tx_v4 {{ oifname . ip saddr . ip daddr counter }}
+        {out}update @tx_v6 {{ oifname . ip6 saddr . ip6 daddr counter }}
+        {inp}update @rx_v4 {{ iifname . ip daddr . ip saddr counter }}
+        {inp}update @rx_v6 {{ iifname . ip6 daddr . ip6 saddr counter }}
+    }}
+}}
+"#
+        ));
+        script
+    }
+
+    /// 解析集合元素的键（网卡名 . 本地IP . 远程IP，各部分按 4 字节对齐），返回 (网卡, 本地IP, 远程IP)
+    fn parse_key(key: &[u8], addr_len: usize) -> Option<(String, IpAddr, IpAddr)> {
+        if key.len() != IFNAME_LEN + 2 * addr_len {
+            return None;
+        }
+        let ifname = &key[..IFNAME_LEN];
+        let ifname = &ifname[..ifname.iter().position(|&b| b == 0).unwrap_or(IFNAME_LEN)];
+        let addr = |bytes: &[u8]| -> Option<IpAddr> {
+            match addr_len {
+                4 => Some(IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?))),
+                _ => Some(IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?))),
+            }
+        };
+        Some((
+            String::from_utf8_lossy(ifname).into_owned(),
+            addr(&key[IFNAME_LEN..IFNAME_LEN + addr_len])?,
+            addr(&key[IFNAME_LEN + addr_len..])?,
+        ))
+    }
+
+    /// 读取全部计数集合并计算增量
+    fn collect(&mut self) -> Result<HashMap<FlowKey, TrafficStats>, Box<dyn Error>> {
+        let mut stats: HashMap<FlowKey, TrafficStats> = HashMap::new();
+        let mut current: HashMap<(bool, FlowKey), (u64, u64)> = HashMap::new();
+
+        for (set_name, is_tx, addr_len) in COUNTER_SETS {
+            let elements = nft::set_counters(nft::NFPROTO_INET, TABLE_NAME, set_name)?;
+            if elements.len() >= SET_SIZE as usize {
+                if self.full_sets.insert(set_name) {
+                    eprintln!(
+                        "[警告] nftables 计数集合 {} 已满（{} 个元素），新的流在已有元素过期（{}）之前不会被统计",
+                        set_name, SET_SIZE, SET_TIMEOUT
+                    );
+                }
+            } else {
+                self.full_sets.remove(set_name);
+            }
+
+            for element in elements {
+                let Some((interface, local_ip, remote_ip)) = Self::parse_key(&element.key, addr_len) else {
+                    continue;
+                };
+                let (bytes, packets) = (element.bytes, element.packets);
+                let key = FlowKey::new(interface, local_ip.to_string(), remote_ip.to_string());
+
+                // 元素过期后重新创建时计数器从 0 开始，新计数全部计入增量
+                let (delta_bytes, delta_packets) = match self.previous.get(&(is_tx, key.clone())) {
+                    Some(&(prev_bytes, prev_packets)) if bytes >= prev_bytes => {
+                        (bytes - prev_bytes, packets.saturating_sub(prev_packets))
+                    }
+                    _ => (bytes, packets),
+                };
+                current.insert((is_tx, key.clone()), (bytes, packets));
+
+                if delta_bytes == 0 || !self.address_filter.is_allowed_str(&key.remote_ip) {
+                    continue;
+                }
+                let entry = stats.entry(key).or_default();
+                if is_tx {
+                    entry.tx_bytes += delta_bytes;
+                    entry.tx_packets += delta_packets;
+                } else {
+                    entry.rx_bytes += delta_bytes;
+                    entry.rx_packets += delta_packets;
+                }
+            }
+        }
+
+        // 只保留仍在集合中的元素
+        self.previous = current;
+        Ok(stats)
+    }
+}
+
+impl TrafficMonitor for NftablesMonitor {
+    fn init(&mut self) -> Result<(), Box<dyn Error>> {
+        let version = nft::version()?;
+
+        nft::run_script(&self.generate_ruleset())?;
+        self.table_installed = true;
+        println!("nftables 监控器初始化成功: {}（表 {} {}）", version, TABLE_FAMILY, TABLE_NAME);
+
+        // 读取一次作为基准；失败时删除刚安装的表
+        if let Err(e) = self.collect() {
+            let _ = self.stop();
+            return Err(e);
+        }
+        self.last_collect = Some(Instant::now());
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<HashMap<FlowKey, TrafficStats>, Box<dyn Error>> {
+        // 等待到本采样周期结束
+        let interval = Duration::from_secs(self.sample_interval as u64);
+        if let Some(last) = self.last_collect {
+            let elapsed = last.elapsed();
+            if elapsed < interval {
+                thread::sleep(interval - elapsed);
+            }
+        }
+        self.last_collect = Some(Instant::now());
+
+        self.collect()
+    }
+
+    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.table_installed {
+            nft::delete_table(TABLE_FAMILY, TABLE_NAME)?;
+            self.table_installed = false;
+            println!("已删除 nftables 表 {} {}", TABLE_FAMILY, TABLE_NAME);
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "nftables"
+    }
+}
@@ -0,0 +1,303 @@
+use crate::address_filter::AddressFilter;
+use crate::monitor::{FlowKey, InterfaceSelector, TrafficMonitor, TrafficStats};
+use crate::packet_parser;
+use std::collections::HashMap;
+use std::error::Error;
+use std::ffi::CString;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+type StatsReceiver = Receiver<HashMap<FlowKey, TrafficStats>>;
+
+// 每次读取的字节数：只需要链路层和 IP 头部，完整长度通过 MSG_TRUNC 获取
+const SNAP_LEN: usize = 256;
+
+// 抓包线程向主线程发送累计数据的间隔
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 基于 AF_PACKET 原始套接字的流量监控器
+///
+/// 每个网卡一个抓包线程，直接解析以太网 / VLAN / IPv4 / IPv6 头部，按数据包方向精确统计字节数和包数。
+/// 以太网网卡上会附加内核 BPF 过滤器，只把 IP 数据包交给用户态。
+pub struct PacketMonitor {
+    interfaces: InterfaceSelector,
+    sample_interval: u32,
+    address_filter: AddressFilter,
+    running: Arc<AtomicBool>,
+    capture_threads: Vec<thread::JoinHandle<()>>,
+    stats_receiver: Option<Arc<Mutex<StatsReceiver>>>,
+    last_collect: Option<Instant>,
+}
+
+impl PacketMonitor {
+    pub fn new(interfaces: InterfaceSelector, sample_interval: u32, address_filter: AddressFilter) -> Self {
+        Self {
+            interfaces,
+            sample_interval,
+            address_filter,
+            running: Arc::new(AtomicBool::new(false)),
+            capture_threads: Vec::new(),
+            stats_receiver: None,
+            last_collect: None,
+        }
+    }
+
+    /// 读取网卡的链路层类型（/sys/class/net/<iface>/type）
+    fn link_type(interface: &str) -> u16 {
+        std::fs::read_to_string(format!("/sys/class/net/{}/type", interface))
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or(libc::ARPHRD_ETHER)
+    }
+
+    /// 打开绑定到指定网卡的 AF_PACKET 套接字
+    fn open_socket(interface: &str, link_type: u16) -> Result<OwnedFd, Box<dyn Error>> {
+        let c_name = CString::new(interface)?;
+        let ifindex = unsafe { libc::if_nametoindex(c_name.as_ptr()) };
+        if ifindex == 0 {
+            return Err(format!("网卡不存在: {}", interface).into());
+        }
+
+        // 先以协议 0 创建（不接收任何数据包），绑定网卡并附加过滤器后再启用 ETH_P_ALL，
+        // 避免在绑定和过滤器生效之前收到其他网卡或非 IP 的数据包
+        let raw_fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW, 0) };
+        if raw_fd < 0 {
+            return Err(format!("创建 AF_PACKET 套接字失败: {}", std::io::Error::last_os_error()).into());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(raw_fd) };
+        Self::bind_socket(&fd, interface, ifindex, 0)?;
+
+        // 设置读取超时，便于抓包线程定期检查退出标志和发送数据
+        let timeout = libc::timeval { tv_sec: 1, tv_usec: 0 };
+        let ret = unsafe {
+            libc::setsockopt(
+                fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &timeout as *const libc::timeval as *const libc::c_void,
+                std::mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(format!("设置套接字读取超时失败: {}", std::io::Error::last_os_error()).into());
+        }
+
+        if link_type == libc::ARPHRD_ETHER {
+            Self::attach_ip_filter(&fd)?;
+        }
+        Self::bind_socket(&fd, interface, ifindex, (libc::ETH_P_ALL as u16).to_be())?;
+
+        Ok(fd)
+    }
+
+    /// 将套接字绑定到网卡，protocol 为网络字节序（0 表示不接收数据包）
+    fn bind_socket(fd: &OwnedFd, interface: &str, ifindex: u32, protocol: u16) -> Result<(), Box<dyn Error>> {
+        let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+        addr.sll_family = libc::AF_PACKET as u16;
+        addr.sll_protocol = protocol;
+        addr.sll_ifindex = ifindex as i32;
+        let ret = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(format!("绑定网卡 {} 失败: {}", interface, std::io::Error::last_os_error()).into());
+        }
+        Ok(())
+    }
+
+    /// 附加内核 BPF 过滤器：只接收 IPv4 / IPv6（包括带 VLAN 标签的）以太网帧
+    fn attach_ip_filter(fd: &OwnedFd) -> Result<(), Box<dyn Error>> {
+        let op = |code: u16, jt: u8, jf: u8, k: u32| libc::sock_filter { code, jt, jf, k };
+        const LDH_ABS: u16 = 0x28; // BPF_LD | BPF_H | BPF_ABS
+        const JEQ_K: u16 = 0x15; // BPF_JMP | BPF_JEQ | BPF_K
+        const RET_K: u16 = 0x06; // BPF_RET | BPF_K
+
+        let mut program = [
+            op(LDH_ABS, 0, 0, 12),
+            op(JEQ_K, 5, 0, 0x0800),
+            op(JEQ_K, 4, 0, 0x86dd),
+            op(JEQ_K, 3, 0, 0x8100),
+            op(JEQ_K, 2, 0, 0x88a8),
+            op(JEQ_K, 1, 0, 0x9100),
+            op(RET_K, 0, 0, 0),
+            op(RET_K, 0, 0, 0x0004_0000),
+        ];
+        let fprog = libc::sock_fprog {
+            len: program.len() as u16,
+            filter: program.as_mut_ptr(),
+        };
+
+        let ret = unsafe {
+            libc::setsockopt(
+                fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_ATTACH_FILTER,
+                &fprog as *const libc::sock_fprog as *const libc::c_void,
+                std::mem::size_of::<libc::sock_fprog>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(format!("附加 BPF 过滤器失败: {}", std::io::Error::last_os_error()).into());
+        }
+        Ok(())
+    }
+
+    /// 抓包循环（在后台线程中运行）
+    fn capture_loop(
+        fd: OwnedFd,
+        interface: String,
+        link_type: u16,
+        address_filter: AddressFilter,
+        running: Arc<AtomicBool>,
+        tx: Sender<HashMap<FlowKey, TrafficStats>>,
+    ) {
+        let mut buf = [0u8; SNAP_LEN];
+        let mut pending: HashMap<FlowKey, TrafficStats> = HashMap::new();
+        let mut last_flush = Instant::now();
+        let has_ethernet_header = link_type == libc::ARPHRD_ETHER || link_type == libc::ARPHRD_LOOPBACK;
+
+        while running.load(Ordering::SeqCst) {
+            let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+            let mut addr_len = std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+            let n = unsafe {
+                libc::recvfrom(
+                    fd.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    libc::MSG_TRUNC,
+                    &mut addr as *mut libc::sockaddr_ll as *mut libc::sockaddr,
+                    &mut addr_len,
+                )
+            };
+
+            if n < 0 {
+                let err = std::io::Error::last_os_error();
+                match err.kind() {
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::Interrupted => {}
+                    _ => {
+                        eprintln!("[错误] 网卡 {} 抓包失败: {}", interface, err);
+                        break;
+                    }
+                }
+            } else {
+                // MSG_TRUNC 下返回值是数据包的完整长度
+                let frame_len = n as usize;
+                let captured = &buf[..frame_len.min(SNAP_LEN)];
+                let info = if has_ethernet_header {
+                    packet_parser::parse_ethernet(captured)
+                } else {
+                    packet_parser::parse_ip(captured)
+                };
+
+                if let Some((local_ip, remote_ip, is_tx)) =
+                    info.and_then(|info| packet_parser::classify_by_packet_type(&info, addr.sll_pkttype))
+                {
+                    if address_filter.is_allowed(&remote_ip) {
+                        let entry = pending
+                            .entry(FlowKey::new(interface.as_str(), local_ip.to_string(), remote_ip.to_string()))
+                            .or_default();
+                        if is_tx {
+                            entry.tx_bytes += frame_len as u64;
+                            entry.tx_packets += 1;
+                        } else {
+                            entry.rx_bytes += frame_len as u64;
+                            entry.rx_packets += 1;
+                        }
+                    }
+                }
+            }
+
+            if last_flush.elapsed() >= FLUSH_INTERVAL {
+                if !pending.is_empty() && tx.send(std::mem::take(&mut pending)).is_err() {
+                    break;
+                }
+                last_flush = Instant::now();
+            }
+        }
+    }
+}
+
+impl TrafficMonitor for PacketMonitor {
+    fn init(&mut self) -> Result<(), Box<dyn Error>> {
+        let (tx, rx) = mpsc::channel();
+        self.stats_receiver = Some(Arc::new(Mutex::new(rx)));
+        self.running.store(true, Ordering::SeqCst);
+
+        for interface in self.interfaces.resolve()? {
+            let link_type = Self::link_type(&interface);
+            let fd = match Self::open_socket(&interface, link_type) {
+                Ok(fd) => fd,
+                Err(e) => {
+                    eprintln!("[警告] 网卡 {} 抓包失败，已跳过: {}", interface, e);
+                    continue;
+                }
+            };
+            println!("AF_PACKET 抓包网卡 {}（链路类型 {}）", interface, link_type);
+
+            let address_filter = self.address_filter.clone();
+            let running = Arc::clone(&self.running);
+            let tx = tx.clone();
+            self.capture_threads.push(thread::spawn(move || {
+                Self::capture_loop(fd, interface, link_type, address_filter, running, tx);
+            }));
+        }
+
+        if self.capture_threads.is_empty() {
+            return Err("没有可监控的网卡".into());
+        }
+        println!("AF_PACKET 监控器初始化成功，共 {} 个网卡", self.capture_threads.len());
+        self.last_collect = Some(Instant::now());
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<HashMap<FlowKey, TrafficStats>, Box<dyn Error>> {
+        // 等待到本采样周期结束
+        let interval = Duration::from_secs(self.sample_interval as u64);
+        if let Some(last) = self.last_collect {
+            let elapsed = last.elapsed();
+            if elapsed < interval {
+                thread::sleep(interval - elapsed);
+            }
+        }
+        self.last_collect = Some(Instant::now());
+
+        let receiver = self
+            .stats_receiver
+            .as_ref()
+            .ok_or("stats_receiver 未初始化")?;
+
+        // 汇总本周期内收到的全部数据
+        let mut stats: HashMap<FlowKey, TrafficStats> = HashMap::new();
+        let recv_guard = receiver.lock().unwrap();
+        while let Ok(batch) = recv_guard.try_recv() {
+            for (key, delta) in batch {
+                stats.entry(key).or_default().add(&delta);
+            }
+        }
+
+        Ok(stats)
+    }
+
+    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        self.running.store(false, Ordering::SeqCst);
+
+        // 抓包线程最多 1 秒（读取超时）后退出，套接字随线程释放
+        for handle in self.capture_threads.drain(..) {
+            let _ = handle.join();
+        }
+
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "packet"
+    }
+}
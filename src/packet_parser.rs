@@ -0,0 +1,272 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// 以太网类型
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+const ETHERTYPE_QINQ_OLD: u16 = 0x9100;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const VLAN_TAG_LEN: usize = 4;
+
+// 抓包文件中的链路层类型（LINKTYPE_*）
+pub const LINKTYPE_NULL: u32 = 0;
+pub const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW_OLD: u32 = 12;
+const LINKTYPE_RAW_OLD_OPENBSD: u32 = 14;
+pub const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LOOP: u32 = 108;
+pub const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+pub const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const SLL_HEADER_LEN: usize = 16;
+const SLL2_HEADER_LEN: usize = 20;
+const NULL_HEADER_LEN: usize = 4;
+
+/// 从数据包头部解析出的三层信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketInfo {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub protocol: u8,
+}
+
+/// 解析以太网帧（支持 802.1Q / QinQ VLAN 标签），返回 IP 层信息
+pub fn parse_ethernet(frame: &[u8]) -> Option<PacketInfo> {
+    if frame.len() < ETHERNET_HEADER_LEN {
+        return None;
+    }
+
+    let mut offset = 12;
+    let mut ethertype = read_u16(frame, offset)?;
+    offset += 2;
+
+    // 逐层跳过 VLAN 标签
+    while matches!(ethertype, ETHERTYPE_VLAN | ETHERTYPE_QINQ | ETHERTYPE_QINQ_OLD) {
+        ethertype = read_u16(frame, offset + 2)?;
+        offset += VLAN_TAG_LEN;
+    }
+
+    match ethertype {
+        ETHERTYPE_IPV4 => parse_ipv4(&frame[offset..]),
+        ETHERTYPE_IPV6 => parse_ipv6(&frame[offset..]),
+        _ => None,
+    }
+}
+
+/// 解析不带链路层头部的 IP 数据包（根据版本号区分 IPv4 / IPv6）
+pub fn parse_ip(packet: &[u8]) -> Option<PacketInfo> {
+    match packet.first()? >> 4 {
+        4 => parse_ipv4(packet),
+        6 => parse_ipv6(packet),
+        _ => None,
+    }
+}
+
+/// 按抓包文件的链路层类型解析数据包
+pub fn parse_frame(link_type: u32, frame: &[u8]) -> Option<PacketInfo> {
+    match link_type {
+        LINKTYPE_ETHERNET => parse_ethernet(frame),
+        LINKTYPE_RAW | LINKTYPE_RAW_OLD | LINKTYPE_RAW_OLD_OPENBSD | LINKTYPE_IPV4 | LINKTYPE_IPV6 => {
+            parse_ip(frame)
+        }
+        // BSD 回环：4 字节地址族后直接是 IP 数据包
+        LINKTYPE_NULL | LINKTYPE_LOOP => parse_ip(frame.get(NULL_HEADER_LEN..)?),
+        LINKTYPE_LINUX_SLL => parse_ip(frame.get(SLL_HEADER_LEN..)?),
+        LINKTYPE_LINUX_SLL2 => parse_ip(frame.get(SLL2_HEADER_LEN..)?),
+        _ => None,
+    }
+}
+
+/// Linux cooked capture（tcpdump -i any）记录了数据包类型，可以直接判断方向
+pub fn linux_packet_type(link_type: u32, frame: &[u8]) -> Option<u8> {
+    let pkt_type = match link_type {
+        LINKTYPE_LINUX_SLL => read_u16(frame, 0)?,
+        LINKTYPE_LINUX_SLL2 => *frame.get(10)? as u16,
+        _ => return None,
+    };
+    u8::try_from(pkt_type).ok()
+}
+
+/// 根据数据包类型（PACKET_*）判断方向，返回 (本地IP, 远程IP, 是否为发送)
+pub fn classify_by_packet_type(info: &PacketInfo, pkt_type: u8) -> Option<(IpAddr, IpAddr, bool)> {
+    match pkt_type {
+        libc::PACKET_OUTGOING => Some((info.src, info.dst, true)),
+        libc::PACKET_HOST | libc::PACKET_BROADCAST | libc::PACKET_MULTICAST => Some((info.dst, info.src, false)),
+        // 混杂模式下收到的发往其他主机的数据包
+        _ => None,
+    }
+}
+
+fn parse_ipv4(packet: &[u8]) -> Option<PacketInfo> {
+    if packet.len() < 20 || packet[0] >> 4 != 4 {
+        return None;
+    }
+    let src = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+    let dst = Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]);
+    Some(PacketInfo {
+        src: IpAddr::V4(src),
+        dst: IpAddr::V4(dst),
+        protocol: packet[9],
+    })
+}
+
+fn parse_ipv6(packet: &[u8]) -> Option<PacketInfo> {
+    if packet.len() < 40 || packet[0] >> 4 != 6 {
+        return None;
+    }
+    let src: [u8; 16] = packet[8..24].try_into().ok()?;
+    let dst: [u8; 16] = packet[24..40].try_into().ok()?;
+    Some(PacketInfo {
+        src: IpAddr::V6(Ipv6Addr::from(src)),
+        dst: IpAddr::V6(Ipv6Addr::from(dst)),
+        protocol: packet[6],
+    })
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// 构造最小的 IPv4 头部（只填版本、协议号和地址）
+    pub(crate) fn ipv4_packet(src: [u8; 4], dst: [u8; 4], protocol: u8) -> Vec<u8> {
+        let mut packet = vec![0u8; 20];
+        packet[0] = 0x45;
+        packet[9] = protocol;
+        packet[12..16].copy_from_slice(&src);
+        packet[16..20].copy_from_slice(&dst);
+        packet
+    }
+
+    fn ipv6_packet(src: Ipv6Addr, dst: Ipv6Addr, next_header: u8) -> Vec<u8> {
+        let mut packet = vec![0u8; 40];
+        packet[0] = 0x60;
+        packet[6] = next_header;
+        packet[8..24].copy_from_slice(&src.octets());
+        packet[24..40].copy_from_slice(&dst.octets());
+        packet
+    }
+
+    /// 以太网帧：目的 / 源 MAC + 以太网类型链（VLAN 标签按顺序插入）+ 载荷
+    pub(crate) fn ethernet_frame(ethertypes: &[u16], payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        for (i, ethertype) in ethertypes.iter().enumerate() {
+            frame.extend_from_slice(&ethertype.to_be_bytes());
+            // VLAN 标签后面是 2 字节的 TCI
+            if i + 1 < ethertypes.len() {
+                frame.extend_from_slice(&[0x00, 0x64]);
+            }
+        }
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn v4(a: u8, b: u8, c: u8, d: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(a, b, c, d))
+    }
+
+    #[test]
+    fn ethernet_ipv4() {
+        let frame = ethernet_frame(&[ETHERTYPE_IPV4], &ipv4_packet([10, 0, 0, 1], [192, 0, 2, 9], 6));
+        let info = parse_ethernet(&frame).unwrap();
+        assert_eq!(info.src, v4(10, 0, 0, 1));
+        assert_eq!(info.dst, v4(192, 0, 2, 9));
+        assert_eq!(info.protocol, 6);
+        assert_eq!(parse_frame(LINKTYPE_ETHERNET, &frame), Some(info));
+    }
+
+    #[test]
+    fn ethernet_ipv6() {
+        let src: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let dst: Ipv6Addr = "fe80::2".parse().unwrap();
+        let frame = ethernet_frame(&[ETHERTYPE_IPV6], &ipv6_packet(src, dst, 17));
+        let info = parse_ethernet(&frame).unwrap();
+        assert_eq!(info.src, IpAddr::V6(src));
+        assert_eq!(info.dst, IpAddr::V6(dst));
+        assert_eq!(info.protocol, 17);
+    }
+
+    #[test]
+    fn vlan_and_qinq_tags() {
+        let packet = ipv4_packet([10, 0, 0, 1], [10, 0, 0, 2], 1);
+        for ethertypes in [
+            &[ETHERTYPE_VLAN, ETHERTYPE_IPV4][..],
+            &[ETHERTYPE_QINQ, ETHERTYPE_VLAN, ETHERTYPE_IPV4][..],
+            &[ETHERTYPE_QINQ_OLD, ETHERTYPE_VLAN, ETHERTYPE_IPV4][..],
+        ] {
+            let info = parse_ethernet(&ethernet_frame(ethertypes, &packet)).unwrap();
+            assert_eq!((info.src, info.dst, info.protocol), (v4(10, 0, 0, 1), v4(10, 0, 0, 2), 1));
+        }
+    }
+
+    #[test]
+    fn truncated_and_unknown_frames() {
+        let packet = ipv4_packet([10, 0, 0, 1], [10, 0, 0, 2], 6);
+        let frame = ethernet_frame(&[ETHERTYPE_IPV4], &packet);
+        assert_eq!(parse_ethernet(&frame[..13]), None);
+        assert_eq!(parse_ethernet(&frame[..frame.len() - 1]), None);
+
+        // VLAN 标签在内层以太网类型之前被截断
+        let vlan = ethernet_frame(&[ETHERTYPE_VLAN, ETHERTYPE_IPV4], &packet);
+        assert_eq!(parse_ethernet(&vlan[..16]), None);
+        assert_eq!(parse_ethernet(&[0x81, 0x00].repeat(7)), None);
+
+        // ARP 等非 IP 帧
+        assert_eq!(parse_ethernet(&ethernet_frame(&[0x0806], &[0u8; 28])), None);
+        // 以太网类型与 IP 版本号不一致
+        let ipv6 = ipv6_packet(Ipv6Addr::LOCALHOST, Ipv6Addr::LOCALHOST, 6);
+        assert_eq!(parse_ethernet(&ethernet_frame(&[ETHERTYPE_IPV4], &ipv6)), None);
+        assert_eq!(parse_ethernet(&ethernet_frame(&[ETHERTYPE_IPV6], &packet)), None);
+    }
+
+    #[test]
+    fn other_link_types() {
+        let packet = ipv4_packet([10, 0, 0, 1], [10, 0, 0, 2], 17);
+        let expected = parse_ip(&packet).unwrap();
+        assert_eq!(parse_frame(LINKTYPE_RAW, &packet), Some(expected));
+
+        let mut null = vec![2, 0, 0, 0];
+        null.extend_from_slice(&packet);
+        assert_eq!(parse_frame(LINKTYPE_NULL, &null), Some(expected));
+
+        // SLL：数据包类型在前 2 字节（PACKET_OUTGOING = 4）
+        let mut sll = vec![0u8; SLL_HEADER_LEN];
+        sll[1] = libc::PACKET_OUTGOING;
+        sll.extend_from_slice(&packet);
+        assert_eq!(parse_frame(LINKTYPE_LINUX_SLL, &sll), Some(expected));
+        assert_eq!(linux_packet_type(LINKTYPE_LINUX_SLL, &sll), Some(libc::PACKET_OUTGOING));
+
+        // SLL2：数据包类型在第 10 字节
+        let mut sll2 = vec![0u8; SLL2_HEADER_LEN];
+        sll2[10] = libc::PACKET_HOST;
+        sll2.extend_from_slice(&packet);
+        assert_eq!(parse_frame(LINKTYPE_LINUX_SLL2, &sll2), Some(expected));
+        assert_eq!(linux_packet_type(LINKTYPE_LINUX_SLL2, &sll2), Some(libc::PACKET_HOST));
+        assert_eq!(linux_packet_type(LINKTYPE_ETHERNET, &sll2), None);
+
+        assert_eq!(parse_frame(LINKTYPE_LINUX_SLL, &sll[..SLL_HEADER_LEN - 1]), None);
+        assert_eq!(parse_frame(9999, &packet), None);
+    }
+
+    #[test]
+    fn direction_from_packet_type() {
+        let info = parse_ip(&ipv4_packet([10, 0, 0, 1], [10, 0, 0, 2], 6)).unwrap();
+        assert_eq!(
+            classify_by_packet_type(&info, libc::PACKET_OUTGOING),
+            Some((info.src, info.dst, true))
+        );
+        assert_eq!(
+            classify_by_packet_type(&info, libc::PACKET_HOST),
+            Some((info.dst, info.src, false))
+        );
+        assert_eq!(classify_by_packet_type(&info, libc::PACKET_OTHERHOST), None);
+    }
+}
@@ -0,0 +1,299 @@
+use crate::address_filter::{AddressFilter, LocalNetworks};
+use crate::monitor::{FlowKey, InterfaceSelector, TrafficMonitor, TrafficStats, ANY_INTERFACE};
+use crate::packet_parser::{self, PacketInfo};
+use crate::pcap_reader::{PcapPacket, PcapReader};
+use chrono::{Local, TimeZone};
+use std::collections::HashMap;
+use std::error::Error;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// 离线回放 pcap / pcapng 抓包文件的监控器
+///
+/// 按数据包时间戳把文件切分为 sample_interval 长度的窗口，每次 start() 返回一个窗口内的流量，
+/// 没有数据包的窗口直接跳过。文件读完后 is_finished() 返回 true。
+pub struct PcapMonitor {
+    path: String,
+    sample_interval: u32,
+    address_filter: AddressFilter,
+    local_networks: LocalNetworks,
+    interfaces: InterfaceSelector,
+    reader: Option<PcapReader>,
+    // 已读出但属于下一个窗口的数据包
+    pending: Option<PcapPacket>,
+    first_timestamp_ns: Option<u64>,
+    last_timestamp_ns: u64,
+    // 上一个窗口的结束时间，用于计算跳过空窗口后的周期时长
+    last_window_end_ns: Option<u64>,
+    cycle_duration: Option<Duration>,
+    finished: bool,
+    total_packets: u64,
+    counted_packets: u64,
+}
+
+impl PcapMonitor {
+    pub fn new(
+        path: String,
+        sample_interval: u32,
+        address_filter: AddressFilter,
+        local_networks: LocalNetworks,
+        interfaces: InterfaceSelector,
+    ) -> Self {
+        Self {
+            path,
+            sample_interval,
+            address_filter,
+            local_networks,
+            interfaces,
+            reader: None,
+            pending: None,
+            first_timestamp_ns: None,
+            last_timestamp_ns: 0,
+            last_window_end_ns: None,
+            cycle_duration: None,
+            finished: false,
+            total_packets: 0,
+            counted_packets: 0,
+        }
+    }
+
+    /// 读取下一个数据包（优先返回上一个窗口多读出的数据包）
+    fn next_packet(&mut self) -> Option<PcapPacket> {
+        if let Some(packet) = self.pending.take() {
+            return Some(packet);
+        }
+        let reader = self.reader.as_mut()?;
+        match reader.next_packet() {
+            Ok(packet) => packet,
+            Err(e) => {
+                // 截断的文件（例如 tcpdump 被强制结束）按文件结束处理
+                eprintln!("[警告] 读取抓包文件失败，回放提前结束: {}", e);
+                None
+            }
+        }
+    }
+
+    /// 判断数据包方向，返回 (本地IP, 远程IP, 是否为发送)
+    ///
+    /// Linux cooked capture 直接使用记录的数据包类型；否则按本地地址规则判断，两端都是或都不是本地地址时丢弃。
+    fn classify(&self, packet: &PcapPacket, info: &PacketInfo) -> Option<(IpAddr, IpAddr, bool)> {
+        if let Some(pkt_type) = packet_parser::linux_packet_type(packet.link_type, &packet.data) {
+            return packet_parser::classify_by_packet_type(info, pkt_type);
+        }
+
+        match (
+            self.local_networks.contains(&info.src),
+            self.local_networks.contains(&info.dst),
+        ) {
+            (true, false) => Some((info.src, info.dst, true)),
+            (false, true) => Some((info.dst, info.src, false)),
+            _ => None,
+        }
+    }
+
+    /// 把一个数据包累加到窗口统计中
+    fn account(&mut self, packet: &PcapPacket, stats: &mut HashMap<FlowKey, TrafficStats>) {
+        self.total_packets += 1;
+
+        let interface = packet.interface.as_deref().unwrap_or(ANY_INTERFACE);
+        if packet.interface.is_some() && !self.interfaces.matches(interface) {
+            return;
+        }
+        let Some(info) = packet_parser::parse_frame(packet.link_type, &packet.data) else {
+            return;
+        };
+        let Some((local_ip, remote_ip, is_tx)) = self.classify(packet, &info) else {
+            return;
+        };
+        if !self.address_filter.is_allowed(&remote_ip) {
+            return;
+        }
+
+        self.counted_packets += 1;
+        let entry = stats
+            .entry(FlowKey::new(interface, local_ip.to_string(), remote_ip.to_string()))
+            .or_default();
+        if is_tx {
+            entry.tx_bytes += packet.orig_len as u64;
+            entry.tx_packets += 1;
+        } else {
+            entry.rx_bytes += packet.orig_len as u64;
+            entry.rx_packets += 1;
+        }
+    }
+
+    fn format_timestamp(timestamp_ns: u64) -> String {
+        Local
+            .timestamp_nanos(timestamp_ns as i64)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    }
+}
+
+impl TrafficMonitor for PcapMonitor {
+    fn init(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.sample_interval == 0 {
+            return Err("采样间隔必须大于 0".into());
+        }
+        self.reader = Some(PcapReader::open(&self.path)?);
+        println!("pcap 回放监控器初始化成功，文件: {}", self.path);
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<HashMap<FlowKey, TrafficStats>, Box<dyn Error>> {
+        let mut stats: HashMap<FlowKey, TrafficStats> = HashMap::new();
+
+        let Some(first_packet) = self.next_packet() else {
+            self.finished = true;
+            self.cycle_duration = Some(Duration::ZERO);
+            return Ok(stats);
+        };
+
+        // 窗口按第一个数据包的时间对齐
+        let interval_ns = self.sample_interval as u64 * 1_000_000_000;
+        let origin = *self.first_timestamp_ns.get_or_insert(first_packet.timestamp_ns);
+        let window_index = first_packet.timestamp_ns.saturating_sub(origin) / interval_ns;
+        let window_start = origin + window_index * interval_ns;
+        let window_end = window_start + interval_ns;
+        // 周期时长按抓包时间计算，包含跳过的空窗口
+        let cycle_ns = window_end - self.last_window_end_ns.unwrap_or(window_start);
+        self.cycle_duration = Some(Duration::from_nanos(cycle_ns));
+        self.last_window_end_ns = Some(window_end);
+
+        self.last_timestamp_ns = self.last_timestamp_ns.max(first_packet.timestamp_ns);
+        self.account(&first_packet, &mut stats);
+
+        while let Some(packet) = self.next_packet() {
+            if packet.timestamp_ns >= window_end {
+                self.pending = Some(packet);
+                break;
+            }
+            // 乱序的数据包计入当前窗口
+            self.last_timestamp_ns = self.last_timestamp_ns.max(packet.timestamp_ns);
+            self.account(&packet, &mut stats);
+        }
+
+        if self.pending.is_none() {
+            self.finished = true;
+        }
+
+        println!(
+            "回放窗口: {} ~ {}",
+            Self::format_timestamp(window_start),
+            Self::format_timestamp(window_end)
+        );
+
+        Ok(stats)
+    }
+
+    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        self.reader = None;
+        if let Some(first) = self.first_timestamp_ns {
+            println!(
+                "回放结束：抓包时间 {} ~ {}，共 {} 个数据包，统计 {} 个",
+                Self::format_timestamp(first),
+                Self::format_timestamp(self.last_timestamp_ns),
+                self.total_packets,
+                self.counted_packets
+            );
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "pcap"
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn cycle_duration(&self) -> Option<Duration> {
+        self.cycle_duration
+    }
+
+    fn is_local(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet_parser::tests::{ethernet_frame, ipv4_packet};
+    use crate::pcap_reader::tests::pcap_file;
+
+    fn monitor(name: &str, bytes: &[u8], sample_interval: u32) -> PcapMonitor {
+        let path = std::env::temp_dir().join(format!("pcap_monitor_{}_{}.pcap", name, std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        let local_networks = LocalNetworks::new(vec!["10.0.0.0/8".parse().unwrap()]);
+        let mut monitor = PcapMonitor::new(
+            path.to_string_lossy().into_owned(),
+            sample_interval,
+            AddressFilter::default(),
+            local_networks,
+            InterfaceSelector::new(Vec::new(), Vec::new()),
+        );
+        monitor.init().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        monitor
+    }
+
+    fn flow(local: &str, remote: &str) -> FlowKey {
+        FlowKey::new(ANY_INTERFACE, local, remote)
+    }
+
+    #[test]
+    fn windows_follow_capture_time() {
+        let tx = ethernet_frame(&[0x0800], &ipv4_packet([10, 0, 0, 1], [192, 0, 2, 9], 6));
+        let rx = ethernet_frame(&[0x0800], &ipv4_packet([192, 0, 2, 9], [10, 0, 0, 1], 6));
+        // 两端都不是本地地址的数据包不计入统计
+        let transit = ethernet_frame(&[0x0800], &ipv4_packet([192, 0, 2, 1], [192, 0, 2, 9], 6));
+        let bytes = pcap_file(
+            false,
+            false,
+            65535,
+            1,
+            &[
+                (100, 200_000, &tx),
+                (100, 900_000, &rx),
+                (101, 0, &transit),
+                (101, 100_000, &tx),
+                // 乱序的数据包计入当前窗口
+                (100, 950_000, &tx),
+                // 102 ~ 104 秒没有数据包
+                (105, 300_000, &rx),
+            ],
+        );
+        let mut monitor = monitor("windows", &bytes, 2);
+        let key = flow("10.0.0.1", "192.0.2.9");
+        // 统计的是线路上的原始长度（pcap_file 中为保存长度 + 100）
+        let len = tx.len() as u64 + 100;
+
+        // 第一个窗口：100.2 ~ 102.2 秒
+        let stats = monitor.start().unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!((stats[&key].tx_packets, stats[&key].rx_packets), (3, 1));
+        assert_eq!((stats[&key].tx_bytes, stats[&key].rx_bytes), (3 * len, len));
+        assert_eq!(monitor.cycle_duration(), Some(Duration::from_secs(2)));
+        assert!(!monitor.is_finished());
+
+        // 102.2 ~ 104.2 秒为空窗口被跳过，第二个窗口为 104.2 ~ 106.2 秒，周期时长包含跳过的窗口
+        let stats = monitor.start().unwrap();
+        assert_eq!((stats[&key].tx_packets, stats[&key].rx_packets), (0, 1));
+        assert_eq!(monitor.cycle_duration(), Some(Duration::from_secs(4)));
+        assert!(monitor.is_finished());
+
+        assert_eq!(monitor.total_packets, 6);
+        assert_eq!(monitor.counted_packets, 5);
+        assert_eq!(monitor.last_timestamp_ns, 105_300_000_000);
+    }
+
+    #[test]
+    fn empty_file_finishes_immediately() {
+        let mut monitor = monitor("empty", &pcap_file(true, true, 65535, 1, &[]), 1);
+        assert!(monitor.start().unwrap().is_empty());
+        assert!(monitor.is_finished());
+        assert_eq!(monitor.cycle_duration(), Some(Duration::ZERO));
+    }
+}
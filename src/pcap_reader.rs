@@ -0,0 +1,579 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+
+// 经典 pcap 文件的魔数（微秒 / 纳秒时间戳）
+const PCAP_MAGIC_US: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NS: u32 = 0xa1b2_3c4d;
+
+// pcapng 块类型
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const PCAPNG_OBSOLETE_PACKET: u32 = 0x0000_0002;
+const PCAPNG_SIMPLE_PACKET: u32 = 0x0000_0003;
+const PCAPNG_ENHANCED_PACKET: u32 = 0x0000_0006;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+
+// pcapng 接口描述块的选项
+const OPT_END: u16 = 0;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_TSRESOL: u16 = 9;
+
+// 单个数据包保存长度的下限（文件头中的 snaplen 更小时仍允许到该长度，兼容 snaplen 为 0 等不规范的文件）
+const MAX_SNAPLEN: usize = 262_144;
+
+// 单个数据包 / pcapng 块的最大长度，防止损坏或恶意的文件导致巨大的内存分配
+const MAX_RECORD_LEN: usize = 16 * 1024 * 1024;
+
+/// 从抓包文件中读出的一个数据包
+#[derive(Debug, Clone)]
+pub struct PcapPacket {
+    /// 抓包时间（Unix 纪元以来的纳秒数）
+    pub timestamp_ns: u64,
+    /// 链路层类型（LINKTYPE_*）
+    pub link_type: u32,
+    /// 抓包网卡名（仅 pcapng 文件记录了 if_name 时可用）
+    pub interface: Option<String>,
+    /// 数据包在线路上的原始长度
+    pub orig_len: u32,
+    /// 实际保存的数据（可能被 snaplen 截断）
+    pub data: Vec<u8>,
+}
+
+/// pcapng 文件中的接口描述
+#[derive(Debug, Clone)]
+struct PcapngInterface {
+    link_type: u32,
+    name: Option<String>,
+    tsresol: u8,
+}
+
+enum Format {
+    Pcap {
+        big_endian: bool,
+        nanosecond: bool,
+        link_type: u32,
+        snaplen: usize,
+    },
+    Pcapng {
+        big_endian: bool,
+        interfaces: Vec<PcapngInterface>,
+    },
+}
+
+/// pcap / pcapng 文件读取器（自动识别格式和字节序）
+pub struct PcapReader {
+    reader: Box<dyn Read + Send + Sync>,
+    format: Format,
+}
+
+impl PcapReader {
+    pub fn open(path: &str) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(path).map_err(|e| format!("打开抓包文件 {} 失败: {}", path, e))?;
+        Self::from_reader(BufReader::new(file)).map_err(|e| format!("读取抓包文件 {} 失败: {}", path, e).into())
+    }
+
+    /// 从任意数据源读取（文件头在此处解析）
+    pub fn from_reader(reader: impl Read + Send + Sync + 'static) -> Result<Self, Box<dyn Error>> {
+        let mut reader: Box<dyn Read + Send + Sync> = Box::new(reader);
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+
+        let format = if u32::from_le_bytes(magic) == PCAPNG_SECTION_HEADER {
+            let big_endian = Self::read_section_header(&mut reader)?;
+            Format::Pcapng {
+                big_endian,
+                interfaces: Vec::new(),
+            }
+        } else {
+            let (big_endian, nanosecond) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+                (PCAP_MAGIC_US, _) => (false, false),
+                (PCAP_MAGIC_NS, _) => (false, true),
+                (_, PCAP_MAGIC_US) => (true, false),
+                (_, PCAP_MAGIC_NS) => (true, true),
+                _ => return Err("不是 pcap / pcapng 文件".into()),
+            };
+            // 文件头剩余部分：版本号(4) + 时区(4) + 精度(4) + snaplen(4) + 链路类型(4)
+            let mut header = [0u8; 20];
+            reader.read_exact(&mut header)?;
+            Format::Pcap {
+                big_endian,
+                nanosecond,
+                link_type: read_u32(&header[16..20], big_endian) & 0x0fff_ffff,
+                snaplen: read_u32(&header[12..16], big_endian) as usize,
+            }
+        };
+
+        Ok(Self { reader, format })
+    }
+
+    /// 读取下一个数据包，文件结束时返回 None
+    pub fn next_packet(&mut self) -> Result<Option<PcapPacket>, Box<dyn Error>> {
+        match self.format {
+            Format::Pcap { .. } => self.next_pcap_packet(),
+            Format::Pcapng { .. } => self.next_pcapng_packet(),
+        }
+    }
+
+    fn next_pcap_packet(&mut self) -> Result<Option<PcapPacket>, Box<dyn Error>> {
+        let Format::Pcap { big_endian, nanosecond, link_type, snaplen } = self.format else {
+            unreachable!()
+        };
+
+        let mut header = [0u8; 16];
+        if !read_exact_or_eof(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+        let ts_sec = read_u32(&header[0..4], big_endian) as u64;
+        let ts_frac = read_u32(&header[4..8], big_endian) as u64;
+        let caplen = read_u32(&header[8..12], big_endian) as usize;
+        let orig_len = read_u32(&header[12..16], big_endian);
+        if caplen > snaplen.clamp(MAX_SNAPLEN, MAX_RECORD_LEN) {
+            return Err(format!("pcap 数据包长度无效: {}（snaplen {}）", caplen, snaplen).into());
+        }
+
+        let mut data = vec![0u8; caplen];
+        self.reader.read_exact(&mut data)?;
+
+        let frac_ns = if nanosecond { ts_frac } else { ts_frac * 1_000 };
+        Ok(Some(PcapPacket {
+            timestamp_ns: ts_sec * 1_000_000_000 + frac_ns,
+            link_type,
+            interface: None,
+            orig_len,
+            data,
+        }))
+    }
+
+    fn next_pcapng_packet(&mut self) -> Result<Option<PcapPacket>, Box<dyn Error>> {
+        loop {
+            let Format::Pcapng { big_endian, .. } = self.format else {
+                unreachable!()
+            };
+
+            let mut header = [0u8; 8];
+            if !read_exact_or_eof(&mut self.reader, &mut header)? {
+                return Ok(None);
+            }
+            let block_type = read_u32(&header[0..4], big_endian);
+
+            // 新的 section：字节序可能变化，接口列表重新开始
+            if block_type == PCAPNG_SECTION_HEADER {
+                let big_endian = Self::read_section_header_body(&mut self.reader, &header[4..8])?;
+                self.format = Format::Pcapng {
+                    big_endian,
+                    interfaces: Vec::new(),
+                };
+                continue;
+            }
+
+            let total_len = read_u32(&header[4..8], big_endian) as usize;
+            if total_len < 12 || !total_len.is_multiple_of(4) || total_len > MAX_RECORD_LEN {
+                return Err(format!("pcapng 块长度无效: {}", total_len).into());
+            }
+            // 块内容 + 结尾的重复长度字段
+            let mut body = vec![0u8; total_len - 8];
+            self.reader.read_exact(&mut body)?;
+            let body = &body[..body.len() - 4];
+
+            let Format::Pcapng { interfaces, .. } = &mut self.format else {
+                unreachable!()
+            };
+
+            match block_type {
+                PCAPNG_INTERFACE_DESCRIPTION => {
+                    interfaces.push(parse_interface_description(body, big_endian)?);
+                }
+                PCAPNG_ENHANCED_PACKET | PCAPNG_OBSOLETE_PACKET => {
+                    if body.len() < 20 {
+                        return Err("pcapng 数据包块过短".into());
+                    }
+                    let interface_id = if block_type == PCAPNG_ENHANCED_PACKET {
+                        read_u32(&body[0..4], big_endian) as usize
+                    } else {
+                        read_u16(&body[0..2], big_endian) as usize
+                    };
+                    let ts_high = read_u32(&body[4..8], big_endian) as u64;
+                    let ts_low = read_u32(&body[8..12], big_endian) as u64;
+                    let caplen = read_u32(&body[12..16], big_endian) as usize;
+                    let orig_len = read_u32(&body[16..20], big_endian);
+                    let data = body
+                        .get(20..20 + caplen)
+                        .ok_or("pcapng 数据包长度超出块范围")?
+                        .to_vec();
+
+                    let interface = interfaces
+                        .get(interface_id)
+                        .ok_or_else(|| format!("pcapng 数据包引用了未定义的接口 {}", interface_id))?;
+                    return Ok(Some(PcapPacket {
+                        timestamp_ns: timestamp_to_ns((ts_high << 32) | ts_low, interface.tsresol),
+                        link_type: interface.link_type,
+                        interface: interface.name.clone(),
+                        orig_len,
+                        data,
+                    }));
+                }
+                // 简单数据包块没有时间戳，无法切分采样窗口，直接跳过
+                PCAPNG_SIMPLE_PACKET => {}
+                // 统计、名称解析等其他块不影响流量统计
+                _ => {}
+            }
+        }
+    }
+
+    /// 读取 section header block（魔数已读取），返回该 section 是否为大端序
+    fn read_section_header(reader: &mut dyn Read) -> Result<bool, Box<dyn Error>> {
+        let mut len_bytes = [0u8; 4];
+        reader.read_exact(&mut len_bytes)?;
+        Self::read_section_header_body(reader, &len_bytes)
+    }
+
+    fn read_section_header_body(reader: &mut dyn Read, len_bytes: &[u8]) -> Result<bool, Box<dyn Error>> {
+        let mut bom = [0u8; 4];
+        reader.read_exact(&mut bom)?;
+        let big_endian = match u32::from_le_bytes(bom) {
+            PCAPNG_BYTE_ORDER_MAGIC => false,
+            m if m.swap_bytes() == PCAPNG_BYTE_ORDER_MAGIC => true,
+            _ => return Err("pcapng 字节序标记无效".into()),
+        };
+
+        let total_len = read_u32(len_bytes, big_endian) as usize;
+        if !(28..=MAX_RECORD_LEN).contains(&total_len) {
+            return Err(format!("pcapng section header 长度无效: {}", total_len).into());
+        }
+        // 跳过版本号、section 长度、选项和结尾的长度字段
+        let mut rest = vec![0u8; total_len - 12];
+        reader.read_exact(&mut rest)?;
+        Ok(big_endian)
+    }
+}
+
+fn parse_interface_description(body: &[u8], big_endian: bool) -> Result<PcapngInterface, Box<dyn Error>> {
+    if body.len() < 8 {
+        return Err("pcapng 接口描述块过短".into());
+    }
+    let mut interface = PcapngInterface {
+        link_type: read_u16(&body[0..2], big_endian) as u32,
+        name: None,
+        tsresol: 6,
+    };
+
+    let mut offset = 8;
+    while offset + 4 <= body.len() {
+        let code = read_u16(&body[offset..offset + 2], big_endian);
+        let len = read_u16(&body[offset + 2..offset + 4], big_endian) as usize;
+        let Some(value) = body.get(offset + 4..offset + 4 + len) else {
+            break;
+        };
+        match code {
+            OPT_END => break,
+            OPT_IF_NAME => {
+                let name = String::from_utf8_lossy(value).trim_end_matches('\0').to_string();
+                if !name.is_empty() {
+                    interface.name = Some(name);
+                }
+            }
+            OPT_IF_TSRESOL => {
+                if let Some(&resol) = value.first() {
+                    interface.tsresol = resol;
+                }
+            }
+            _ => {}
+        }
+        // 选项值按 4 字节对齐
+        offset += 4 + len.div_ceil(4) * 4;
+    }
+
+    Ok(interface)
+}
+
+/// 按接口的时间戳精度把时间戳换算为纳秒
+fn timestamp_to_ns(ts: u64, tsresol: u8) -> u64 {
+    let exponent = (tsresol & 0x7f) as u32;
+    if tsresol & 0x80 == 0 {
+        // 精度为 10^-exponent 秒
+        if exponent <= 9 {
+            ts.saturating_mul(10u64.pow(9 - exponent))
+        } else {
+            ts / 10u64.pow(exponent.min(19) - 9)
+        }
+    } else {
+        // 精度为 2^-exponent 秒
+        ((ts as u128 * 1_000_000_000) >> exponent.min(127)) as u64
+    }
+}
+
+/// 读满缓冲区；在文件末尾（未读到任何数据）时返回 false
+fn read_exact_or_eof(reader: &mut impl Read, buf: &mut [u8]) -> Result<bool, Box<dyn Error>> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn read_u16(bytes: &[u8], big_endian: bool) -> u16 {
+    let bytes = [bytes[0], bytes[1]];
+    if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    }
+}
+
+fn read_u32(bytes: &[u8], big_endian: bool) -> u32 {
+    let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+    if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn put_u16(buf: &mut Vec<u8>, value: u16, big_endian: bool) {
+        buf.extend_from_slice(&if big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+    }
+
+    fn put_u32(buf: &mut Vec<u8>, value: u32, big_endian: bool) {
+        buf.extend_from_slice(&if big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+    }
+
+    /// 经典 pcap 文件：packets 为 (秒, 秒内的微秒 / 纳秒, 数据)
+    pub(crate) fn pcap_file(
+        big_endian: bool,
+        nanosecond: bool,
+        snaplen: u32,
+        link_type: u32,
+        packets: &[(u32, u32, &[u8])],
+    ) -> Vec<u8> {
+        let mut buf = Vec::new();
+        put_u32(&mut buf, if nanosecond { PCAP_MAGIC_NS } else { PCAP_MAGIC_US }, big_endian);
+        put_u16(&mut buf, 2, big_endian);
+        put_u16(&mut buf, 4, big_endian);
+        put_u32(&mut buf, 0, big_endian);
+        put_u32(&mut buf, 0, big_endian);
+        put_u32(&mut buf, snaplen, big_endian);
+        put_u32(&mut buf, link_type, big_endian);
+        for (sec, frac, data) in packets {
+            put_u32(&mut buf, *sec, big_endian);
+            put_u32(&mut buf, *frac, big_endian);
+            put_u32(&mut buf, data.len() as u32, big_endian);
+            // 原始长度大于保存长度，模拟被 snaplen 截断的数据包
+            put_u32(&mut buf, data.len() as u32 + 100, big_endian);
+            buf.extend_from_slice(data);
+        }
+        buf
+    }
+
+    /// pcapng 块：类型 + 长度 + 内容（补齐到 4 字节）+ 重复的长度
+    fn block(block_type: u32, body: &[u8], big_endian: bool) -> Vec<u8> {
+        let padded = body.len().div_ceil(4) * 4;
+        let mut buf = Vec::new();
+        put_u32(&mut buf, block_type, big_endian);
+        put_u32(&mut buf, (padded + 12) as u32, big_endian);
+        buf.extend_from_slice(body);
+        buf.resize(8 + padded, 0);
+        put_u32(&mut buf, (padded + 12) as u32, big_endian);
+        buf
+    }
+
+    fn section_header(big_endian: bool) -> Vec<u8> {
+        let mut body = Vec::new();
+        put_u32(&mut body, PCAPNG_BYTE_ORDER_MAGIC, big_endian);
+        put_u16(&mut body, 1, big_endian);
+        put_u16(&mut body, 0, big_endian);
+        body.extend_from_slice(&[0xff; 8]);
+        block(PCAPNG_SECTION_HEADER, &body, big_endian)
+    }
+
+    fn interface_description(link_type: u16, name: Option<&str>, tsresol: Option<u8>, big_endian: bool) -> Vec<u8> {
+        let mut body = Vec::new();
+        put_u16(&mut body, link_type, big_endian);
+        put_u16(&mut body, 0, big_endian);
+        put_u32(&mut body, 0, big_endian);
+        let mut option = |code: u16, value: &[u8]| {
+            put_u16(&mut body, code, big_endian);
+            put_u16(&mut body, value.len() as u16, big_endian);
+            body.extend_from_slice(value);
+            body.resize(body.len().div_ceil(4) * 4, 0);
+        };
+        if let Some(name) = name {
+            option(OPT_IF_NAME, name.as_bytes());
+        }
+        if let Some(tsresol) = tsresol {
+            option(OPT_IF_TSRESOL, &[tsresol]);
+        }
+        option(OPT_END, &[]);
+        block(PCAPNG_INTERFACE_DESCRIPTION, &body, big_endian)
+    }
+
+    fn enhanced_packet(interface_id: u32, timestamp: u64, data: &[u8], big_endian: bool) -> Vec<u8> {
+        let mut body = Vec::new();
+        put_u32(&mut body, interface_id, big_endian);
+        put_u32(&mut body, (timestamp >> 32) as u32, big_endian);
+        put_u32(&mut body, timestamp as u32, big_endian);
+        put_u32(&mut body, data.len() as u32, big_endian);
+        put_u32(&mut body, data.len() as u32, big_endian);
+        body.extend_from_slice(data);
+        block(PCAPNG_ENHANCED_PACKET, &body, big_endian)
+    }
+
+    fn read_all(bytes: Vec<u8>) -> Result<Vec<PcapPacket>, Box<dyn Error>> {
+        let mut reader = PcapReader::from_reader(Cursor::new(bytes))?;
+        let mut packets = Vec::new();
+        while let Some(packet) = reader.next_packet()? {
+            packets.push(packet);
+        }
+        Ok(packets)
+    }
+
+    #[test]
+    fn pcap_timestamp_precision_and_byte_order() {
+        for big_endian in [false, true] {
+            let bytes = pcap_file(big_endian, false, 65535, 1, &[(1_700_000_000, 250_000, b"abc"), (1_700_000_001, 0, b"")]);
+            let packets = read_all(bytes).unwrap();
+            assert_eq!(packets.len(), 2);
+            assert_eq!(packets[0].timestamp_ns, 1_700_000_000_250_000_000);
+            assert_eq!(packets[0].data, b"abc");
+            assert_eq!(packets[0].orig_len, 103);
+            assert_eq!(packets[0].link_type, 1);
+            assert_eq!(packets[0].interface, None);
+            assert_eq!(packets[1].timestamp_ns, 1_700_000_001_000_000_000);
+
+            let bytes = pcap_file(big_endian, true, 65535, 101, &[(1_700_000_000, 123_456_789, b"x")]);
+            let packets = read_all(bytes).unwrap();
+            assert_eq!(packets[0].timestamp_ns, 1_700_000_000_123_456_789);
+            assert_eq!(packets[0].link_type, 101);
+        }
+    }
+
+    #[test]
+    fn pcap_record_length_limits() {
+        // 超出 snaplen 但不超过 MAX_SNAPLEN 的数据包仍然接受（兼容不规范的 snaplen）
+        let data = vec![0u8; 70_000];
+        assert_eq!(read_all(pcap_file(false, false, 0, 1, &[(1, 0, &data)])).unwrap()[0].data.len(), 70_000);
+
+        // 超过 MAX_SNAPLEN 且超过 snaplen 的数据包被拒绝，不会按记录头中的长度分配内存
+        let mut bytes = pcap_file(false, false, 65535, 1, &[]);
+        bytes.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&(MAX_SNAPLEN as u32 + 1).to_le_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        assert!(read_all(bytes).unwrap_err().to_string().contains("长度无效"));
+
+        // snaplen 再大也不能超过 MAX_RECORD_LEN
+        let mut bytes = pcap_file(true, false, u32::MAX, 1, &[]);
+        bytes.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&u32::MAX.to_be_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        assert!(read_all(bytes).unwrap_err().to_string().contains("长度无效"));
+    }
+
+    #[test]
+    fn pcap_truncated_and_invalid_files() {
+        assert!(PcapReader::from_reader(Cursor::new(b"GIF89a".to_vec())).is_err());
+        assert!(PcapReader::from_reader(Cursor::new(Vec::new())).is_err());
+        assert!(PcapReader::from_reader(Cursor::new(PCAP_MAGIC_US.to_le_bytes().to_vec())).is_err());
+
+        // 记录头完整但数据被截断
+        let mut bytes = pcap_file(false, false, 65535, 1, &[(1, 0, b"abcdef")]);
+        bytes.truncate(bytes.len() - 2);
+        assert!(read_all(bytes).is_err());
+        // 记录头被截断视为文件结束
+        let mut bytes = pcap_file(false, false, 65535, 1, &[(1, 0, b"abcdef")]);
+        bytes.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(read_all(bytes).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn pcapng_interfaces_and_timestamp_resolution() {
+        for big_endian in [false, true] {
+            let mut bytes = section_header(big_endian);
+            // 默认精度为微秒
+            bytes.extend(interface_description(1, Some("eth0"), None, big_endian));
+            // tsresol = 9：纳秒
+            bytes.extend(interface_description(113, Some("any"), Some(9), big_endian));
+            // tsresol 最高位为 1：2^-10 秒
+            bytes.extend(interface_description(101, None, Some(0x80 | 10), big_endian));
+            bytes.extend(enhanced_packet(0, 1_700_000_000_500_000, b"first", big_endian));
+            // 与数据包无关的块被跳过
+            bytes.extend(block(5, &[0; 8], big_endian));
+            bytes.extend(enhanced_packet(1, 1_700_000_000_123_456_789, b"second", big_endian));
+            bytes.extend(enhanced_packet(2, 3 * 1024 + 512, b"third", big_endian));
+
+            let packets = read_all(bytes).unwrap();
+            assert_eq!(packets.len(), 3);
+            assert_eq!(packets[0].timestamp_ns, 1_700_000_000_500_000_000);
+            assert_eq!(packets[0].interface.as_deref(), Some("eth0"));
+            assert_eq!(packets[0].link_type, 1);
+            assert_eq!(packets[0].data, b"first");
+            assert_eq!(packets[1].timestamp_ns, 1_700_000_000_123_456_789);
+            assert_eq!(packets[1].interface.as_deref(), Some("any"));
+            assert_eq!(packets[1].link_type, 113);
+            assert_eq!(packets[2].timestamp_ns, 3_500_000_000);
+            assert_eq!(packets[2].interface, None);
+        }
+    }
+
+    #[test]
+    fn pcapng_section_resets_byte_order_and_interfaces() {
+        let mut bytes = section_header(false);
+        bytes.extend(interface_description(1, Some("eth0"), None, false));
+        bytes.extend(enhanced_packet(0, 1_000_000, b"le", false));
+        bytes.extend(section_header(true));
+        bytes.extend(interface_description(1, Some("eth1"), Some(9), true));
+        bytes.extend(enhanced_packet(0, 2_000_000_000, b"be", true));
+
+        let packets = read_all(bytes).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!((packets[0].interface.as_deref(), packets[0].timestamp_ns), (Some("eth0"), 1_000_000_000));
+        assert_eq!((packets[1].interface.as_deref(), packets[1].timestamp_ns), (Some("eth1"), 2_000_000_000));
+
+        // 新 section 中接口列表重新开始，引用上一个 section 的接口无效
+        let mut bytes = section_header(false);
+        bytes.extend(interface_description(1, Some("eth0"), None, false));
+        bytes.extend(section_header(false));
+        bytes.extend(enhanced_packet(0, 1, b"x", false));
+        assert!(read_all(bytes).unwrap_err().to_string().contains("未定义的接口"));
+    }
+
+    #[test]
+    fn pcapng_invalid_blocks() {
+        let with_block = |block: &[u8]| {
+            let mut bytes = section_header(false);
+            bytes.extend(interface_description(1, None, None, false));
+            bytes.extend_from_slice(block);
+            read_all(bytes)
+        };
+
+        // 块长度超过 MAX_RECORD_LEN、不是 4 的倍数或小于最小长度
+        for total_len in [MAX_RECORD_LEN as u32 + 4, u32::MAX, 30, 8] {
+            let mut block = PCAPNG_ENHANCED_PACKET.to_le_bytes().to_vec();
+            block.extend_from_slice(&total_len.to_le_bytes());
+            block.extend_from_slice(&[0; 32]);
+            assert!(with_block(&block).unwrap_err().to_string().contains("块长度无效"), "{}", total_len);
+        }
+
+        // 数据包长度超出块范围
+        let mut packet = enhanced_packet(0, 1, b"abcd", false);
+        packet[20..24].copy_from_slice(&1000u32.to_le_bytes());
+        assert!(with_block(&packet).unwrap_err().to_string().contains("超出块范围"));
+
+        // 块内容被截断
+        let packet = enhanced_packet(0, 1, b"abcd", false);
+        assert!(with_block(&packet[..packet.len() - 3]).is_err());
+
+        // section header 长度和字节序标记
+        let mut bytes = section_header(false);
+        bytes[4..8].copy_from_slice(&(MAX_RECORD_LEN as u32 + 4).to_le_bytes());
+        assert!(PcapReader::from_reader(Cursor::new(bytes)).is_err());
+        let mut bytes = section_header(false);
+        bytes[8..12].copy_from_slice(&[1, 2, 3, 4]);
+        assert!(PcapReader::from_reader(Cursor::new(bytes)).is_err());
+    }
+}
@@ -0,0 +1,318 @@
+use crate::address_filter::Cidr;
+use crate::billing::{self, MAX_BILLING_DAY};
+use crate::monitor::{format_bytes, TrafficStats};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+
+// 控制台告警的使用比例（百分比）
+const WARN_LEVELS: [u8; 2] = [80, 100];
+
+/// 配额的统计范围
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuotaScope {
+    Global,
+    Ip(IpAddr),
+    Network(Cidr),
+    Process(String),
+}
+
+impl QuotaScope {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Global => "global",
+            Self::Ip(_) => "ip",
+            Self::Network(_) => "cidr",
+            Self::Process(_) => "process",
+        }
+    }
+
+    pub fn target(&self) -> String {
+        match self {
+            Self::Global => String::new(),
+            Self::Ip(ip) => ip.to_string(),
+            Self::Network(cidr) => cidr.to_string(),
+            Self::Process(name) => name.clone(),
+        }
+    }
+}
+
+/// 配额统计的流量方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaDirection {
+    Total,
+    Tx,
+    Rx,
+}
+
+impl QuotaDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Total => "total",
+            Self::Tx => "tx",
+            Self::Rx => "rx",
+        }
+    }
+
+    fn bytes(&self, traffic: &TrafficStats) -> u64 {
+        match self {
+            Self::Total => traffic.total(),
+            Self::Tx => traffic.tx_bytes,
+            Self::Rx => traffic.rx_bytes,
+        }
+    }
+}
+
+/// 一条配额定义
+#[derive(Debug, Clone)]
+pub struct Quota {
+    pub scope: QuotaScope,
+    pub direction: QuotaDirection,
+    pub limit_bytes: u64,
+}
+
+impl Quota {
+    /// 配额的唯一名称，例如 global、ip:1.2.3.4@tx（同时作为状态文件的键）
+    pub fn name(&self) -> String {
+        let mut name = self.scope.kind().to_string();
+        if self.scope != QuotaScope::Global {
+            name.push(':');
+            name.push_str(&self.scope.target());
+        }
+        if self.direction != QuotaDirection::Total {
+            name.push('@');
+            name.push_str(self.direction.as_str());
+        }
+        name
+    }
+}
+
+impl FromStr for Quota {
+    type Err = String;
+
+    /// 解析配额定义：<范围>[:<对象>]=<大小>[@tx|@rx]，例如 global=1T、ip:1.2.3.4=100G@tx、
+    /// cidr:10.0.0.0/8=500G、process:nginx=200G
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("无效的配额定义 \"{}\"（格式: global=1T、ip:1.2.3.4=100G@tx、cidr:10.0.0.0/8=500G、process:nginx=200G）", s);
+        let (scope_str, limit_str) = s.split_once('=').ok_or_else(invalid)?;
+        let (limit_str, direction) = match limit_str.split_once('@') {
+            Some((limit, "tx")) => (limit, QuotaDirection::Tx),
+            Some((limit, "rx")) => (limit, QuotaDirection::Rx),
+            Some(_) => return Err(invalid()),
+            None => (limit_str, QuotaDirection::Total),
+        };
+        let scope = match scope_str.trim().split_once(':') {
+            None if scope_str.trim() == "global" => QuotaScope::Global,
+            Some(("ip", ip)) => QuotaScope::Ip(ip.trim().parse().map_err(|_| format!("无效的 IP 地址: {}", ip))?),
+            Some(("cidr", cidr)) => QuotaScope::Network(cidr.parse()?),
+            Some(("process", name)) if !name.trim().is_empty() => QuotaScope::Process(name.trim().to_string()),
+            _ => return Err(invalid()),
+        };
+        Ok(Self {
+            scope,
+            direction,
+            limit_bytes: parse_size(limit_str)?,
+        })
+    }
+}
+
+/// 解析流量大小，支持 K/M/G/T 后缀（1024 进制），例如 500G、1.5T
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let upper = s.to_uppercase();
+    let number = upper.trim_end_matches('B').trim_end_matches('I');
+    let (number, unit) = match number.char_indices().last() {
+        Some((i, c @ ('K' | 'M' | 'G' | 'T' | 'P'))) => (&number[..i], c),
+        _ => (number, ' '),
+    };
+    let multiplier: u64 = match unit {
+        'K' => 1 << 10,
+        'M' => 1 << 20,
+        'G' => 1 << 30,
+        'T' => 1 << 40,
+        'P' => 1 << 50,
+        _ => 1,
+    };
+    let value: f64 = number.trim().parse().map_err(|_| format!("无效的流量大小: {}", s))?;
+    if !value.is_finite() || value <= 0.0 {
+        return Err(format!("流量大小必须大于 0: {}", s));
+    }
+    Ok((value * multiplier as f64) as u64)
+}
+
+/// 持久化文件格式：配额名称 -> 已用字节数
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct QuotaState {
+    period_start: i64,
+    used: BTreeMap<String, u64>,
+}
+
+/// 单个配额的当前状态
+#[derive(Debug, Clone)]
+pub struct QuotaStatus {
+    pub name: String,
+    pub quota: Quota,
+    pub used_bytes: u64,
+}
+
+/// 按月统计配额使用量
+///
+/// 每条配额独立累计计费周期（每月重置日 0 点开始）内的流量，使用比例首次超过 80% 和 100% 时在控制台告警。
+/// 使用量每个周期写入状态文件，重启后同一周期内的用量会被恢复。
+pub struct QuotaTracker {
+    reset_day: u32,
+    quotas: Vec<Quota>,
+    state_path: Option<String>,
+    state: QuotaState,
+    // 每条配额本周期已经告警过的最高比例
+    warned: BTreeMap<String, u8>,
+}
+
+impl QuotaTracker {
+    pub fn new(quotas: Vec<Quota>, reset_day: u32, state_path: Option<String>) -> Result<Self, Box<dyn Error>> {
+        if !(1..=MAX_BILLING_DAY).contains(&reset_day) {
+            return Err(format!("配额重置日必须在 1~{} 之间", MAX_BILLING_DAY).into());
+        }
+        let period_start = billing::period_start(Local::now(), reset_day).timestamp();
+        let mut state = QuotaState {
+            period_start,
+            ..Default::default()
+        };
+        if let Some(path) = state_path.as_deref().filter(|p| Path::new(p).exists()) {
+            let content = fs::read_to_string(path).map_err(|e| format!("无法读取配额状态文件 {}: {}", path, e))?;
+            let saved: QuotaState =
+                serde_json::from_str(&content).map_err(|e| format!("配额状态文件 {} 格式错误: {}", path, e))?;
+            if saved.period_start == period_start {
+                state = saved;
+            }
+        }
+
+        let mut tracker = Self {
+            reset_day,
+            quotas,
+            state_path,
+            state,
+            warned: BTreeMap::new(),
+        };
+        // 恢复的用量已经超过的比例不再重复告警
+        for status in tracker.statuses() {
+            let percent = status.used_bytes.saturating_mul(100) / status.quota.limit_bytes.max(1);
+            if let Some(level) = WARN_LEVELS.iter().rev().find(|&&l| percent >= l as u64) {
+                tracker.warned.insert(status.name, *level);
+            }
+        }
+        Ok(tracker)
+    }
+
+    /// 是否有按进程统计的配额（需要查询流量所属进程）
+    pub fn needs_process(&self) -> bool {
+        self.quotas.iter().any(|q| matches!(q.scope, QuotaScope::Process(_)))
+    }
+
+    /// 进入新的计费周期时清空用量
+    fn roll_period(&mut self) {
+        let period_start = billing::period_start(Local::now(), self.reset_day).timestamp();
+        if period_start != self.state.period_start {
+            self.state = QuotaState {
+                period_start,
+                ..Default::default()
+            };
+            self.warned.clear();
+        }
+    }
+
+    /// 累加一条流的流量
+    pub fn record(&mut self, remote_ip: &str, process: Option<&str>, traffic: &TrafficStats) {
+        self.roll_period();
+        let remote = remote_ip.parse::<IpAddr>().ok();
+        for quota in &self.quotas {
+            let matched = match &quota.scope {
+                QuotaScope::Global => true,
+                QuotaScope::Ip(ip) => remote == Some(*ip),
+                QuotaScope::Network(cidr) => remote.is_some_and(|r| cidr.contains(&r)),
+                QuotaScope::Process(name) => process == Some(name.as_str()),
+            };
+            if matched {
+                let used = self.state.used.entry(quota.name()).or_default();
+                *used = used.saturating_add(quota.direction.bytes(traffic));
+            }
+        }
+    }
+
+    /// 检查告警阈值并保存用量，返回本周期新触发的告警
+    pub fn check(&mut self) -> Result<Vec<String>, Box<dyn Error>> {
+        self.roll_period();
+        let mut warnings = Vec::new();
+        for status in self.statuses() {
+            let percent = status.used_bytes.saturating_mul(100) / status.quota.limit_bytes.max(1);
+            let Some(&level) = WARN_LEVELS.iter().rev().find(|&&l| percent >= l as u64) else {
+                continue;
+            };
+            if self.warned.get(&status.name).is_some_and(|&warned| warned >= level) {
+                continue;
+            }
+            self.warned.insert(status.name.clone(), level);
+            warnings.push(format!(
+                "配额 {} 已使用 {}%（{} / {}）",
+                status.name,
+                percent,
+                format_bytes(status.used_bytes),
+                format_bytes(status.quota.limit_bytes)
+            ));
+        }
+        self.save()?;
+        Ok(warnings)
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        let Some(path) = self.state_path.as_deref() else {
+            return Ok(());
+        };
+        let tmp_path = format!("{}.tmp", path);
+        fs::write(&tmp_path, serde_json::to_vec(&self.state)?)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// 全部配额的当前用量
+    pub fn statuses(&self) -> Vec<QuotaStatus> {
+        self.quotas
+            .iter()
+            .map(|quota| {
+                let name = quota.name();
+                QuotaStatus {
+                    used_bytes: self.state.used.get(&name).copied().unwrap_or(0),
+                    name,
+                    quota: quota.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_saturates() {
+        let quotas = vec!["global=1T".parse().unwrap(), "ip:1.2.3.4=1G@tx".parse().unwrap()];
+        let mut tracker = QuotaTracker::new(quotas, 1, None).unwrap();
+        let traffic = TrafficStats {
+            tx_bytes: u64::MAX,
+            rx_bytes: 1,
+            ..Default::default()
+        };
+        tracker.record("1.2.3.4", None, &traffic);
+        tracker.record("1.2.3.4", None, &traffic);
+        for status in tracker.statuses() {
+            assert_eq!(status.used_bytes, u64::MAX, "{}", status.name);
+        }
+        assert_eq!(tracker.check().unwrap().len(), 2);
+    }
+}
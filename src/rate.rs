@@ -0,0 +1,104 @@
+use crate::monitor::{FlowKey, TrafficStats};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// 速率统计窗口：(标签值, 时长)
+pub const RATE_WINDOWS: [(&str, Duration); 3] = [
+    ("1m", Duration::from_secs(60)),
+    ("5m", Duration::from_secs(5 * 60)),
+    ("15m", Duration::from_secs(15 * 60)),
+];
+
+// 最长窗口之外的样本会被丢弃
+const MAX_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// 一个窗口内的平均速率（每秒）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rate {
+    pub tx_bytes: f64,
+    pub rx_bytes: f64,
+    pub tx_packets: f64,
+    pub rx_packets: f64,
+}
+
+/// 按流维护滑动窗口吞吐量
+///
+/// 每个周期记录一次各流的增量，窗口速率为窗口内增量之和除以窗口时长；
+/// 启动不足一个窗口时除以实际运行时长，避免刚启动时速率偏低。
+/// 时间按数据源的周期时长推进：实时数据源使用墙上时间，pcap / 流量记录回放使用抓包或记录中的时间，
+/// 否则回放时一个周期覆盖的抓包时间与实际耗时不同，速率会偏差很大。
+pub struct RateTracker {
+    started: Instant,
+    last_record: Option<Instant>,
+    // 数据源时间：启动以来各周期时长之和
+    clock: Duration,
+    last_cycle: Duration,
+    samples: HashMap<FlowKey, VecDeque<(Duration, TrafficStats)>>,
+}
+
+impl RateTracker {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last_record: None,
+            clock: Duration::ZERO,
+            last_cycle: Duration::ZERO,
+            samples: HashMap::new(),
+        }
+    }
+
+    /// 记录一个周期的流量增量，并清理超出最长窗口的样本
+    ///
+    /// cycle_duration 为数据源给出的周期时长，为 None 时使用距上次记录的墙上时间。
+    pub fn record(&mut self, cycle: &HashMap<FlowKey, TrafficStats>, cycle_duration: Option<Duration>) {
+        let now = Instant::now();
+        self.last_cycle = cycle_duration.unwrap_or_else(|| now.duration_since(self.last_record.unwrap_or(self.started)));
+        self.last_record = Some(now);
+        self.clock += self.last_cycle;
+
+        let clock = self.clock;
+        for (key, traffic) in cycle {
+            self.samples.entry(key.clone()).or_default().push_back((clock, traffic.clone()));
+        }
+        self.samples.retain(|_, samples| {
+            while samples.front().is_some_and(|(time, _)| clock - *time > MAX_WINDOW) {
+                samples.pop_front();
+            }
+            !samples.is_empty()
+        });
+    }
+
+    /// 上一个周期的时长（用于计算当前速率）
+    pub fn last_cycle(&self) -> Duration {
+        self.last_cycle
+    }
+
+    /// 流在指定窗口内的平均速率
+    pub fn rate(&self, key: &FlowKey, window: Duration) -> Rate {
+        let Some(samples) = self.samples.get(key) else {
+            return Rate::default();
+        };
+        let mut total = TrafficStats::default();
+        for (_, traffic) in samples.iter().rev().take_while(|(time, _)| self.clock - *time < window) {
+            total.add(traffic);
+        }
+
+        let seconds = window.min(self.clock).as_secs_f64().max(1.0);
+        Rate {
+            tx_bytes: total.tx_bytes as f64 / seconds,
+            rx_bytes: total.rx_bytes as f64 / seconds,
+            tx_packets: total.tx_packets as f64 / seconds,
+            rx_packets: total.rx_packets as f64 / seconds,
+        }
+    }
+
+    /// 只保留 keep 返回 true 的流的样本（Top-K 模式下清理已淘汰的流）
+    pub fn retain(&mut self, keep: impl Fn(&FlowKey) -> bool) {
+        self.samples.retain(|key, _| keep(key));
+    }
+
+    /// 流在最长窗口内是否有流量
+    pub fn contains(&self, key: &FlowKey) -> bool {
+        self.samples.contains_key(key)
+    }
+}
@@ -0,0 +1,365 @@
+use crate::flow_collector::{DatagramDecoder, FlowRecord};
+use crate::packet_parser;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const SFLOW_VERSION: u32 = 5;
+
+// 采样类型（企业号 0）
+const FLOW_SAMPLE: u32 = 1;
+const EXPANDED_FLOW_SAMPLE: u32 = 3;
+
+// 流记录类型（企业号 0）
+const RAW_PACKET_HEADER: u32 = 1;
+const SAMPLED_IPV4: u32 = 3;
+const SAMPLED_IPV6: u32 = 4;
+
+// 原始数据包头部的协议
+const HEADER_PROTOCOL_ETHERNET: u32 = 1;
+const HEADER_PROTOCOL_IPV4: u32 = 11;
+const HEADER_PROTOCOL_IPV6: u32 = 12;
+
+/// sFlow v5 报文解码器
+///
+/// 只处理流采样（flow sample / expanded flow sample），计数器采样被忽略。
+/// 每个采样代表 sampling_rate 个数据包，字节数按采样帧长度乘以采样率估算。
+/// 报文或采样被截断时整个报文返回错误；无法解析的数据包头部只跳过该记录。
+#[derive(Default)]
+pub struct SflowDecoder;
+
+impl SflowDecoder {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// 解码一个流采样中的全部流记录，采样被截断时返回 None
+    fn decode_flow_sample(sample: &mut Cursor, expanded: bool, records: &mut Vec<FlowRecord>) -> Option<()> {
+        sample.skip(4)?; // sequence_number
+        sample.skip(if expanded { 8 } else { 4 })?; // source_id
+        let sampling_rate = (sample.u32()? as u64).max(1);
+        sample.skip(8)?; // sample_pool + drops
+        sample.skip(if expanded { 16 } else { 8 })?; // input + output
+        let record_count = sample.u32()?;
+
+        for _ in 0..record_count {
+            let format = sample.u32()?;
+            let mut record = Cursor::new(sample.bytes_padded()?);
+            let decoded = match format {
+                RAW_PACKET_HEADER => Self::decode_raw_header(&mut record),
+                SAMPLED_IPV4 => Self::decode_sampled_ip(&mut record, 4),
+                SAMPLED_IPV6 => Self::decode_sampled_ip(&mut record, 16),
+                _ => None,
+            };
+            if let Some((src, dst, frame_len)) = decoded {
+                records.push(FlowRecord {
+                    src,
+                    dst,
+                    bytes: frame_len.saturating_mul(sampling_rate),
+                    packets: sampling_rate,
+                });
+                // 同一个采样只统计一次（交换机可能同时附带原始头部和解析后的 IP 记录）
+                break;
+            }
+        }
+        Some(())
+    }
+
+    /// 原始数据包头部记录，返回 (源地址, 目的地址, 帧长度)
+    fn decode_raw_header(record: &mut Cursor) -> Option<(IpAddr, IpAddr, u64)> {
+        let protocol = record.u32()?;
+        let frame_len = record.u32()? as u64;
+        record.skip(4)?; // stripped
+        let header = record.bytes_padded()?;
+        let info = match protocol {
+            HEADER_PROTOCOL_ETHERNET => packet_parser::parse_ethernet(header),
+            HEADER_PROTOCOL_IPV4 | HEADER_PROTOCOL_IPV6 => packet_parser::parse_ip(header),
+            _ => None,
+        }?;
+        Some((info.src, info.dst, frame_len))
+    }
+
+    /// 已解析的 IPv4 / IPv6 记录，返回 (源地址, 目的地址, 包长度)
+    fn decode_sampled_ip(record: &mut Cursor, addr_len: usize) -> Option<(IpAddr, IpAddr, u64)> {
+        let length = record.u32()? as u64;
+        record.skip(4)?; // protocol
+        let src = read_addr(record.take(addr_len)?)?;
+        let dst = read_addr(record.take(addr_len)?)?;
+        Some((src, dst, length))
+    }
+}
+
+impl DatagramDecoder for SflowDecoder {
+    fn decode(&mut self, _exporter: IpAddr, data: &[u8]) -> Result<Vec<FlowRecord>, String> {
+        let mut cursor = Cursor::new(data);
+        let version = cursor.u32().ok_or("报文过短")?;
+        if version != SFLOW_VERSION {
+            return Err(format!("不支持的 sFlow 版本: {}", version));
+        }
+        let agent_len = match cursor.u32().ok_or("报文过短")? {
+            1 => 4,
+            2 => 16,
+            t => return Err(format!("未知的 agent 地址类型: {}", t)),
+        };
+        cursor.skip(agent_len + 12).ok_or("报文过短")?; // agent 地址 + sub_agent_id + sequence + uptime
+        let sample_count = cursor.u32().ok_or("报文过短")?;
+
+        let mut records = Vec::new();
+        for _ in 0..sample_count {
+            let format = cursor.u32().ok_or("采样被截断")?;
+            let body = cursor.bytes_padded().ok_or("采样被截断")?;
+            // 高 20 位为企业号，只处理标准格式
+            let (enterprise, format) = (format >> 12, format & 0xfff);
+            if enterprise != 0 {
+                continue;
+            }
+            match format {
+                FLOW_SAMPLE | EXPANDED_FLOW_SAMPLE => {
+                    Self::decode_flow_sample(&mut Cursor::new(body), format == EXPANDED_FLOW_SAMPLE, &mut records)
+                        .ok_or("流采样被截断")?;
+                }
+                _ => {}
+            }
+        }
+        Ok(records)
+    }
+
+    fn is_sampled(&self) -> bool {
+        true
+    }
+}
+
+/// XDR 格式（4 字节对齐、大端序）的读取游标
+struct Cursor<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Cursor<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let value = self.data.get(..len)?;
+        self.data = &self.data[len..];
+        Some(value)
+    }
+
+    fn skip(&mut self, len: usize) -> Option<()> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let b = self.take(4)?;
+        Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// 读取带长度前缀的不透明数据，并跳过 4 字节对齐的填充
+    fn bytes_padded(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        let value = self.take(len)?;
+        let padding = len.next_multiple_of(4) - len;
+        self.take(padding.min(self.data.len()))?;
+        Some(value)
+    }
+}
+
+fn read_addr(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => Some(IpAddr::V4(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]))),
+        16 => Some(IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::TrafficStats;
+
+    const EXPORTER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    const COUNTER_SAMPLE: u32 = 2;
+
+    fn put_u32(data: &mut Vec<u8>, value: u32) {
+        data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn put_opaque(data: &mut Vec<u8>, value: &[u8]) {
+        put_u32(data, value.len() as u32);
+        data.extend_from_slice(value);
+        data.resize(data.len().next_multiple_of(4), 0);
+    }
+
+    fn datagram(samples: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        put_u32(&mut data, SFLOW_VERSION);
+        put_u32(&mut data, 1); // agent 地址类型 IPv4
+        data.extend_from_slice(&[192, 0, 2, 1]);
+        data.extend_from_slice(&[0; 12]); // sub_agent_id + sequence + uptime
+        put_u32(&mut data, samples.len() as u32);
+        for (format, body) in samples {
+            put_u32(&mut data, *format);
+            put_opaque(&mut data, body);
+        }
+        data
+    }
+
+    fn flow_sample(expanded: bool, sampling_rate: u32, records: &[(u32, Vec<u8>)]) -> (u32, Vec<u8>) {
+        let mut body = Vec::new();
+        put_u32(&mut body, 1); // sequence_number
+        body.extend_from_slice(&vec![0; if expanded { 8 } else { 4 }]); // source_id
+        put_u32(&mut body, sampling_rate);
+        body.extend_from_slice(&[0; 8]); // sample_pool + drops
+        body.extend_from_slice(&vec![0; if expanded { 16 } else { 8 }]); // input + output
+        put_u32(&mut body, records.len() as u32);
+        for (format, record) in records {
+            put_u32(&mut body, *format);
+            put_opaque(&mut body, record);
+        }
+        (if expanded { EXPANDED_FLOW_SAMPLE } else { FLOW_SAMPLE }, body)
+    }
+
+    fn counter_sample() -> (u32, Vec<u8>) {
+        let mut body = Vec::new();
+        put_u32(&mut body, 1); // sequence_number
+        put_u32(&mut body, 3); // source_id
+        put_u32(&mut body, 1); // 一条计数器记录
+        put_u32(&mut body, 1); // generic interface counters
+        put_opaque(&mut body, &[0xaa; 88]);
+        (COUNTER_SAMPLE, body)
+    }
+
+    /// 以太网 + IPv4 头部 + 2 字节载荷（共 36 字节，不需要 XDR 填充）
+    fn ethernet_ipv4(src: [u8; 4], dst: [u8; 4]) -> Vec<u8> {
+        let mut frame = vec![0x02; 12];
+        frame.extend_from_slice(&0x0800u16.to_be_bytes());
+        frame.extend_from_slice(&[0x45, 0, 0, 22, 0, 0, 0, 0, 64, 17, 0, 0]);
+        frame.extend_from_slice(&src);
+        frame.extend_from_slice(&dst);
+        frame.extend_from_slice(&[0, 0]);
+        frame
+    }
+
+    fn raw_header(protocol: u32, frame_len: u32, header: &[u8]) -> (u32, Vec<u8>) {
+        let mut record = Vec::new();
+        put_u32(&mut record, protocol);
+        put_u32(&mut record, frame_len);
+        put_u32(&mut record, 4); // stripped
+        put_opaque(&mut record, header);
+        (RAW_PACKET_HEADER, record)
+    }
+
+    fn sampled_ipv6(length: u32, src: Ipv6Addr, dst: Ipv6Addr) -> (u32, Vec<u8>) {
+        let mut record = Vec::new();
+        put_u32(&mut record, length);
+        put_u32(&mut record, 6); // protocol
+        record.extend_from_slice(&src.octets());
+        record.extend_from_slice(&dst.octets());
+        record.extend_from_slice(&[0; 16]); // 端口、TCP 标志、优先级
+        (SAMPLED_IPV6, record)
+    }
+
+    fn v4(a: u8, b: u8, c: u8, d: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(a, b, c, d))
+    }
+
+    #[test]
+    fn raw_packet_header() {
+        let sample = flow_sample(false, 100, &[raw_header(HEADER_PROTOCOL_ETHERNET, 1500, &ethernet_ipv4([10, 0, 0, 1], [8, 8, 8, 8]))]);
+        let records = SflowDecoder::new().decode(EXPORTER, &datagram(&[sample])).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].src, records[0].dst), (v4(10, 0, 0, 1), v4(8, 8, 8, 8)));
+        assert_eq!((records[0].bytes, records[0].packets), (150_000, 100));
+
+        // 不带以太网头部的 IP 数据包
+        let ip_header = &ethernet_ipv4([8, 8, 8, 8], [10, 0, 0, 1])[14..];
+        let sample = flow_sample(false, 10, &[raw_header(HEADER_PROTOCOL_IPV4, 64, ip_header)]);
+        let records = SflowDecoder::new().decode(EXPORTER, &datagram(&[sample])).unwrap();
+        assert_eq!((records[0].src, records[0].bytes), (v4(8, 8, 8, 8), 640));
+    }
+
+    #[test]
+    fn expanded_sample_with_sampled_ipv6_record() {
+        let src: Ipv6Addr = "fd00::1".parse().unwrap();
+        let dst: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        // 无法解析的原始头部被跳过，使用后面的 IPv6 记录；同一采样只统计一次
+        let sample = flow_sample(
+            true,
+            4,
+            &[raw_header(HEADER_PROTOCOL_ETHERNET, 100, &[0xff; 8]), sampled_ipv6(1000, src, dst), sampled_ipv6(1, dst, src)],
+        );
+        let records = SflowDecoder::new().decode(EXPORTER, &datagram(&[sample])).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].src, records[0].dst), (IpAddr::V6(src), IpAddr::V6(dst)));
+        assert_eq!((records[0].bytes, records[0].packets), (4000, 4));
+    }
+
+    #[test]
+    fn counter_and_enterprise_samples_are_ignored() {
+        let flow = flow_sample(false, 1, &[raw_header(HEADER_PROTOCOL_ETHERNET, 60, &ethernet_ipv4([10, 0, 0, 1], [1, 1, 1, 1]))]);
+        let enterprise = ((9 << 12) | FLOW_SAMPLE, flow.1.clone());
+        let records = SflowDecoder::new()
+            .decode(EXPORTER, &datagram(&[counter_sample(), enterprise, flow]))
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].dst, v4(1, 1, 1, 1));
+        assert!(SflowDecoder::new().decode(EXPORTER, &datagram(&[counter_sample()])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn sampling_rate_scaling_saturates() {
+        let frame = ethernet_ipv4([10, 0, 0, 1], [8, 8, 8, 8]);
+        let sample = flow_sample(false, u32::MAX, &[raw_header(HEADER_PROTOCOL_ETHERNET, u32::MAX, &frame)]);
+        let records = SflowDecoder::new().decode(EXPORTER, &datagram(&[sample.clone(), sample])).unwrap();
+        assert_eq!(records[0].bytes, u32::MAX as u64 * u32::MAX as u64);
+        assert_eq!(records[0].packets, u32::MAX as u64);
+
+        // 累加两个最大的采样时饱和
+        let mut total = TrafficStats::default();
+        for record in &records {
+            total.add(&TrafficStats {
+                tx_bytes: record.bytes,
+                tx_packets: record.packets,
+                ..Default::default()
+            });
+        }
+        assert_eq!(total.tx_bytes, u64::MAX);
+        assert_eq!(total.tx_packets, 2 * u32::MAX as u64);
+    }
+
+    #[test]
+    fn truncated_and_garbage_datagrams() {
+        let sample = flow_sample(false, 100, &[raw_header(HEADER_PROTOCOL_ETHERNET, 1500, &ethernet_ipv4([10, 0, 0, 1], [8, 8, 8, 8]))]);
+        let data = datagram(&[sample]);
+        for len in 0..data.len() {
+            assert!(SflowDecoder::new().decode(EXPORTER, &data[..len]).is_err(), "截断长度 {}", len);
+        }
+
+        let mut decoder = SflowDecoder::new();
+        let mut wrong_version = data.clone();
+        wrong_version[3] = 4;
+        assert!(decoder.decode(EXPORTER, &wrong_version).is_err());
+        let mut wrong_agent = data.clone();
+        wrong_agent[7] = 3;
+        assert!(decoder.decode(EXPORTER, &wrong_agent).is_err());
+        // 采样数大于实际数量
+        let mut extra_samples = data.clone();
+        extra_samples[27] = 2;
+        assert!(decoder.decode(EXPORTER, &extra_samples).is_err());
+
+        let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+        for _ in 0..1000 {
+            let len = (seed % 256) as usize;
+            let mut data: Vec<u8> = (0..len)
+                .map(|_| {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 7;
+                    seed ^= seed << 17;
+                    seed as u8
+                })
+                .collect();
+            if data.len() >= 8 {
+                data[..8].copy_from_slice(&[0, 0, 0, 5, 0, 0, 0, 1]);
+            }
+            let _ = decoder.decode(EXPORTER, &data);
+        }
+    }
+}
@@ -0,0 +1,402 @@
+use crate::address_filter::Cidr;
+use crate::monitor::{format_bytes, ANY_INTERFACE};
+use crate::quota::parse_size;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
+use std::process::Command;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+// HTB 根队列的句柄和限速类的起始编号（1:10 之前留给手工配置）
+const ROOT_HANDLE: &str = "1:";
+const FIRST_CLASS: u32 = 0x10;
+const MAX_CLASS: u32 = 0xffff;
+
+/// 限速策略的统计对象
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ShapeScope {
+    Ip,
+    Process,
+}
+
+impl ShapeScope {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Ip => "IP",
+            Self::Process => "进程",
+        }
+    }
+
+    // 每个过滤器独占一个优先级（可以按优先级单独删除）；按 IP 的过滤器优先于按进程的过滤器
+    fn filter_prios(&self) -> RangeInclusive<u32> {
+        match self {
+            Self::Ip => 1..=0x7fff,
+            Self::Process => 0x8000..=0xffff,
+        }
+    }
+}
+
+/// 一条限速策略：TX 速率超过 threshold（字节/秒）的对象限速到 rate（字节/秒）
+#[derive(Debug, Clone)]
+pub struct ShapePolicy {
+    pub scope: ShapeScope,
+    pub threshold: u64,
+    pub rate: u64,
+}
+
+impl FromStr for ShapePolicy {
+    type Err = String;
+
+    /// 解析限速策略：<ip|process>:<阈值>=<限速>，例如 ip:10M=2M
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("无效的限速策略 \"{}\"（格式: ip:10M=2M、process:50M=10M）", s);
+        let (scope, rest) = s.trim().split_once(':').ok_or_else(invalid)?;
+        let (threshold, rate) = rest.split_once('=').ok_or_else(invalid)?;
+        let scope = match scope {
+            "ip" => ShapeScope::Ip,
+            "process" => ShapeScope::Process,
+            _ => return Err(invalid()),
+        };
+        Ok(Self {
+            scope,
+            threshold: parse_size(threshold)?,
+            rate: parse_size(rate)?,
+        })
+    }
+}
+
+// 网卡 -> 远程地址
+type Peers = BTreeMap<String, BTreeSet<String>>;
+
+// 一个网卡上期望的 (类编号 -> 速率, 远程网段 -> (策略类型, 类编号))
+type DesiredRules = (BTreeMap<u32, u64>, BTreeMap<Cidr, (ShapeScope, u32)>);
+
+/// 一个被限速的对象
+struct Shaped {
+    rate: u64,
+    class_id: u32,
+    // 网卡 -> 需要限速的远程地址（按进程限速时为该进程的全部对端）
+    peers: Peers,
+    last_over: Instant,
+}
+
+/// 某个网卡上已安装的类和过滤器
+#[derive(Default)]
+struct InstalledInterface {
+    // 类编号 -> 速率
+    classes: BTreeMap<u32, u64>,
+    // 远程网段 -> (过滤器优先级, 策略类型, 类编号)
+    filters: BTreeMap<Cidr, (u32, ShapeScope, u32)>,
+}
+
+/// 基于 tc HTB 的限速
+///
+/// 每个周期按策略检查远程 IP 和进程的 TX 速率，超过阈值的对象在其流量所在网卡的出方向获得一个 HTB 类，
+/// 并用 u32 过滤器按目的地址把流量归入该类；速率连续 hold 时间低于阈值后解除。
+/// 已安装的类和过滤器每个周期与期望状态对比，只增删有变化的部分；命令失败或根队列被外部修改的网卡整体重建。
+/// 未匹配过滤器的流量不经过 HTB 类，不受影响。只在根队列为内核默认队列（句柄 0:）的网卡上安装，
+/// 已有自定义根队列的网卡跳过，不会覆盖；shutdown() 时删除安装过的根队列，恢复默认队列。
+pub struct Shaper {
+    policies: Vec<ShapePolicy>,
+    hold: Duration,
+    shaped: BTreeMap<(ShapeScope, String), Shaped>,
+    installed: BTreeMap<String, InstalledInterface>,
+    // 因已有自定义根队列而跳过的网卡（只提示一次）
+    skipped: BTreeSet<String>,
+}
+
+impl Shaper {
+    pub fn new(policies: Vec<ShapePolicy>, hold: Duration) -> Result<Self, Box<dyn Error>> {
+        Command::new("tc")
+            .arg("-V")
+            .output()
+            .map_err(|e| format!("tc 不可用: {}. 请确保已安装 iproute2", e))?;
+        Ok(Self {
+            policies,
+            hold,
+            shaped: BTreeMap::new(),
+            installed: BTreeMap::new(),
+            skipped: BTreeSet::new(),
+        })
+    }
+
+    /// 用一个周期的流量更新限速对象并同步 tc 规则，返回本周期的变化（用于打印）
+    ///
+    /// flows 为 (网卡, 远程 IP, 进程名, TX 字节数)；网卡未知（any）的流无法安装规则，不参与限速。
+    pub fn reconcile<'a>(
+        &mut self,
+        flows: impl IntoIterator<Item = (&'a str, &'a str, Option<&'a str>, u64)>,
+        cycle_seconds: f64,
+    ) -> Vec<String> {
+        let mut events = Vec::new();
+
+        // 本周期每个对象的 TX 字节数和对端
+        let mut cycle: HashMap<(ShapeScope, String), (u64, Peers)> = HashMap::new();
+        for (interface, remote_ip, process, tx_bytes) in flows.into_iter().filter(|f| f.0 != ANY_INTERFACE) {
+            let mut targets = vec![(ShapeScope::Ip, remote_ip)];
+            if let Some(process) = process {
+                targets.push((ShapeScope::Process, process));
+            }
+            for (scope, target) in targets {
+                let entry = cycle.entry((scope, target.to_string())).or_default();
+                entry.0 = entry.0.saturating_add(tx_bytes);
+                entry.1.entry(interface.to_string()).or_default().insert(remote_ip.to_string());
+            }
+        }
+
+        let now = Instant::now();
+        for ((scope, target), (tx_bytes, peers)) in cycle {
+            let rate = tx_bytes as f64 / cycle_seconds.max(1.0);
+            let policy = self
+                .policies
+                .iter()
+                .filter(|p| p.scope == scope && rate > p.threshold as f64)
+                .min_by_key(|p| p.rate);
+            let key = (scope, target);
+            match (self.shaped.get_mut(&key), policy) {
+                (Some(shaped), policy) => {
+                    if let Some(policy) = policy {
+                        shaped.last_over = now;
+                        shaped.rate = shaped.rate.min(policy.rate);
+                    }
+                    for (interface, remotes) in peers {
+                        shaped.peers.entry(interface).or_default().extend(remotes);
+                    }
+                }
+                (None, Some(policy)) => {
+                    let Some(class_id) = self.allocate_class() else {
+                        events.push(format!("限速类编号已用尽，无法限速{} {}", scope.label(), key.1));
+                        continue;
+                    };
+                    events.push(format!(
+                        "{} {} 的 TX 速率 {}/s 超过 {}/s，限速到 {}/s",
+                        scope.label(),
+                        key.1,
+                        format_bytes(rate as u64),
+                        format_bytes(policy.threshold),
+                        format_bytes(policy.rate)
+                    ));
+                    self.shaped.insert(
+                        key,
+                        Shaped {
+                            rate: policy.rate,
+                            class_id,
+                            peers,
+                            last_over: now,
+                        },
+                    );
+                }
+                (None, None) => {}
+            }
+        }
+
+        let hold = self.hold;
+        self.shaped.retain(|(scope, target), shaped| {
+            let keep = shaped.last_over.elapsed() < hold;
+            if !keep {
+                events.push(format!("{} {} 的速率已持续低于阈值，解除限速", scope.label(), target));
+            }
+            keep
+        });
+
+        self.sync(&mut events);
+        events
+    }
+
+    /// 分配一个未使用的类编号
+    fn allocate_class(&self) -> Option<u32> {
+        let used: BTreeSet<u32> = self.shaped.values().map(|s| s.class_id).collect();
+        (FIRST_CLASS..=MAX_CLASS).find(|id| !used.contains(id))
+    }
+
+    /// 把已安装的规则同步到期望状态
+    fn sync(&mut self, events: &mut Vec<String>) {
+        let mut desired: BTreeMap<String, DesiredRules> = BTreeMap::new();
+        for ((scope, _), shaped) in &self.shaped {
+            for (interface, remotes) in &shaped.peers {
+                let (classes, filters) = desired.entry(interface.clone()).or_default();
+                classes.insert(shaped.class_id, shaped.rate);
+                for remote in remotes {
+                    let Ok(cidr) = remote.parse::<Cidr>() else {
+                        continue;
+                    };
+                    // 同一地址只归入一个类（按 IP 的策略优先）
+                    filters.entry(cidr).or_insert((*scope, shaped.class_id));
+                }
+            }
+        }
+
+        // 根队列被外部修改（例如手工执行 tc qdisc del）时按未安装处理，重新安装全部规则
+        self.installed.retain(|interface, _| {
+            let intact = root_qdisc_installed(interface);
+            if !intact {
+                events.push(format!("网卡 {} 上的限速队列已被外部修改，重新安装", interface));
+            }
+            intact
+        });
+
+        let interfaces: BTreeSet<String> = desired.keys().chain(self.installed.keys()).cloned().collect();
+        for interface in interfaces {
+            let (classes, filters) = desired.remove(&interface).unwrap_or_default();
+            if !classes.is_empty() && !self.installed.contains_key(&interface) && !self.root_qdisc_available(&interface, events) {
+                continue;
+            }
+            if let Err(e) = self.sync_interface(&interface, &classes, &filters) {
+                events.push(format!("网卡 {} 的 tc 规则同步失败（下个周期重建）: {}", interface, e));
+                // 只删除本程序安装的根队列
+                if self.installed.remove(&interface).is_some() {
+                    let _ = tc(&["qdisc", "del", "dev", &interface, "root"]);
+                }
+            }
+        }
+    }
+
+    /// 网卡的根队列是否为内核默认队列（可以安装 HTB 根队列）；已有自定义根队列时跳过该网卡
+    fn root_qdisc_available(&mut self, interface: &str, events: &mut Vec<String>) -> bool {
+        match custom_root_qdisc(interface) {
+            Ok(None) => {
+                self.skipped.remove(interface);
+                true
+            }
+            Ok(Some(qdisc)) => {
+                if self.skipped.insert(interface.to_string()) {
+                    events.push(format!("网卡 {} 已有自定义根队列（{}），跳过该网卡的限速", interface, qdisc));
+                }
+                false
+            }
+            Err(e) => {
+                events.push(format!("无法读取网卡 {} 的根队列，跳过限速: {}", interface, e));
+                false
+            }
+        }
+    }
+
+    fn sync_interface(
+        &mut self,
+        interface: &str,
+        classes: &BTreeMap<u32, u64>,
+        filters: &BTreeMap<Cidr, (ShapeScope, u32)>,
+    ) -> Result<(), Box<dyn Error>> {
+        if classes.is_empty() {
+            if self.installed.remove(interface).is_some() {
+                tc(&["qdisc", "del", "dev", interface, "root"])?;
+            }
+            return Ok(());
+        }
+        if !self.installed.contains_key(interface) {
+            // 使用 add 而不是 replace：根队列在检查之后被他人修改时失败，而不是覆盖
+            tc(&["qdisc", "add", "dev", interface, "root", "handle", ROOT_HANDLE, "htb"])?;
+            self.installed.insert(interface.to_string(), InstalledInterface::default());
+        }
+        let installed = self.installed.get_mut(interface).expect("刚刚插入");
+
+        // 先删除多余或指向其他类的过滤器，再删除不再需要的类
+        let stale: Vec<Cidr> = installed
+            .filters
+            .iter()
+            .filter(|(remote, (_, scope, class_id))| filters.get(*remote) != Some(&(*scope, *class_id)))
+            .map(|(remote, _)| *remote)
+            .collect();
+        for remote in stale {
+            let (prio, _, _) = installed.filters[&remote];
+            tc(&[
+                "filter", "del", "dev", interface, "parent", ROOT_HANDLE, "protocol", filter_protocol(&remote),
+                "prio", &prio.to_string(),
+            ])?;
+            installed.filters.remove(&remote);
+        }
+        let stale: Vec<u32> = installed.classes.keys().filter(|id| !classes.contains_key(id)).copied().collect();
+        for class_id in stale {
+            tc(&["class", "del", "dev", interface, "classid", &class_name(class_id)])?;
+            installed.classes.remove(&class_id);
+        }
+
+        for (&class_id, &rate) in classes {
+            if installed.classes.get(&class_id) == Some(&rate) {
+                continue;
+            }
+            let rate_bits = format!("{}bit", rate.saturating_mul(8));
+            tc(&[
+                "class", "replace", "dev", interface, "parent", ROOT_HANDLE, "classid", &class_name(class_id),
+                "htb", "rate", &rate_bits, "ceil", &rate_bits,
+            ])?;
+            installed.classes.insert(class_id, rate);
+        }
+
+        for (remote, &(scope, class_id)) in filters {
+            if installed.filters.contains_key(remote) {
+                continue;
+            }
+            let used: BTreeSet<u32> = installed.filters.values().map(|f| f.0).collect();
+            let prio = scope
+                .filter_prios()
+                .find(|p| !used.contains(p))
+                .ok_or("过滤器优先级已用尽")?;
+            let field = match remote.addr() {
+                IpAddr::V4(_) => "ip",
+                IpAddr::V6(_) => "ip6",
+            };
+            tc(&[
+                "filter", "add", "dev", interface, "parent", ROOT_HANDLE, "protocol", filter_protocol(remote),
+                "prio", &prio.to_string(), "u32", "match", field, "dst", &remote.to_string(),
+                "flowid", &class_name(class_id),
+            ])?;
+            installed.filters.insert(*remote, (prio, scope, class_id));
+        }
+        Ok(())
+    }
+
+    /// 删除安装过的根队列（恢复网卡默认队列）
+    pub fn shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        for interface in std::mem::take(&mut self.installed).into_keys() {
+            tc(&["qdisc", "del", "dev", &interface, "root"])?;
+            println!("已删除网卡 {} 上的限速队列", interface);
+        }
+        Ok(())
+    }
+}
+
+fn filter_protocol(remote: &Cidr) -> &'static str {
+    match remote.addr() {
+        IpAddr::V4(_) => "ip",
+        IpAddr::V6(_) => "ipv6",
+    }
+}
+
+/// 网卡的根队列是否仍为本程序安装的 HTB 队列
+fn root_qdisc_installed(interface: &str) -> bool {
+    Command::new("tc")
+        .args(["qdisc", "show", "dev", interface, "root"])
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains(&format!("htb {} ", ROOT_HANDLE)))
+}
+
+/// 读取网卡的根队列，返回非默认的根队列（类型和句柄）；句柄为 0: 的是内核自动创建的默认队列
+fn custom_root_qdisc(interface: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let output = Command::new("tc").args(["qdisc", "show", "dev", interface, "root"]).output()?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string().into());
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // 格式: qdisc <类型> <句柄> root ...
+    let fields: Vec<&str> = stdout.split_whitespace().take(3).collect();
+    match fields.as_slice() {
+        ["qdisc", kind, handle] if *handle != "0:" => Ok(Some(format!("{} {}", kind, handle))),
+        _ => Ok(None),
+    }
+}
+
+fn class_name(class_id: u32) -> String {
+    format!("{}{:x}", ROOT_HANDLE, class_id)
+}
+
+/// 执行一条 tc 命令
+fn tc(args: &[&str]) -> Result<(), Box<dyn Error>> {
+    let output = Command::new("tc").args(args).output()?;
+    if !output.status.success() {
+        return Err(format!("tc {} 执行失败: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,359 @@
+use crate::monitor::{FlowKey, TrafficStats};
+use std::collections::{BTreeMap, HashMap};
+
+/// Space-Saving 中的一个计数器
+struct Counter {
+    // 本次进入 Top-K 以来的真实流量
+    traffic: TrafficStats,
+    // 估计的总字节数（TX + RX，含继承的误差）
+    count: u64,
+    // 进入 Top-K 时继承的被淘汰计数器的字节数，即估计值的误差上界
+    error: u64,
+    seq: u64,
+}
+
+/// 按字节数（TX + RX）跟踪流量最大的 K 个流的 Space-Saving 结构
+///
+/// 内存固定为 K 个计数器：新的流在已满时替换估计字节数最小的计数器，并继承其计数作为误差上界，
+/// 被淘汰流的流量计入 remainder。因此每个流的真实累计字节数位于 [traffic, traffic + error] 之间，
+/// 任何真实字节数超过总量 / K 的流一定在 Top-K 中，且 Top-K 流量与 remainder 之和等于全部流量。
+pub struct SpaceSaving {
+    capacity: usize,
+    counters: HashMap<FlowKey, Counter>,
+    // (估计字节数, 序号) -> 流，用于找到最小的计数器
+    order: BTreeMap<(u64, u64), FlowKey>,
+    next_seq: u64,
+    remainder: TrafficStats,
+    evictions: u64,
+}
+
+impl SpaceSaving {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            counters: HashMap::with_capacity(capacity),
+            order: BTreeMap::new(),
+            next_seq: 0,
+            remainder: TrafficStats::default(),
+            evictions: 0,
+        }
+    }
+
+    /// 累加一个流的流量，返回该流的累计流量和被淘汰的流
+    pub fn add(&mut self, key: &FlowKey, traffic: &TrafficStats) -> (TrafficStats, Option<FlowKey>) {
+        let bytes = traffic.total();
+        let mut evicted = None;
+
+        if !self.counters.contains_key(key) {
+            let mut error = 0;
+            if self.counters.len() >= self.capacity {
+                let (_, min_key) = self.order.pop_first().expect("计数器已满时 order 不为空");
+                let min = self.counters.remove(&min_key).expect("order 与 counters 一致");
+                self.remainder.add(&min.traffic);
+                self.evictions += 1;
+                error = min.count;
+                evicted = Some(min_key);
+            }
+            self.next_seq += 1;
+            self.counters.insert(
+                key.clone(),
+                Counter {
+                    traffic: TrafficStats::default(),
+                    count: error,
+                    error,
+                    seq: self.next_seq,
+                },
+            );
+            self.order.insert((error, self.next_seq), key.clone());
+        }
+
+        let counter = self.counters.get_mut(key).expect("刚刚插入");
+        self.order.remove(&(counter.count, counter.seq));
+        counter.count = counter.count.saturating_add(bytes);
+        counter.traffic.add(traffic);
+        self.order.insert((counter.count, counter.seq), key.clone());
+        (counter.traffic.clone(), evicted)
+    }
+
+    /// 删除一个流（例如空闲过期），其流量计入 remainder，保持 Top-K 流量与 remainder 之和等于全部流量
+    pub fn remove(&mut self, key: &FlowKey) -> Option<TrafficStats> {
+        let counter = self.counters.remove(key)?;
+        self.order.remove(&(counter.count, counter.seq));
+        self.remainder.add(&counter.traffic);
+        Some(counter.traffic)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+/// 按流累计的流量存储：精确模式保存全部流，Top-K 模式只保存字节数最大的 K 个流
+pub enum FlowStore {
+    Exact(HashMap<FlowKey, TrafficStats>),
+    TopK(SpaceSaving),
+}
+
+impl Default for FlowStore {
+    fn default() -> Self {
+        Self::Exact(HashMap::new())
+    }
+}
+
+impl FlowStore {
+    /// 累加一个流的流量，返回该流的累计流量和被淘汰的流（仅 Top-K 模式）
+    pub fn add(&mut self, key: &FlowKey, traffic: &TrafficStats) -> (TrafficStats, Option<FlowKey>) {
+        match self {
+            Self::Exact(map) => {
+                let entry = map.entry(key.clone()).or_default();
+                entry.add(traffic);
+                (entry.clone(), None)
+            }
+            Self::TopK(top_k) => top_k.add(key, traffic),
+        }
+    }
+
+    /// 删除一个流，返回其累计流量
+    pub fn remove(&mut self, key: &FlowKey) -> Option<TrafficStats> {
+        match self {
+            Self::Exact(map) => map.remove(key),
+            Self::TopK(top_k) => top_k.remove(key),
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&FlowKey, &TrafficStats)> + '_> {
+        match self {
+            Self::Exact(map) => Box::new(map.iter()),
+            Self::TopK(top_k) => Box::new(top_k.counters.iter().map(|(key, counter)| (key, &counter.traffic))),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Exact(map) => map.len(),
+            Self::TopK(top_k) => top_k.counters.len(),
+        }
+    }
+
+    /// 流累计字节数的误差上界（精确模式为 0）
+    pub fn error(&self, key: &FlowKey) -> u64 {
+        match self {
+            Self::Exact(_) => 0,
+            Self::TopK(top_k) => top_k.counters.get(key).map_or(0, |c| c.error),
+        }
+    }
+
+    /// Top-K 之外（已被淘汰）的流量汇总和淘汰次数；精确模式为 None
+    pub fn remainder(&self) -> Option<(&TrafficStats, u64)> {
+        match self {
+            Self::Exact(_) => None,
+            Self::TopK(top_k) => Some((&top_k.remainder, top_k.evictions)),
+        }
+    }
+
+    /// Top-K 模式的容量
+    pub fn capacity(&self) -> Option<usize> {
+        match self {
+            Self::Exact(_) => None,
+            Self::TopK(top_k) => Some(top_k.capacity()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(remote_ip: &str) -> FlowKey {
+        FlowKey::new("eth0", "10.0.0.1", remote_ip)
+    }
+
+    fn traffic(tx_bytes: u64, rx_bytes: u64) -> TrafficStats {
+        TrafficStats {
+            tx_bytes,
+            rx_bytes,
+            ..Default::default()
+        }
+    }
+
+    fn fields(stats: &TrafficStats) -> (u64, u64, u64, u64) {
+        (stats.tx_bytes, stats.rx_bytes, stats.tx_packets, stats.rx_packets)
+    }
+
+    /// 检查计数器的内部一致性：估计值 = 真实流量 + 误差，order 与 counters 一一对应
+    fn assert_consistent(top_k: &SpaceSaving) {
+        assert!(top_k.counters.len() <= top_k.capacity);
+        assert_eq!(top_k.order.len(), top_k.counters.len());
+        for (key, counter) in &top_k.counters {
+            assert_eq!(counter.count, counter.traffic.total() + counter.error);
+            assert_eq!(top_k.order.get(&(counter.count, counter.seq)), Some(key));
+        }
+    }
+
+    #[test]
+    fn evicts_minimum_counter() {
+        let mut top_k = SpaceSaving::new(2);
+        assert_eq!(top_k.add(&key("1.1.1.1"), &traffic(60, 40)).1, None);
+        assert_eq!(top_k.add(&key("2.2.2.2"), &traffic(50, 0)).1, None);
+
+        // 已满时替换最小的计数器，新流继承其计数作为误差
+        let (stats, evicted) = top_k.add(&key("3.3.3.3"), &traffic(0, 10));
+        assert_eq!(evicted, Some(key("2.2.2.2")));
+        assert_eq!(fields(&stats), (0, 10, 0, 0));
+        assert_eq!(top_k.counters[&key("3.3.3.3")].error, 50);
+        assert_eq!(top_k.counters[&key("3.3.3.3")].count, 60);
+
+        // 继承误差后 3.3.3.3 的估计值（60）仍小于 1.1.1.1（100），因此再次被替换
+        let (_, evicted) = top_k.add(&key("4.4.4.4"), &traffic(1, 0));
+        assert_eq!(evicted, Some(key("3.3.3.3")));
+        assert_eq!(top_k.counters[&key("4.4.4.4")].error, 60);
+
+        // 估计值相同时先进入的计数器先被替换
+        let mut top_k = SpaceSaving::new(2);
+        top_k.add(&key("1.1.1.1"), &traffic(10, 0));
+        top_k.add(&key("2.2.2.2"), &traffic(10, 0));
+        assert_eq!(top_k.add(&key("3.3.3.3"), &traffic(1, 0)).1, Some(key("1.1.1.1")));
+        assert_consistent(&top_k);
+    }
+
+    #[test]
+    fn error_bounds_and_remainder() {
+        let mut top_k = SpaceSaving::new(4);
+        let mut exact: HashMap<FlowKey, u64> = HashMap::new();
+        let mut total = TrafficStats::default();
+
+        // 一个大流夹杂在 20 个小流中，字节数用线性同余序列生成
+        let mut seed: u64 = 12345;
+        for i in 0..500 {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let (remote, bytes) = if i % 3 == 0 {
+                ("9.9.9.9".to_string(), 1000 + seed % 500)
+            } else {
+                (format!("10.1.0.{}", seed % 20), seed % 400)
+            };
+            let stats = TrafficStats {
+                tx_bytes: bytes / 2,
+                rx_bytes: bytes - bytes / 2,
+                tx_packets: 1,
+                rx_packets: i % 2,
+            };
+            top_k.add(&key(&remote), &stats);
+            *exact.entry(key(&remote)).or_default() += bytes;
+            total.add(&stats);
+            assert_consistent(&top_k);
+        }
+
+        // 每个被跟踪的流：traffic <= 真实字节数 <= traffic + error
+        for (key, counter) in &top_k.counters {
+            let actual = exact[key];
+            assert!(counter.traffic.total() <= actual, "{:?}", key);
+            assert!(actual <= counter.traffic.total() + counter.error, "{:?}", key);
+        }
+        // 任何误差都不超过总量 / K
+        assert!(top_k.counters.values().all(|c| c.error <= total.total() / 4));
+        // 真实字节数超过总量 / K 的大流一定在 Top-K 中
+        assert!(exact[&key("9.9.9.9")] > total.total() / 4);
+        assert!(top_k.counters.contains_key(&key("9.9.9.9")));
+
+        // remainder = 全部流量 - Top-K 中的流量（各字段分别成立）
+        let mut tracked = TrafficStats::default();
+        for counter in top_k.counters.values() {
+            tracked.add(&counter.traffic);
+        }
+        let remainder = &top_k.remainder;
+        assert_eq!(
+            fields(remainder),
+            (
+                total.tx_bytes - tracked.tx_bytes,
+                total.rx_bytes - tracked.rx_bytes,
+                total.tx_packets - tracked.tx_packets,
+                total.rx_packets - tracked.rx_packets,
+            )
+        );
+        assert!(top_k.evictions > 0);
+    }
+
+    #[test]
+    fn single_counter_pruning() {
+        // 容量 0 按 1 处理
+        let mut store = FlowStore::TopK(SpaceSaving::new(0));
+        assert_eq!(store.capacity(), Some(1));
+
+        // 按流保存的附属状态（如最近活跃时间）按 Top-K 中的流裁剪
+        let mut last_seen: HashMap<FlowKey, u64> = HashMap::new();
+        let remotes = ["1.1.1.1", "2.2.2.2", "1.1.1.1", "3.3.3.3"];
+        let mut previous: Option<FlowKey> = None;
+        let mut previous_count = 0;
+        for (i, remote) in remotes.iter().enumerate() {
+            let (stats, evicted) = store.add(&key(remote), &traffic(100, 0));
+            assert_eq!(evicted, previous.take());
+            // 每次都替换唯一的计数器，真实流量只有本次，其余都是继承的误差
+            assert_eq!(stats.tx_bytes, 100);
+            assert_eq!(store.error(&key(remote)), previous_count);
+            previous_count += 100;
+
+            last_seen.insert(key(remote), i as u64);
+            let keys: Vec<&FlowKey> = store.iter().map(|(key, _)| key).collect();
+            last_seen.retain(|key, _| keys.contains(&key));
+            assert_eq!(store.len(), 1);
+            assert_eq!(last_seen.keys().collect::<Vec<_>>(), vec![&key(remote)]);
+            previous = Some(key(remote));
+        }
+
+        let (other, evictions) = store.remainder().unwrap();
+        assert_eq!(other.tx_bytes, 300);
+        assert_eq!(evictions, 3);
+
+        // 同一个流重复累加不会淘汰，也不会增加误差
+        let (stats, evicted) = store.add(&key("3.3.3.3"), &traffic(0, 5));
+        assert_eq!(evicted, None);
+        assert_eq!(fields(&stats), (100, 5, 0, 0));
+        assert_eq!(store.error(&key("3.3.3.3")), 300);
+    }
+
+    #[test]
+    fn removal() {
+        let mut store = FlowStore::TopK(SpaceSaving::new(2));
+        store.add(&key("1.1.1.1"), &traffic(100, 0));
+        store.add(&key("2.2.2.2"), &traffic(0, 50));
+        assert_eq!(store.remove(&key("1.1.1.1")).map(|t| t.tx_bytes), Some(100));
+        assert!(store.remove(&key("1.1.1.1")).is_none());
+        assert_eq!(store.len(), 1);
+
+        // 删除的流量计入 remainder，但不算作淘汰
+        let (other, evictions) = store.remainder().unwrap();
+        assert_eq!((other.tx_bytes, evictions), (100, 0));
+
+        // 空出的计数器直接给新流使用，不会淘汰其他流，也没有误差
+        let (_, evicted) = store.add(&key("3.3.3.3"), &traffic(1, 0));
+        assert_eq!(evicted, None);
+        assert_eq!(store.error(&key("3.3.3.3")), 0);
+        let FlowStore::TopK(top_k) = &store else { unreachable!() };
+        assert_consistent(top_k);
+
+        let mut store = FlowStore::default();
+        store.add(&key("1.1.1.1"), &traffic(100, 0));
+        assert_eq!(store.remove(&key("1.1.1.1")).map(|t| t.tx_bytes), Some(100));
+        assert_eq!(store.len(), 0);
+        assert!(store.remainder().is_none());
+    }
+
+    #[test]
+    fn saturates_maximum_counters() {
+        let mut store = FlowStore::TopK(SpaceSaving::new(1));
+        let max = traffic(u64::MAX, 1);
+        store.add(&key("1.1.1.1"), &max);
+        let (total, evicted) = store.add(&key("1.1.1.1"), &max);
+        assert_eq!(total.tx_bytes, u64::MAX);
+        assert_eq!(total.rx_bytes, 2);
+        assert!(evicted.is_none());
+
+        // 被淘汰的计数器已饱和，新流继承的误差和计数同样饱和
+        let (_, evicted) = store.add(&key("2.2.2.2"), &max);
+        assert_eq!(evicted, Some(key("1.1.1.1")));
+        assert_eq!(store.error(&key("2.2.2.2")), u64::MAX);
+        let (other, evictions) = store.remainder().unwrap();
+        assert_eq!(other.tx_bytes, u64::MAX);
+        assert_eq!(evictions, 1);
+    }
+}